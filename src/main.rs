@@ -1,12 +1,27 @@
-use mikan_script::vm::{VMPool, pre_decoder::PreDecoder};
+use std::io::{self, BufRead, Write};
+
+use mikan_script::vm::{
+    VMPool, code_manager::CodeLoadError, debugger, diagnostic, native::NativeRegistry, pre_decoder::PreDecoder,
+    threads::VmResult, vm::VM,
+};
 
 fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    if let [_, command, path] = args.as_slice() {
+        match command.as_str() {
+            "debug" => return debug(path),
+            "check" => return check(path),
+            _ => {}
+        }
+    }
+
     let mut pool = VMPool::new();
     let source = r#"
 MAIN
 CALL INIT
+.loop:
 ADD_U64_IMMEDIATE r1 1
-LT_U64_JUMP r0 r1 r2 1
+LT_U64_JUMP r0 r1 r2 .loop
 PRINT_U64 r1
 EXIT 0
 
@@ -14,9 +29,79 @@ INIT
 LOAD_U64_IMMEDIATE r2 1000000000
 RET
 "#;
-    let decoder = PreDecoder::new();
-    let functions = decoder.decode(source).expect("decode succeeds");
-    pool.code_manager.set_functions(functions);
+    pool.code_manager
+        .load_source("main".into(), source)
+        .expect("decode succeeds");
     pool.run();
-    pool.wait_all();
-}
\ No newline at end of file
+    let results = pool.wait_all();
+    for result in &results {
+        report_trap(result);
+    }
+    std::process::exit(process_exit_code(&results[0]));
+}
+
+/// trapしたVMのtrapとバックトレースを表示します
+fn report_trap(result: &VmResult) {
+    if let Some(trap) = &result.trap {
+        eprintln!("vm {} trapped: {trap}", result.vm_id);
+        for frame in &result.backtrace {
+            eprintln!("    at {frame}");
+        }
+    }
+}
+
+/// MAINの終了コードをプロセスの終了コードにします
+/// trapした場合は1
+fn process_exit_code(main: &VmResult) -> i32 {
+    match main.trap {
+        Some(_) => 1,
+        None => main.exit_code as i32,
+    }
+}
+
+/// `mikan-script debug <file>`
+/// 標準入力から1行ずつデバッガのコマンドを読みます
+fn debug(path: &str) {
+    let mut vm = VM::new();
+    vm.set_path(path.to_string());
+    if let Err(err) = vm.cm.load() {
+        match (&err, std::fs::read_to_string(path)) {
+            (CodeLoadError::Diagnostics(diagnostics), Ok(source)) => {
+                eprint!("{}", diagnostic::render_diagnostics(diagnostics, &source, path));
+            }
+            _ => eprintln!("{path}: {err}"),
+        }
+        std::process::exit(1);
+    }
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    println!("mikan debugger ('help' for commands)");
+    loop {
+        print!("(mikan) ");
+        stdout.flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        if !debugger::execute_command(&mut vm, &line, &mut stdout).unwrap() {
+            break;
+        }
+    }
+}
+
+/// `mikan-script check <file>`
+/// デコードエラーをすべて表示します
+fn check(path: &str) {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("{path}: {err}");
+            std::process::exit(1);
+        }
+    };
+    if let Err(diagnostics) = PreDecoder::new().decode_with_diagnostics(&source, &NativeRegistry::new()) {
+        eprint!("{}", diagnostic::render_diagnostics(&diagnostics, &source, path));
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,510 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::vm::{
+    code_manager::CodeLoadError,
+    function::{DebugInfo, Function},
+    native::NativeRegistry,
+    operations::Instruction,
+    pre_decoder::{PreDecodeError, PreDecoder, build_instruction, opcode_name},
+};
+
+/// バイナリ形式のバイトコード
+///
+/// テキストのパースを省略するためのコンテナです。
+/// 命令は固定長なので、関数ごとに必要になったときだけデコードできます。
+///
+/// ```text
+/// header   : magic "MIKANBC\0" | version u32 | flags u32 (bit0: debug section)
+/// opcodes  : count u32 | { len u16 | name utf8 } * count
+/// functions: count u32 | { len u16 | name utf8 | instruction_count u32 } * count
+/// natives  : count u32 | { len u16 | name utf8 } * count
+/// code     : { opcode_index u16 | a u64 | b u64 } * 全命令 (関数順)
+/// debug    : { line u32 } * 全命令 (flags bit0 のときのみ)
+/// ```
+/// 数値はすべてリトルエンディアン、関数0がMAINです。
+/// CALLの関数indexとNATIVE_CALLのホスト関数indexはこのファイル内でのindexで、ロード時に再配置されます。
+pub const MAGIC: &[u8; 8] = b"MIKANBC\0";
+pub const VERSION: u32 = 2;
+
+const FLAG_DEBUG: u32 = 1 << 0;
+/// opcode_index u16 + a u64 + b u64
+const INSTRUCTION_SIZE: usize = 2 + 8 + 8;
+
+/// バイナリに書き出す関数
+pub struct BytecodeFunction {
+    pub name: String,
+    pub function: Function,
+    /// 各命令のソース行番号 (デバッグセクション用)
+    pub lines: Option<Vec<usize>>,
+}
+
+/// テキスト形式のソースをバイナリ形式に変換します
+pub fn compile(source: &str, debug: bool) -> Result<Vec<u8>, CodeLoadError> {
+    compile_with_natives(source, debug, &NativeRegistry::new())
+}
+
+/// `compile` と同じですが、NATIVE_CALLを `natives` で解決します
+pub fn compile_with_natives(
+    source: &str,
+    debug: bool,
+    natives: &NativeRegistry,
+) -> Result<Vec<u8>, CodeLoadError> {
+    let decoder = PreDecoder::new();
+    let spans = decoder.scan(source)?;
+    let name_to_index: HashMap<_, _> = spans
+        .iter()
+        .enumerate()
+        .map(|(idx, span)| (span.name.clone(), idx))
+        .collect();
+
+    let functions = spans
+        .iter()
+        .map(|span| {
+            let function = decoder.decode_span(source, span, &name_to_index, natives)?;
+            let lines = function.debug.as_ref().map(|debug| debug.lines.to_vec());
+            Ok(BytecodeFunction {
+                name: span.name.clone(),
+                function,
+                lines: lines.filter(|_| debug),
+            })
+        })
+        .collect::<Result<Vec<_>, PreDecodeError>>()?;
+
+    Ok(encode(&functions, natives.names())?)
+}
+
+/// 関数列をバイナリ形式にエンコードします
+/// `natives` はNATIVE_CALLのホスト関数index順の関数名です
+/// デバッグ情報はすべての関数が `lines` を持つ場合のみ書き出します
+/// 名前が長すぎて書き出せない場合はエラーになります
+pub fn encode(functions: &[BytecodeFunction], natives: &[String]) -> Result<Vec<u8>, BytecodeError> {
+    let debug = !functions.is_empty() && functions.iter().all(|f| f.lines.is_some());
+
+    let mut opcodes: Vec<&'static str> = Vec::new();
+    let mut opcode_index: HashMap<&'static str, u16> = HashMap::new();
+    for function in functions {
+        for ins in function.function.instructions.iter() {
+            let name = opcode_name(ins);
+            opcode_index.entry(name).or_insert_with(|| {
+                opcodes.push(name);
+                (opcodes.len() - 1) as u16
+            });
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(if debug { FLAG_DEBUG } else { 0 }).to_le_bytes());
+
+    out.extend_from_slice(&(opcodes.len() as u32).to_le_bytes());
+    for name in &opcodes {
+        write_str(&mut out, name)?;
+    }
+
+    out.extend_from_slice(&(functions.len() as u32).to_le_bytes());
+    for function in functions {
+        write_str(&mut out, &function.name)?;
+        out.extend_from_slice(&(function.function.instructions.len() as u32).to_le_bytes());
+    }
+
+    out.extend_from_slice(&(natives.len() as u32).to_le_bytes());
+    for name in natives {
+        write_str(&mut out, name)?;
+    }
+
+    for function in functions {
+        for ins in function.function.instructions.iter() {
+            let (a, b) = ins.operands();
+            out.extend_from_slice(&opcode_index[opcode_name(ins)].to_le_bytes());
+            out.extend_from_slice(&a.to_le_bytes());
+            out.extend_from_slice(&b.to_le_bytes());
+        }
+    }
+
+    if debug {
+        for function in functions {
+            for line in function.lines.as_ref().unwrap() {
+                out.extend_from_slice(&(*line as u32).to_le_bytes());
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// 長さはu16で書くので、それを超える名前はエラー
+fn write_str(out: &mut Vec<u8>, s: &str) -> Result<(), BytecodeError> {
+    let len = u16::try_from(s.len()).map_err(|_| BytecodeError::NameTooLong(s.len()))?;
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+/// 読み込み済みのバイナリ
+/// ヘッダと関数テーブルだけを解析し、命令列は `decode_function` で関数ごとにデコードします
+pub struct BytecodeImage {
+    bytes: Arc<[u8]>,
+    /// opcode_index -> オペコード名
+    opcodes: Vec<&'static str>,
+    pub functions: Vec<BinaryFunction>,
+    /// ファイル内のホスト関数index順の関数名
+    pub natives: Vec<String>,
+}
+
+/// バイナリ内の関数
+pub struct BinaryFunction {
+    pub name: String,
+    /// 命令列の先頭バイトオフセット
+    pub code_offset: usize,
+    pub len: usize,
+    /// 行番号テーブルの先頭バイトオフセット
+    pub lines_offset: Option<usize>,
+}
+
+impl BytecodeImage {
+    pub fn parse(bytes: Arc<[u8]>) -> Result<Self, BytecodeError> {
+        let mut reader = Reader { bytes: &bytes, pos: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(BytecodeError::BadMagic);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+        let flags = reader.u32()?;
+
+        let opcode_count = reader.u32()? as usize;
+        let mut opcodes = Vec::with_capacity(opcode_count.min(bytes.len()));
+        for _ in 0..opcode_count {
+            let name = reader.str()?;
+            // &'static str を得るため命令を組み立てて名前を引き直す
+            let ins = build_instruction(name, 0, 0)
+                .ok_or_else(|| BytecodeError::UnknownOpcode(name.to_string()))?;
+            opcodes.push(opcode_name(&ins));
+        }
+
+        let function_count = reader.u32()? as usize;
+        if function_count == 0 {
+            return Err(BytecodeError::MissingMain);
+        }
+        let mut functions = Vec::with_capacity(function_count.min(bytes.len()));
+        let mut total = 0usize;
+        for _ in 0..function_count {
+            let name = reader.str()?.to_string();
+            let len = reader.u32()? as usize;
+            functions.push(BinaryFunction {
+                name,
+                code_offset: 0,
+                len,
+                lines_offset: None,
+            });
+            total = total.checked_add(len).ok_or(BytecodeError::Truncated)?;
+        }
+        if functions[0].name != "MAIN" {
+            return Err(BytecodeError::MissingMain);
+        }
+
+        let native_count = reader.u32()? as usize;
+        let mut natives = Vec::with_capacity(native_count.min(bytes.len()));
+        for _ in 0..native_count {
+            natives.push(reader.str()?.to_string());
+        }
+
+        let code_start = reader.pos;
+        let (lines_start, end) = section_ends(code_start, total, flags & FLAG_DEBUG != 0)?;
+        if end > bytes.len() {
+            return Err(BytecodeError::Truncated);
+        }
+
+        let mut offset = 0usize;
+        for function in &mut functions {
+            function.code_offset = code_start + offset * INSTRUCTION_SIZE;
+            if flags & FLAG_DEBUG != 0 {
+                function.lines_offset = Some(lines_start + offset * 4);
+            }
+            offset += function.len;
+        }
+
+        Ok(BytecodeImage { bytes, opcodes, functions, natives })
+    }
+
+    /// 関数を1つデコードします
+    /// 関数名とデバッグセクションの行番号をデバッグ情報として付けます
+    /// CALLの関数indexには `table_base` を足し、
+    /// NATIVE_CALLのホスト関数indexは `native_map` (ファイル内index -> 登録表のindex) で再配置します
+    pub fn decode_function(
+        &self,
+        index: usize,
+        table_base: usize,
+        native_map: &[u64],
+    ) -> Result<Function, BytecodeError> {
+        let function = &self.functions[index];
+        let mut reader = Reader { bytes: &self.bytes, pos: function.code_offset };
+        let mut instructions = Vec::with_capacity(function.len);
+        for _ in 0..function.len {
+            let opcode_index = reader.u16()? as usize;
+            let a = reader.u64()?;
+            let b = reader.u64()?;
+            let name = *self
+                .opcodes
+                .get(opcode_index)
+                .ok_or(BytecodeError::BadOpcodeIndex(opcode_index))?;
+            instructions.push(relocate(build_instruction(name, a, b).unwrap(), table_base, native_map)?);
+        }
+        let debug = DebugInfo {
+            name: function.name.clone(),
+            source_path: None,
+            lines: self.lines(index).unwrap_or_default().into_boxed_slice(),
+        };
+        Ok(Function::new(instructions.into_boxed_slice()).with_debug_info(debug))
+    }
+
+    /// 関数の各命令のソース行番号
+    pub fn lines(&self, index: usize) -> Option<Vec<usize>> {
+        let function = &self.functions[index];
+        let mut reader = Reader { bytes: &self.bytes, pos: function.lines_offset? };
+        (0..function.len).map(|_| reader.u32().ok().map(|line| line as usize)).collect()
+    }
+
+    /// ホスト関数名を登録表のindexに解決します
+    pub fn resolve_natives(&self, natives: &NativeRegistry) -> Result<Vec<u64>, BytecodeError> {
+        self.natives
+            .iter()
+            .map(|name| {
+                natives
+                    .index_of(name)
+                    .map(|idx| idx as u64)
+                    .ok_or_else(|| BytecodeError::UnknownNative(name.clone()))
+            })
+            .collect()
+    }
+
+    /// すべての関数を再配置せずにデコードします
+    pub fn decode_all(&self) -> Result<Vec<Function>, BytecodeError> {
+        let native_map: Vec<u64> = (0..self.natives.len() as u64).collect();
+        (0..self.functions.len()).map(|idx| self.decode_function(idx, 0, &native_map)).collect()
+    }
+}
+
+/// 命令列が `code_start` から始まるときの (行番号テーブルの先頭, 末尾) のバイトオフセット
+/// 命令数はヘッダの値で信用できないので、溢れたらTruncated
+fn section_ends(code_start: usize, total: usize, debug: bool) -> Result<(usize, usize), BytecodeError> {
+    let lines_start = total
+        .checked_mul(INSTRUCTION_SIZE)
+        .and_then(|code_size| code_start.checked_add(code_size))
+        .ok_or(BytecodeError::Truncated)?;
+    if !debug {
+        return Ok((lines_start, lines_start));
+    }
+    let end = total
+        .checked_mul(4)
+        .and_then(|lines_size| lines_start.checked_add(lines_size))
+        .ok_or(BytecodeError::Truncated)?;
+    Ok((lines_start, end))
+}
+
+/// 関数index・ホスト関数indexを持つ命令の再配置
+/// ファイルの関数indexは信用できないので、溢れたらエラー
+fn relocate(ins: Instruction, table_base: usize, native_map: &[u64]) -> Result<Instruction, BytecodeError> {
+    let func = |func_index: u64| {
+        func_index
+            .checked_add(table_base as u64)
+            .ok_or(BytecodeError::FunctionIndexOverflow(func_index))
+    };
+    Ok(match ins {
+        Instruction::Call(func_index, pc) => Instruction::Call(func(func_index)?, pc),
+        Instruction::CallArgs(func_index, regs) => Instruction::CallArgs(func(func_index)?, regs),
+        Instruction::Spawn(func_index, regs) => Instruction::Spawn(func(func_index)?, regs),
+        Instruction::CoCreate(func_index, regs) => Instruction::CoCreate(func(func_index)?, regs),
+        Instruction::LoadFuncIndex(dst, func_index) => Instruction::LoadFuncIndex(dst, func(func_index)?),
+        Instruction::NativeCall(native_index, regs) => {
+            let native_index = native_map
+                .get(native_index as usize)
+                .ok_or(BytecodeError::BadNativeIndex(native_index))?;
+            Instruction::NativeCall(*native_index, regs)
+        }
+        Instruction::NativeCallAsync(native_index, regs) => {
+            let native_index = native_map
+                .get(native_index as usize)
+                .ok_or(BytecodeError::BadNativeIndex(native_index))?;
+            Instruction::NativeCallAsync(*native_index, regs)
+        }
+        other => other,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self.pos.checked_add(len).ok_or(BytecodeError::Truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(BytecodeError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, BytecodeError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> Result<&'a str, BytecodeError> {
+        let len = self.u16()? as usize;
+        std::str::from_utf8(self.take(len)?).map_err(|_| BytecodeError::InvalidUtf8)
+    }
+}
+
+#[derive(Debug)]
+pub enum BytecodeError {
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated,
+    InvalidUtf8,
+    UnknownOpcode(String),
+    BadOpcodeIndex(usize),
+    /// ファイル内のホスト関数表にないindex
+    BadNativeIndex(u64),
+    /// 登録されていないホスト関数
+    UnknownNative(String),
+    MissingMain,
+    /// 再配置で関数indexが溢れた
+    FunctionIndexOverflow(u64),
+    /// 書き出せない長さの名前
+    NameTooLong(usize),
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BytecodeError::BadMagic => write!(f, "not a mikan bytecode file (bad magic)"),
+            BytecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported bytecode version {version} (expected {VERSION})")
+            }
+            BytecodeError::Truncated => write!(f, "bytecode is truncated"),
+            BytecodeError::InvalidUtf8 => write!(f, "name in bytecode is not valid UTF-8"),
+            BytecodeError::UnknownOpcode(name) => write!(f, "unknown opcode '{name}' in bytecode"),
+            BytecodeError::BadOpcodeIndex(index) => {
+                write!(f, "opcode index {index} is out of the opcode table")
+            }
+            BytecodeError::BadNativeIndex(index) => {
+                write!(f, "native index {index} is out of the native table")
+            }
+            BytecodeError::UnknownNative(name) => {
+                write!(f, "native function '{name}' is not registered")
+            }
+            BytecodeError::MissingMain => write!(f, "MAIN function must be the first function"),
+            BytecodeError::FunctionIndexOverflow(index) => {
+                write!(f, "function index {index} overflows when relocated")
+            }
+            BytecodeError::NameTooLong(len) => {
+                write!(f, "name of {len} bytes is too long for bytecode (max 65535)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BytecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+MAIN
+CALL SUB
+.loop:
+ADD_U64_IMMEDIATE r1 1
+LT_U64_JUMP r0 r1 r2 .loop
+EXIT 0
+
+SUB
+LOAD_U64_IMMEDIATE r2 10 ; comment
+ATOMIC_ADD_U64 r8 r3 r0 r1
+RET
+"#;
+
+    #[test]
+    fn compile_round_trips_instructions_and_lines() {
+        let bytes = compile(SOURCE, true).expect("compile succeeds");
+        let image = BytecodeImage::parse(bytes.into()).expect("parse succeeds");
+
+        let expected = PreDecoder::new().decode(SOURCE).expect("decode succeeds");
+        let decoded = image.decode_all().expect("decode succeeds");
+        assert_eq!(decoded.len(), expected.len());
+        for (decoded, expected) in decoded.iter().zip(&expected) {
+            assert_eq!(decoded.instructions[..], expected.instructions[..]);
+        }
+
+        assert_eq!(image.functions[1].name, "SUB");
+        assert_eq!(image.lines(0), Some(vec![3, 5, 6, 7]));
+        assert_eq!(image.lines(1), Some(vec![10, 11, 12]));
+    }
+
+    #[test]
+    fn decode_function_relocates_calls() {
+        let image = BytecodeImage::parse(compile(SOURCE, false).unwrap().into()).unwrap();
+        assert_eq!(image.lines(0), None);
+        assert_eq!(image.decode_function(0, 5, &[]).unwrap().instructions[0], Instruction::Call(6, 0));
+    }
+
+    #[test]
+    fn parse_rejects_bad_input() {
+        let bytes = compile(SOURCE, false).unwrap();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(BytecodeImage::parse(bad_magic.into()), Err(BytecodeError::BadMagic)));
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(matches!(BytecodeImage::parse(truncated.into()), Err(BytecodeError::Truncated)));
+    }
+
+    #[test]
+    fn parse_rejects_forged_sizes() {
+        // 命令数だけ大きく偽ったヘッダ
+        let mut forged = Vec::new();
+        forged.extend_from_slice(MAGIC);
+        forged.extend_from_slice(&VERSION.to_le_bytes());
+        forged.extend_from_slice(&FLAG_DEBUG.to_le_bytes());
+        forged.extend_from_slice(&0u32.to_le_bytes());
+        forged.extend_from_slice(&2u32.to_le_bytes());
+        for name in ["MAIN", "SUB"] {
+            write_str(&mut forged, name).unwrap();
+            forged.extend_from_slice(&u32::MAX.to_le_bytes());
+        }
+        forged.extend_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(BytecodeImage::parse(forged.into()), Err(BytecodeError::Truncated)));
+
+        // 64bitでは関数表を並べても届かない大きさなので、直接確かめる
+        assert!(matches!(section_ends(100, usize::MAX / INSTRUCTION_SIZE, false), Err(BytecodeError::Truncated)));
+        let code_size = usize::MAX / INSTRUCTION_SIZE * INSTRUCTION_SIZE;
+        assert!(matches!(section_ends(0, usize::MAX / INSTRUCTION_SIZE, true), Err(BytecodeError::Truncated)));
+        assert_eq!(section_ends(0, usize::MAX / INSTRUCTION_SIZE, false).unwrap(), (code_size, code_size));
+        assert!(matches!(section_ends(0, usize::MAX, false), Err(BytecodeError::Truncated)));
+    }
+
+    #[test]
+    fn relocation_overflow_and_long_names_are_errors() {
+        let image = BytecodeImage::parse(compile(SOURCE, false).unwrap().into()).unwrap();
+        assert!(matches!(image.decode_function(0, usize::MAX, &[]), Err(BytecodeError::FunctionIndexOverflow(1))));
+
+        let function = BytecodeFunction {
+            name: "F".repeat(70000),
+            function: Function::new(Box::new([Instruction::Exit(0, 0)])),
+            lines: None,
+        };
+        assert!(matches!(encode(&[function], &[]), Err(BytecodeError::NameTooLong(70000))));
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, RwLock},
+};
+
+use crate::vm::{memory::HeepStorage, trap::TrapKind};
+
+/// CHAN_SEND/CHAN_RECVが書き込む状態コード
+pub mod status {
+    /// 送受信できた
+    pub const OK: u64 = 0;
+    /// 閉じられている (受信は閉じられていて空のとき)
+    pub const CLOSED: u64 = 1;
+    /// TRY系で、満杯または空だった
+    pub const WOULD_BLOCK: u64 = 2;
+}
+
+/// チャネルで送るもの
+/// heepは送信元のVMから取り出され、受信したVMのheepになります
+pub enum Message {
+    Value(u64),
+    Heep(HeepStorage),
+}
+
+/// 容量付きのMPMCチャネル
+pub struct Channel {
+    state: Mutex<ChannelState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+}
+
+struct ChannelState {
+    queue: VecDeque<Message>,
+    closed: bool,
+}
+
+impl Channel {
+    /// 容量0は1として扱います
+    pub fn new(capacity: usize) -> Self {
+        Channel {
+            state: Mutex::new(ChannelState {
+                queue: VecDeque::new(),
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// `block` なら空きができるまで待ちます
+    /// 送れなかった場合は状態コードとメッセージを返します
+    pub fn send(&self, message: Message, block: bool) -> Result<(), (u64, Message)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.closed {
+                return Err((status::CLOSED, message));
+            }
+            if state.queue.len() < self.capacity {
+                break;
+            }
+            if !block {
+                return Err((status::WOULD_BLOCK, message));
+            }
+            state = self.not_full.wait(state).unwrap();
+        }
+        state.queue.push_back(message);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// `block` なら届くまで待ちます
+    /// 閉じられた後も残っているメッセージは受信できます
+    pub fn recv(&self, block: bool) -> Result<Message, u64> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(message) = state.queue.pop_front() {
+                self.not_full.notify_one();
+                return Ok(message);
+            }
+            if state.closed {
+                return Err(status::CLOSED);
+            }
+            if !block {
+                return Err(status::WOULD_BLOCK);
+            }
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// 待っている送受信はすべて起こされます
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+/// VM間のチャネルの表
+/// VMPoolが1つ持ち、チャネルはハンドル (表上のindex) で識別します
+pub struct Channels {
+    channels: RwLock<Vec<Arc<Channel>>>,
+}
+
+impl Channels {
+    pub fn new() -> Self {
+        Channels {
+            channels: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// 新しいチャネルのハンドル
+    pub fn create(&self, capacity: usize) -> u64 {
+        let mut channels = self.channels.write().unwrap();
+        channels.push(Arc::new(Channel::new(capacity)));
+        (channels.len() - 1) as u64
+    }
+
+    pub fn get(&self, handle: u64) -> Result<Arc<Channel>, TrapKind> {
+        self.channels
+            .read()
+            .unwrap()
+            .get(handle as usize)
+            .cloned()
+            .ok_or(TrapKind::InvalidChannel(handle))
+    }
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_send_and_close() {
+        let channel = Channel::new(1);
+        assert!(channel.send(Message::Value(1), false).is_ok());
+        assert!(matches!(channel.send(Message::Value(2), false), Err((status::WOULD_BLOCK, _))));
+        channel.close();
+        assert!(matches!(channel.send(Message::Value(3), true), Err((status::CLOSED, _))));
+        // 閉じる前に送ったものは受け取れる
+        assert!(matches!(channel.recv(true), Ok(Message::Value(1))));
+        assert!(matches!(channel.recv(true), Err(status::CLOSED)));
+    }
+}
@@ -1,8 +1,8 @@
-use std::{ops::Deref, path::PathBuf, sync::{Arc, RwLock}};
+use std::{fmt, ops::Deref, path::PathBuf, sync::{Arc, RwLock}};
 
 use rustc_hash::FxHashMap;
 
-use crate::vm::{function::{Function, FunctionPtr}, pre_decoder::PreDecoder};
+use crate::vm::{bytecode::{self, BytecodeError, BytecodeImage}, diagnostic::{self, Diagnostic}, function::{Function, FunctionPtr}, native::{DuplicateNative, NativeFunction, NativeRegistry}, operations::Instruction, pre_decoder::{FunctionSpan, PreDecodeError, PreDecoder}, verifier::{self, VerifyError}};
 
 pub struct CodeManager {
     inner: Arc<CodeManagerInner>,
@@ -35,13 +35,23 @@ pub struct CodeManagerInner {
     /// 初期でMainとその差し替え関数のみが入ってるとしておく
     pub latest_function_table: RwLock<Vec<FunctionPtr>>,
     /// 所有権保持実態 RUST安全外
-    /// 削除禁止
-    pub owned_functions: RwLock<Vec<Function>>,
+    /// 削除禁止 Boxなので関数テーブルのポインタはVecが伸びても動きません
+    pub owned_functions: RwLock<Vec<Box<Function>>>,
     /// 遅延ロードを実現するためにbytecodeのfunction id を置き換えます。
     /// index = decode_id
-    /// 関数ぜんぶここになげこんで、MAINから再帰的にパースしていく感じ？
-    /// 再帰のためのバッファは処理系に投げとくか
-    pub functions: RwLock<FxHashMap<FunctionPath, UnDecodedFunction>>,
+    /// MAINだけ先にデコードして、残りは呼ばれたときにデコードします
+    pub functions: RwLock<Vec<UnDecodedFunction>>,
+    /// 関数テーブルのindex -> decode_id
+    /// 呼び出し先の先読みに使います。`functions` と同時に更新します
+    pub table_to_decode: RwLock<FxHashMap<usize, usize>>,
+    /// 関数名 -> 関数テーブルのindex
+    /// CALLの解決に使います
+    pub name_to_index: RwLock<FxHashMap<FunctionPath, usize>>,
+    /// 読み込み済みのソース
+    pub sources: RwLock<FxHashMap<PathBuf, LoadedSource>>,
+    /// ホスト関数
+    /// NATIVE_CALLの解決に使います
+    pub natives: RwLock<NativeRegistry>,
     pub decoder: PreDecoder,
     /// MAINあるやつ
     pub root_dir: PathBuf,
@@ -52,37 +62,283 @@ type FunctionPath = String;
 impl CodeManagerInner {
     pub fn new(root_dir: PathBuf) -> Self {
         let latest_function_table = RwLock::new(Vec::new());
-        CodeManagerInner { 
-            latest_function_table, 
-            owned_functions: RwLock::new(Vec::new()), 
-            functions: RwLock::new(FxHashMap::default()), 
+        CodeManagerInner {
+            latest_function_table,
+            owned_functions: RwLock::new(Vec::new()),
+            functions: RwLock::new(Vec::new()),
+            table_to_decode: RwLock::new(FxHashMap::default()),
+            name_to_index: RwLock::new(FxHashMap::default()),
+            sources: RwLock::new(FxHashMap::default()),
+            natives: RwLock::new(NativeRegistry::new()),
             decoder: PreDecoder::new(),
             root_dir,
         }
     }
 
+    /// 関数をテーブルに追加します
+    /// 検証しないので、`verifier::verify` を通る関数を渡すこと
     pub fn set_functions(&self, functions: Vec<Function>) {
-        let mut owned_functions = self.owned_functions.write().unwrap();
-        for func in functions {
-            owned_functions.push(func);
+        let table = functions.into_iter().map(|func| self.own_function(func)).collect::<Vec<_>>();
+        self.latest_function_table.write().unwrap().extend(table);
+    }
+
+    /// ホスト関数を登録してindexを返します
+    /// NATIVE_CALLから使う関数は、その関数をデコードする前に登録すること
+    /// 同名の関数は登録できません
+    pub fn register_native(&self, name: &str, function: NativeFunction) -> Result<usize, DuplicateNative> {
+        self.natives.write().unwrap().register(name, function)
+    }
+
+    pub fn get_natives(&self) -> Box<[NativeFunction]> {
+        self.natives.read().unwrap().functions()
+    }
+
+    /// `root_dir` のソースを読み込みます
+    /// 先頭がマジックならバイナリ形式、それ以外はテキスト形式として扱います
+    pub fn load(&self) -> Result<(), CodeLoadError> {
+        let bytes = std::fs::read(&self.root_dir).map_err(CodeLoadError::Io)?;
+        if bytes.starts_with(bytecode::MAGIC) {
+            return self.load_bytecode(self.root_dir.clone(), bytes.into());
+        }
+        let source = String::from_utf8(bytes)
+            .map_err(|err| CodeLoadError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, err)))?;
+        self.load_source(self.root_dir.clone(), &source)?;
+        Ok(())
+    }
+
+    /// ソースを遅延ロード用に登録します
+    /// すべての関数をデコードして `verifier::verify` で検証し、エラーがあればすべて返して何も登録しません
+    /// テーブルにはMAINだけを置き、それ以外の関数はGET_DECODEだけの差し替え関数を置いて呼ばれたときにデコードし直します
+    pub fn load_source(&self, source_path: PathBuf, source: &str) -> Result<(), CodeLoadError> {
+        let mut errors = Vec::new();
+        let spans = self.decoder.scan_inner(source, &mut errors);
+
+        let mut functions = self.functions.write().unwrap();
+        let mut table_to_decode = self.table_to_decode.write().unwrap();
+        let mut name_to_index = self.name_to_index.write().unwrap();
+
+        let table_base = self.latest_function_table.read().unwrap().len();
+        let decode_base = functions.len();
+
+        // 読み込みに失敗したら関数名を残さないよう、手元の表で検査してから反映する
+        // ソース内での重複はscanが報告する
+        let mut names = name_to_index.clone();
+        for (idx, span) in spans.iter().enumerate() {
+            if name_to_index.contains_key(&span.name) {
+                errors.push(PreDecodeError::DuplicateFunction {
+                    name: span.name.clone(),
+                    line: span.line,
+                });
+            }
+            names.entry(span.name.clone()).or_insert(table_base + idx);
+        }
+
+        let natives = self.natives.read().unwrap();
+        let decoded = spans
+            .iter()
+            .map(|span| self.decoder.decode_span_inner(source, span, &names, &natives, &mut errors))
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(CodeLoadError::Diagnostics(diagnostic::collect(errors, source)));
         }
-        drop(owned_functions);
-        let owned_functions = self.owned_functions.read().unwrap();
-        let mut latest_function_table = self.latest_function_table.write().unwrap();
-        owned_functions.iter().for_each(|f| {
-            latest_function_table.push(FunctionPtr(Box::into_raw(Box::new(f.clone()))));
-        });
-    }
-    
+        verify_all(&decoded)?;
+
+        // MAINは先頭にある
+        let mut main = decoded.into_iter().next().expect("scan found MAIN");
+        main.set_source_path(source_path.clone());
+        *name_to_index = names;
+
+        let mut main = Some(main);
+        let mut table = Vec::with_capacity(spans.len());
+        for (idx, span) in spans.into_iter().enumerate() {
+            let is_decoded = idx == 0;
+            let replacement_function = if is_decoded {
+                self.own_function(main.take().unwrap())
+            } else {
+                let replacement = Function::new(Box::new([Instruction::GetDecode((decode_base + idx) as u64, 0)]));
+                self.own_function(replacement)
+            };
+            table.push(replacement_function);
+            table_to_decode.insert(table_base + idx, decode_base + idx);
+            functions.push(UnDecodedFunction {
+                is_decoded,
+                table_index: table_base + idx,
+                replacement_function,
+                source_path: source_path.clone(),
+                location: FunctionLocation::Text(span),
+            });
+        }
+        self.latest_function_table.write().unwrap().extend(table);
+
+        self.sources.write().unwrap().insert(source_path, LoadedSource::Text(Arc::from(source)));
+        Ok(())
+    }
+
+    /// バイナリ形式のバイトコードを遅延ロード用に登録します
+    /// テキスト形式と同様にすべての関数をデコードして検証し、テーブルにはMAINだけを置きます
+    pub fn load_bytecode(&self, source_path: PathBuf, bytes: Arc<[u8]>) -> Result<(), CodeLoadError> {
+        let image = BytecodeImage::parse(bytes)?;
+        let native_map: Arc<[u64]> = image.resolve_natives(&self.natives.read().unwrap())?.into();
+
+        let mut functions = self.functions.write().unwrap();
+        let mut table_to_decode = self.table_to_decode.write().unwrap();
+        let mut name_to_index = self.name_to_index.write().unwrap();
+
+        let table_base = self.latest_function_table.read().unwrap().len();
+        let decode_base = functions.len();
+
+        for (idx, function) in image.functions.iter().enumerate() {
+            if name_to_index.contains_key(&function.name)
+                || image.functions[..idx].iter().any(|f| f.name == function.name)
+            {
+                return Err(CodeLoadError::Decode(PreDecodeError::DuplicateFunction {
+                    name: function.name.clone(),
+                    line: 0,
+                }));
+            }
+        }
+        let decoded = (0..image.functions.len())
+            .map(|idx| image.decode_function(idx, table_base, &native_map))
+            .collect::<Result<Vec<_>, _>>()?;
+        verify_all(&decoded)?;
+        let mut main = decoded.into_iter().next().expect("parse checked MAIN");
+        main.set_source_path(source_path.clone());
+
+        let mut main = Some(main);
+        let mut table = Vec::with_capacity(image.functions.len());
+        for (idx, function) in image.functions.iter().enumerate() {
+            name_to_index.insert(function.name.clone(), table_base + idx);
+            let is_decoded = idx == 0;
+            let replacement_function = if is_decoded {
+                self.own_function(main.take().unwrap())
+            } else {
+                let replacement = Function::new(Box::new([Instruction::GetDecode((decode_base + idx) as u64, 0)]));
+                self.own_function(replacement)
+            };
+            table.push(replacement_function);
+            table_to_decode.insert(table_base + idx, decode_base + idx);
+            functions.push(UnDecodedFunction {
+                is_decoded,
+                table_index: table_base + idx,
+                replacement_function,
+                source_path: source_path.clone(),
+                location: FunctionLocation::Binary { index: idx, table_base },
+            });
+        }
+        self.latest_function_table.write().unwrap().extend(table);
+
+        self.sources.write().unwrap().insert(
+            source_path,
+            LoadedSource::Binary { image: Arc::new(image), native_map },
+        );
+        Ok(())
+    }
+
+    /// 関数テーブルのindex順の関数名
+    pub fn function_names(&self) -> Vec<String> {
+        let name_to_index = self.name_to_index.read().unwrap();
+        let mut names = vec![String::new(); self.latest_function_table.read().unwrap().len()];
+        for (name, &index) in name_to_index.iter() {
+            if let Some(slot) = names.get_mut(index) {
+                slot.clone_from(name);
+            }
+        }
+        names
+    }
+
     pub fn get_decoded(&self) -> Box<[FunctionPtr]> {
         self.latest_function_table.read().unwrap().to_vec().into_boxed_slice()
     }
 
-    pub fn get_decode(&self, _func_id: u64, _decode_id: u64, _deep: u64, ) -> Box<[FunctionPtr]> {
-        unimplemented!()
+    /// 差し替え関数から呼ばれ、関数をデコードして最新の関数テーブルを返します
+    /// deep > 0 の場合は呼び出し先の関数も deep 段まで先にデコードします
+    pub fn get_decode(&self, decode_id: u64, deep: u64) -> Result<Box<[FunctionPtr]>, CodeLoadError> {
+        self.decode_function(decode_id as usize, deep)?;
+        Ok(self.get_decoded())
+    }
+
+    /// 関数をデコードして `latest_function_table` に公開します
+    /// デコード済みなら何もしません
+    pub fn decode_function(&self, decode_id: usize, deep: u64) -> Result<(), CodeLoadError> {
+        let mut functions = self.functions.write().unwrap();
+        self.decode_locked(&mut functions, decode_id, deep)
+    }
+
+    fn decode_locked(
+        &self,
+        functions: &mut [UnDecodedFunction],
+        decode_id: usize,
+        deep: u64,
+    ) -> Result<(), CodeLoadError> {
+        let Some(entry) = functions.get(decode_id) else {
+            return Ok(());
+        };
+        if entry.is_decoded {
+            return Ok(());
+        }
+
+        let source = self.sources.read().unwrap()[&entry.source_path].clone();
+        let mut function = match (&source, &entry.location) {
+            (LoadedSource::Text(source), FunctionLocation::Text(span)) => {
+                let name_to_index = self.name_to_index.read().unwrap();
+                let natives = self.natives.read().unwrap();
+                self.decoder.decode_span(source, span, &name_to_index, &natives)?
+            }
+            (LoadedSource::Binary { image, native_map }, FunctionLocation::Binary { index, table_base }) => {
+                image.decode_function(*index, *table_base, native_map)?
+            }
+            _ => unreachable!("source kind and function location mismatch"),
+        };
+        verifier::verify(&function)?;
+        function.set_source_path(entry.source_path.clone());
+
+        let callees: Vec<usize> = if deep > 0 {
+            function
+                .instructions
+                .iter()
+                .filter_map(|ins| match ins {
+                    Instruction::Call(func_index, _)
+                    | Instruction::CallArgs(func_index, _)
+                    | Instruction::LoadFuncIndex(_, func_index) => Some(*func_index as usize),
+                    _ => None,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let table_index = entry.table_index;
+        let function_ptr = self.own_function(function);
+        self.latest_function_table.write().unwrap()[table_index] = function_ptr;
+        functions[decode_id].is_decoded = true;
+
+        for callee in callees {
+            let callee_id = self.table_to_decode.read().unwrap().get(&callee).copied();
+            if let Some(callee_id) = callee_id {
+                self.decode_locked(functions, callee_id, deep - 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 関数を保持し、VMから参照するためのポインタを返します
+    fn own_function(&self, function: Function) -> FunctionPtr {
+        let function = Box::new(function);
+        let ptr = FunctionPtr(&*function);
+        self.owned_functions.write().unwrap().push(function);
+        ptr
     }
 }
 
+/// すべての関数を検証し、失敗した関数のエラーをまとめて返します
+fn verify_all(functions: &[Function]) -> Result<(), CodeLoadError> {
+    let errors = functions.iter().filter_map(|function| verifier::verify(function).err()).collect::<Vec<_>>();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(CodeLoadError::Verify(errors))
+}
+
 pub struct UnDecodedFunction {
     pub is_decoded: bool,
     /// 未割当ならMAXで
@@ -91,4 +347,243 @@ pub struct UnDecodedFunction {
     pub replacement_function: FunctionPtr,
     /// バイトコードのソースパス
     pub source_path: PathBuf,
-}
\ No newline at end of file
+    /// ソース上の位置
+    pub location: FunctionLocation,
+}
+
+/// 読み込み済みのソース
+#[derive(Clone)]
+pub enum LoadedSource {
+    Text(Arc<str>),
+    /// バイナリと、そのホスト関数indexの再配置表
+    Binary { image: Arc<BytecodeImage>, native_map: Arc<[u64]> },
+}
+
+/// 関数のソース上の位置
+pub enum FunctionLocation {
+    Text(FunctionSpan),
+    /// バイナリ内の関数indexと、再配置先の関数テーブルの先頭
+    Binary { index: usize, table_base: usize },
+}
+
+#[derive(Debug)]
+pub enum CodeLoadError {
+    Io(std::io::Error),
+    Decode(PreDecodeError),
+    Bytecode(BytecodeError),
+    /// 読み込み時に集めたデコードエラー (行順)
+    Diagnostics(Vec<Diagnostic>),
+    /// 検証に失敗した関数ごとのエラー
+    Verify(Vec<VerifyError>),
+}
+
+impl From<PreDecodeError> for CodeLoadError {
+    fn from(err: PreDecodeError) -> Self {
+        CodeLoadError::Decode(err)
+    }
+}
+
+impl From<BytecodeError> for CodeLoadError {
+    fn from(err: BytecodeError) -> Self {
+        CodeLoadError::Bytecode(err)
+    }
+}
+
+impl From<VerifyError> for CodeLoadError {
+    fn from(err: VerifyError) -> Self {
+        CodeLoadError::Verify(vec![err])
+    }
+}
+
+impl fmt::Display for CodeLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodeLoadError::Io(err) => write!(f, "failed to read bytecode: {err}"),
+            CodeLoadError::Decode(err) => write!(f, "failed to decode bytecode: {err}"),
+            CodeLoadError::Bytecode(err) => write!(f, "failed to load binary bytecode: {err}"),
+            CodeLoadError::Diagnostics(diagnostics) => {
+                let errors = diagnostics.iter().map(|diagnostic| diagnostic.error.to_string()).collect::<Vec<_>>();
+                write!(f, "failed to decode bytecode: {}", errors.join("; "))
+            }
+            CodeLoadError::Verify(errors) => {
+                let errors = errors.iter().map(VerifyError::to_string).collect::<Vec<_>>();
+                write!(f, "failed to verify bytecode: {}", errors.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for CodeLoadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::vm::VM;
+
+    const SOURCE: &str = r#"
+MAIN
+CALL FIRST
+EXIT 0
+
+FIRST
+LOAD_U64_IMMEDIATE r1 7
+CALL SECOND
+RET
+
+SECOND
+ADD_U64_IMMEDIATE r1 1
+RET
+
+UNUSED
+LOAD_U64_IMMEDIATE r1 100
+RET
+"#;
+
+    fn decoded_flags(cm: &CodeManager) -> Vec<bool> {
+        cm.functions.read().unwrap().iter().map(|f| f.is_decoded).collect()
+    }
+
+    /// 読み込みエラーの (行, エラー)
+    fn diagnostics(err: CodeLoadError) -> Vec<(Option<usize>, PreDecodeError)> {
+        match err {
+            CodeLoadError::Diagnostics(diagnostics) => {
+                diagnostics.into_iter().map(|diagnostic| (diagnostic.line, diagnostic.error)).collect()
+            }
+            other => panic!("expected diagnostics, got {other}"),
+        }
+    }
+
+    #[test]
+    fn load_source_decodes_only_main() {
+        let cm = CodeManager::new("test".into());
+        cm.load_source("test".into(), SOURCE).expect("load succeeds");
+
+        assert_eq!(decoded_flags(&cm), [true, false, false, false]);
+        let table = cm.get_decoded();
+        assert_eq!(table.len(), 4);
+        assert_eq!(table[2].instructions[..], [Instruction::GetDecode(2, 0)]);
+    }
+
+    #[test]
+    fn call_decodes_callee_on_first_use() {
+        let cm = CodeManager::new("test".into());
+        cm.load_source("test".into(), SOURCE).expect("load succeeds");
+
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm.clone_shared());
+        vm.run().expect("run succeeds");
+
+        assert_eq!(vm.st.r[1], 8);
+        assert_eq!(decoded_flags(&cm), [true, true, true, false]);
+        assert_eq!(cm.get_decoded()[2].instructions[..], [Instruction::AddU64Immediate(1, 1), Instruction::Ret(0, 0)]);
+    }
+
+    #[test]
+    fn failed_load_leaves_no_function_names() {
+        let cm = CodeManager::new("test".into());
+        let err = cm.load_source("a".into(), "MAIN\nEXIT 0\n\nHELPER\nRET\n\nHELPER\nRET\n").unwrap_err();
+        assert!(matches!(diagnostics(err)[..], [(Some(7), PreDecodeError::DuplicateFunction { .. })]));
+        assert!(cm.name_to_index.read().unwrap().is_empty());
+
+        // 失敗した読み込みの関数は解決できない
+        let err = cm.load_source("b".into(), "MAIN\nCALL HELPER\nEXIT 0\n").unwrap_err();
+        assert!(matches!(diagnostics(err)[..], [(Some(2), PreDecodeError::UnknownFunction { .. })]));
+        cm.load_source("c".into(), "MAIN\nEXIT 0\n").expect("load succeeds");
+    }
+
+    #[test]
+    fn load_reports_errors_in_every_function() {
+        let cm = CodeManager::new("test".into());
+        cm.load_source("lib".into(), "MAIN\nEXIT 0\n\nHELPER\nRET\n").expect("load succeeds");
+
+        // 呼ばれない関数のエラーも、既に読み込んだ関数名との重複も読み込み時に報告する
+        let source = "MAIN\nCALL MISSING\nEXIT 0\n\nHELPER\nRET\n\nUNUSED\nADD_U46 r1 r2\nRET\n";
+        let found = diagnostics(cm.load_source("app".into(), source).unwrap_err());
+        assert!(matches!(
+            found[..],
+            [
+                (Some(1), PreDecodeError::DuplicateFunction { .. }),
+                (Some(2), PreDecodeError::UnknownFunction { .. }),
+                (Some(5), PreDecodeError::DuplicateFunction { .. }),
+                (Some(9), PreDecodeError::UnknownOpcode { .. }),
+            ]
+        ));
+        assert_eq!(cm.name_to_index.read().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn get_decode_prefetches_callees() {
+        let cm = CodeManager::new("test".into());
+        cm.load_source("test".into(), SOURCE).expect("load succeeds");
+
+        cm.get_decode(1, 1).expect("decode succeeds");
+        assert_eq!(decoded_flags(&cm), [true, true, true, false]);
+    }
+
+    #[test]
+    fn get_decode_prefetches_callees_after_set_functions() {
+        // 関数テーブルのindexとdecode_idがずれていても呼び出し先を先読みする
+        let cm = CodeManager::new("test".into());
+        cm.set_functions(vec![Function::new(Box::new([Instruction::Exit(0, 0)]))]);
+        cm.load_source("test".into(), SOURCE).expect("load succeeds");
+
+        cm.get_decode(1, 1).expect("decode succeeds");
+        assert_eq!(decoded_flags(&cm), [true, true, true, false]);
+    }
+
+    #[test]
+    fn load_bytecode_decodes_lazily() {
+        let cm = CodeManager::new("test".into());
+        let bytes = bytecode::compile(SOURCE, true).expect("compile succeeds");
+        cm.load_bytecode("test.bin".into(), bytes.into()).expect("load succeeds");
+        assert_eq!(decoded_flags(&cm), [true, false, false, false]);
+
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm.clone_shared());
+        vm.run().expect("run succeeds");
+
+        assert_eq!(vm.st.r[1], 8);
+        assert_eq!(decoded_flags(&cm), [true, true, true, false]);
+    }
+
+    #[test]
+    fn unverified_functions_are_rejected() {
+        let cm = CodeManager::new("test".into());
+        let err = cm.load_source("test".into(), "MAIN\nMOV r0 r1\nEXIT 0\n").unwrap_err();
+        assert!(matches!(err, CodeLoadError::Verify(_)));
+
+        // 呼ばれていない関数も読み込み時に検証する
+        let source = "MAIN\nMOV r0 r1\nEXIT 0\n\nUNUSED\nADD_U64 r1 r2\n";
+        let Err(CodeLoadError::Verify(errors)) = cm.load_source("test".into(), source) else {
+            panic!("verify fails");
+        };
+        let found = errors.iter().map(|err| (err.function_name.as_deref(), err.kind.clone())).collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (Some("MAIN"), verifier::VerifyErrorKind::WriteToFixedRegister(0)),
+                (Some("UNUSED"), verifier::VerifyErrorKind::FallsOffEnd),
+            ]
+        );
+
+        let bytes = bytecode::compile(source, false).expect("compile succeeds");
+        let Err(CodeLoadError::Verify(errors)) = cm.load_bytecode("test.bin".into(), bytes.into()) else {
+            panic!("verify fails");
+        };
+        assert_eq!(errors.len(), 2);
+        assert!(cm.name_to_index.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn lazy_decode_stubs_are_rejected_in_user_code() {
+        let stub_error = |err: CodeLoadError| {
+            matches!(&err, CodeLoadError::Verify(errors) if matches!(errors[..], [VerifyError { kind: verifier::VerifyErrorKind::ReservedOpcode(_), .. }]))
+        };
+        let source = "MAIN\nGET_DECODE 0 0\nEXIT 0\n";
+        let cm = CodeManager::new("test".into());
+        assert!(stub_error(cm.load_source("test".into(), source).unwrap_err()));
+
+        let bytes = bytecode::compile(source, false).expect("compile succeeds");
+        assert!(stub_error(cm.load_bytecode("test.bin".into(), bytes.into()).unwrap_err()));
+    }
+}
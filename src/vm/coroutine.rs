@@ -0,0 +1,284 @@
+use std::mem;
+
+use crate::vm::{
+    trap::TrapKind,
+    vm::{Frame, VM},
+};
+
+/// CO_RESUMEが *status_reg に書く値
+pub mod status {
+    /// YIELDで中断した
+    pub const YIELDED: u64 = 0;
+    /// 関数の最後までRETした
+    pub const FINISHED: u64 = 1;
+    /// 既に終わっているので切り替えなかった
+    pub const DEAD: u64 = 2;
+}
+
+/// コルーチンの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoroutineState {
+    /// 作られたばかりか、YIELDで中断している
+    Suspended,
+    /// 実行中
+    Running,
+    /// CO_RESUMEで別のコルーチンを待っている
+    Normal,
+    /// 終了した
+    Dead,
+}
+
+/// 切り替え時に退避する実行状態
+/// heep・fuel・チャネルはVMで共有します
+/// 枠ごとに1つ持ち、切り替えのたびに確保せず上書きします
+pub struct Context {
+    pub r: Box<[u64; 256]>,
+    pub pc: usize,
+    pub call_stack: Vec<usize>,
+    pub frames: Vec<Frame>,
+    pub now_call_index: usize,
+}
+
+/// 再開されたときに値を受け取るレジスタ
+#[derive(Debug, Clone, Copy)]
+enum Delivery {
+    /// 何も受け取らない (作られたばかり)
+    None,
+    /// YIELDの結果
+    Yield { reg: usize },
+    /// CO_RESUMEの結果
+    Resume { status_reg: usize, value_reg: usize },
+}
+
+struct Coroutine {
+    /// 枠を使い回した回数 idの上位32bitに入ります
+    generation: u32,
+    state: CoroutineState,
+    /// 実行中でなければ退避した状態 実行中と終了後は使いません
+    context: Context,
+    /// 最後にCO_RESUMEしたコルーチン
+    resumer: usize,
+    delivery: Delivery,
+}
+
+/// 1つのVMの中で協調的に切り替わるコルーチン
+/// id 0はMAINなどVMが最初に実行する文脈です
+/// 命令は切り替え先を `next` に置いて `state_flag::SWITCH` を立て、
+/// `VM::run` のループが次の命令の前に切り替えます
+/// 終わったコルーチンの枠は使い回し、idは [ 世代(32bit) | 枠(32bit) ] なので古いidは終了したままに見えます
+pub struct Coroutines {
+    /// 実行中のコルーチンの枠
+    pub current: usize,
+    list: Vec<Coroutine>,
+    /// 次に切り替える枠 1命令で切り替え先は1つなので1つだけ
+    next: Option<usize>,
+    /// 終わって使い回せる枠
+    free: Vec<usize>,
+}
+
+impl Coroutines {
+    pub fn new() -> Self {
+        Coroutines {
+            current: 0,
+            list: vec![Coroutine::new(CoroutineState::Running)],
+            next: None,
+            free: Vec::new(),
+        }
+    }
+
+    /// `func_index` の関数の先頭から始まる、中断した状態のコルーチンを作ってそのid
+    /// `args` は r1.. に入ります
+    /// 終わったコルーチンの枠があれば世代を進め、レジスタの領域ごと使い回します
+    pub fn create(&mut self, func_index: usize, args: &[u64]) -> u64 {
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                let co = &mut self.list[slot];
+                co.generation = co.generation.wrapping_add(1);
+                co.state = CoroutineState::Suspended;
+                co.resumer = 0;
+                co.delivery = Delivery::None;
+                co.context.r.fill(0);
+                co.context.call_stack.clear();
+                co.context.frames.clear();
+                slot
+            }
+            None => {
+                self.list.push(Coroutine::new(CoroutineState::Suspended));
+                self.list.len() - 1
+            }
+        };
+        let context = &mut self.list[slot].context;
+        context.r[255] = u64::MAX;
+        context.r[1..1 + args.len()].copy_from_slice(args);
+        context.pc = 0;
+        context.now_call_index = func_index;
+        ((self.list[slot].generation as u64) << 32) | slot as u64
+    }
+
+    /// コルーチンの状態 存在しなければNone
+    /// 枠が使い回された古いidはDead
+    pub fn state(&self, id: u64) -> Option<CoroutineState> {
+        let co = self.list.get(id as u32 as usize)?;
+        Some(if co.generation == (id >> 32) as u32 { co.state } else { CoroutineState::Dead })
+    }
+
+    /// 使っている枠の数 (id 0を含む)
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// 実行中のコルーチンから `id` を再開する準備をします
+    /// 終わっているならfalseで、切り替えません
+    pub(crate) fn resume(&mut self, id: u64, value: u64, status_reg: usize, value_reg: usize) -> Result<bool, TrapKind> {
+        let current = self.current;
+        let slot = id as u32 as usize;
+        let Some(target) = self.list.get_mut(slot) else {
+            return Err(TrapKind::InvalidCoroutine(id));
+        };
+        // 枠が使い回された古いidのコルーチンは終わっている
+        if target.generation != (id >> 32) as u32 {
+            return Ok(false);
+        }
+        match target.state {
+            CoroutineState::Dead => return Ok(false),
+            CoroutineState::Running | CoroutineState::Normal => return Err(TrapKind::InvalidCoroutine(id)),
+            CoroutineState::Suspended => {}
+        }
+        target.resumer = current;
+        if let Delivery::Yield { reg } = target.delivery {
+            target.context.r[reg] = value;
+        }
+        self.list[current].state = CoroutineState::Normal;
+        self.list[current].delivery = Delivery::Resume { status_reg, value_reg };
+        self.next = Some(slot);
+        Ok(true)
+    }
+
+    /// 実行中のコルーチンを中断し、再開したコルーチンに戻る準備をします
+    /// 戻った先では *reg に次のCO_RESUMEの値が入ります
+    pub(crate) fn yield_value(&mut self, value: u64, reg: usize) -> Result<(), TrapKind> {
+        let current = self.current;
+        if current == 0 {
+            return Err(TrapKind::YieldOutsideCoroutine);
+        }
+        self.list[current].state = CoroutineState::Suspended;
+        self.list[current].delivery = Delivery::Yield { reg };
+        self.return_to_resumer(value, status::YIELDED);
+        Ok(())
+    }
+
+    /// 実行中のコルーチンを終了し、再開したコルーチンに戻る準備をします
+    pub(crate) fn finish(&mut self, value: u64) {
+        let current = self.current;
+        self.list[current].state = CoroutineState::Dead;
+        self.return_to_resumer(value, status::FINISHED);
+    }
+
+    fn return_to_resumer(&mut self, value: u64, code: u64) {
+        let resumer = self.list[self.current].resumer;
+        let target = &mut self.list[resumer];
+        if let Delivery::Resume { status_reg, value_reg } = target.delivery {
+            target.context.r[value_reg] = value;
+            target.context.r[status_reg] = code;
+        }
+        self.next = Some(resumer);
+    }
+}
+
+impl Coroutine {
+    fn new(state: CoroutineState) -> Self {
+        Coroutine {
+            generation: 0,
+            state,
+            context: Context {
+                r: Box::new([0; 256]),
+                pc: 0,
+                call_stack: Vec::new(),
+                frames: Vec::new(),
+                now_call_index: 0,
+            },
+            resumer: 0,
+            delivery: Delivery::None,
+        }
+    }
+}
+
+impl Default for Coroutines {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// コルーチンの切り替え
+impl VM {
+    /// `next` のコルーチンに切り替えます
+    /// 実行中の状態は枠に退避し、終了したコルーチンの枠は使い回せるようにします
+    /// レジスタはコピーし、スタックは入れ替えるので確保はしません
+    pub(crate) fn switch_coroutine(&mut self) {
+        let coroutines = &mut self.st.coroutines;
+        let Some(next) = coroutines.next.take() else {
+            return;
+        };
+        let current = coroutines.current;
+        let [current_co, next_co] = coroutines
+            .list
+            .get_disjoint_mut([current, next])
+            .expect("switch to another coroutine");
+
+        let saved = &mut current_co.context;
+        *saved.r = self.st.r;
+        saved.pc = self.st.pc;
+        saved.now_call_index = self.st.now_call_index;
+        mem::swap(&mut saved.call_stack, &mut self.st.call_stack);
+        mem::swap(&mut saved.frames, &mut self.st.frames);
+        if current_co.state == CoroutineState::Dead {
+            coroutines.free.push(current);
+        }
+
+        next_co.state = CoroutineState::Running;
+        let context = &mut next_co.context;
+        self.st.r = *context.r;
+        self.st.pc = context.pc;
+        self.st.now_call_index = context.now_call_index;
+        mem::swap(&mut context.call_stack, &mut self.st.call_stack);
+        mem::swap(&mut context.frames, &mut self.st.frames);
+        coroutines.current = next;
+
+        match self.function_ptr(self.st.now_call_index as u64) {
+            Some(function) => self.st.now_function_ptr = function,
+            None => self.trap(TrapKind::BadFunctionIndex(self.st.now_call_index as u64)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_and_yield_deliver_values() {
+        let mut coroutines = Coroutines::new();
+        let id = coroutines.create(3, &[10, 20]);
+        let co = &coroutines.list[id as usize].context;
+        assert_eq!((co.now_call_index, co.r[1], co.r[2], co.r[255]), (3, 10, 20, u64::MAX));
+        assert_eq!(coroutines.yield_value(1, 1), Err(TrapKind::YieldOutsideCoroutine));
+
+        // MAIN -> co
+        assert_eq!(coroutines.resume(id, 7, 2, 3), Ok(true));
+        assert_eq!(coroutines.state(0), Some(CoroutineState::Normal));
+        coroutines.current = coroutines.next.take().unwrap();
+
+        // co -> MAIN
+        coroutines.yield_value(42, 5).unwrap();
+        let main = &coroutines.list[0].context;
+        assert_eq!((main.r[2], main.r[3]), (status::YIELDED, 42));
+        assert_eq!(coroutines.next, Some(0));
+        assert_eq!(coroutines.state(id), Some(CoroutineState::Suspended));
+        assert_eq!(coroutines.resume(0, 0, 1, 1), Err(TrapKind::InvalidCoroutine(0)));
+        assert_eq!(coroutines.resume(9, 0, 1, 1), Err(TrapKind::InvalidCoroutine(9)));
+    }
+}
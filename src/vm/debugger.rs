@@ -0,0 +1,398 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::vm::{
+    disassembler::Disassembler,
+    operations::Instruction,
+    trap::{TrapKind, VmTrap},
+    vm::{VM, state_flag},
+};
+
+/// デバッガで実行を止めた理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// 1命令実行した
+    Step,
+    /// ブレークポイントに到達した
+    Breakpoint,
+    /// EXITで終了した
+    Exited,
+    /// 命令数の上限に達した
+    OutOfFuel,
+    /// NATIVE_CALL_ASYNCのホストI/Oを待っている
+    Pending(u64),
+}
+
+/// バックトレースの1段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    pub function_index: usize,
+    pub pc: usize,
+    /// 以下は関数にデバッグ情報がある場合のみ
+    pub name: Option<String>,
+    pub source_path: Option<PathBuf>,
+    /// ソースの行番号
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{name}:{}", self.pc)?,
+            None => write!(f, "function {}:{}", self.function_index, self.pc)?,
+        }
+        match (&self.source_path, self.line) {
+            (Some(path), Some(line)) => write!(f, " ({}:{line})", path.display()),
+            (None, Some(line)) => write!(f, " (line {line})"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// デバッガAPI
+/// `run` と違い1命令ずつ実行するので遅いです
+impl VM {
+    /// 1命令だけ実行します
+    pub fn step(&mut self) -> Result<StopReason, VmTrap> {
+        if self.st.now_function_ptr.0.is_null() {
+            self.prepare();
+        }
+        self.st.state_flag &= !state_flag::OUT_OF_FUEL;
+        if let Some(reason) = self.stopped()? {
+            return Ok(reason);
+        }
+        match &mut self.st.fuel {
+            Some(0) => return Ok(StopReason::OutOfFuel),
+            Some(fuel) => *fuel -= 1,
+            None => {}
+        }
+
+        let function = self.st.now_function_ptr;
+        match function.instructions.get(self.st.pc) {
+            Some(ins) => ins.run(self),
+            None => self.bad_jump_target(self.st.pc),
+        }
+        if self.st.state_flag & state_flag::SWITCH != 0 {
+            self.st.state_flag &= !state_flag::SWITCH;
+            self.switch_coroutine();
+        }
+
+        // 遅延デコードの差し替え関数は見せずにデコードまで進める
+        while self.st.state_flag == 0
+            && let Some(Instruction::GetDecode(..)) = self.st.now_function_ptr.instructions.get(self.st.pc)
+        {
+            let function = self.st.now_function_ptr;
+            function.instructions[self.st.pc].run(self);
+        }
+
+        Ok(self.stopped()?.unwrap_or(StopReason::Step))
+    }
+
+    /// ブレークポイントに到達するか終了するまで実行します
+    /// 現在位置のブレークポイントでは止まりません
+    pub fn continue_until_breakpoint(&mut self) -> Result<StopReason, VmTrap> {
+        loop {
+            match self.step()? {
+                StopReason::Step => {}
+                reason => return Ok(reason),
+            }
+            if self.breakpoints.contains(&(self.st.now_call_index, self.st.pc)) {
+                return Ok(StopReason::Breakpoint);
+            }
+        }
+    }
+
+    /// 関数名とpcでブレークポイントを設定します
+    /// 関数が存在しなければfalse
+    pub fn add_breakpoint(&mut self, function: &str, pc: usize) -> bool {
+        let name = function.to_ascii_uppercase();
+        let Some(&index) = self.cm.name_to_index.read().unwrap().get(&name) else {
+            return false;
+        };
+        self.breakpoints.insert((index, pc));
+        true
+    }
+
+    pub fn remove_breakpoint(&mut self, function: &str, pc: usize) -> bool {
+        let name = function.to_ascii_uppercase();
+        let Some(&index) = self.cm.name_to_index.read().unwrap().get(&name) else {
+            return false;
+        };
+        self.breakpoints.remove(&(index, pc))
+    }
+
+    /// 現在位置から呼び出し元へ向かう呼び出し履歴
+    pub fn backtrace(&self) -> Vec<StackFrame> {
+        let frame = |function_index: usize, pc: usize| {
+            let debug = self.function_table.get(function_index).and_then(|f| f.debug.clone());
+            StackFrame {
+                function_index,
+                pc,
+                name: debug.as_ref().map(|debug| debug.name.clone()),
+                source_path: debug.as_ref().and_then(|debug| debug.source_path.clone()),
+                line: debug.as_ref().and_then(|debug| debug.lines.get(pc).copied()),
+            }
+        };
+
+        let mut frames = vec![frame(self.st.now_call_index, self.st.pc)];
+        // call_stack は [pc, 関数index] の組で積まれている
+        for pair in self.st.call_stack.chunks_exact(2).rev() {
+            frames.push(frame(pair[1], pair[0]));
+        }
+        frames
+    }
+
+    /// heepの内容を読み出します
+    pub fn read_heep(&self, id: u64, offset: usize, len: usize) -> Result<Vec<u8>, TrapKind> {
+        self.st.mem.read_bytes(id, offset, len)
+    }
+
+    /// 現在の命令の逆アセンブル
+    pub fn current_instruction(&self) -> Option<String> {
+        if self.st.now_function_ptr.0.is_null() {
+            return None;
+        }
+        let ins = self.st.now_function_ptr.instructions.get(self.st.pc)?;
+        let names = self.cm.function_names();
+        let natives = self.cm.natives.read().unwrap().names().to_vec();
+        Some(Disassembler::new(&names).with_natives(&natives).disassemble_instruction(ins))
+    }
+
+    fn stopped(&self) -> Result<Option<StopReason>, VmTrap> {
+        if self.st.state_flag & state_flag::TRAP != 0 {
+            return Err(self.st.trap.clone().expect("trap flag without trap"));
+        }
+        if self.st.state_flag & state_flag::PAUSE != 0 {
+            return Ok(Some(match self.pending() {
+                Some(token) => StopReason::Pending(token),
+                None => StopReason::Exited,
+            }));
+        }
+        Ok(None)
+    }
+}
+
+const HELP: &str = "\
+commands:
+  s, step [n]            execute n instructions (default 1)
+  c, continue            run until a breakpoint or exit
+  b, break FUNC PC       set a breakpoint
+  d, delete FUNC PC      remove a breakpoint
+  w, where               show the current location
+  bt, backtrace          show the call stack
+  r, regs                show non-zero registers
+  p, print rN            show a register
+  set rN VALUE           modify a register
+  x ID OFFSET LEN        dump heep bytes
+  q, quit                exit the debugger";
+
+/// デバッガのコマンドを1行実行します
+/// quitならfalseを返します
+pub fn execute_command(vm: &mut VM, line: &str, out: &mut impl Write) -> io::Result<bool> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    let Some((&command, args)) = tokens.split_first() else {
+        return Ok(true);
+    };
+
+    match (command, args) {
+        ("q" | "quit", _) => return Ok(false),
+        ("h" | "help", _) => writeln!(out, "{HELP}")?,
+        ("s" | "step", args) => {
+            let count = match args.first().map(|n| n.parse::<usize>()) {
+                None => 1,
+                Some(Ok(count)) => count,
+                Some(Err(_)) => return usage(out, "step [n]"),
+            };
+            let mut result = Ok(StopReason::Step);
+            for _ in 0..count {
+                result = vm.step();
+                if result != Ok(StopReason::Step) {
+                    break;
+                }
+            }
+            report(vm, result, out)?;
+        }
+        ("c" | "continue", _) => {
+            let result = vm.continue_until_breakpoint();
+            report(vm, result, out)?;
+        }
+        ("b" | "break", [function, pc]) => match pc.parse() {
+            Ok(pc) if vm.add_breakpoint(function, pc) => writeln!(out, "breakpoint at {function}:{pc}")?,
+            Ok(_) => writeln!(out, "unknown function '{function}'")?,
+            Err(_) => return usage(out, "break FUNC PC"),
+        },
+        ("d" | "delete", [function, pc]) => match pc.parse() {
+            Ok(pc) if vm.remove_breakpoint(function, pc) => writeln!(out, "deleted {function}:{pc}")?,
+            Ok(_) => writeln!(out, "no breakpoint at {function}:{pc}")?,
+            Err(_) => return usage(out, "delete FUNC PC"),
+        },
+        ("w" | "where", _) => write_location(vm, out)?,
+        ("bt" | "backtrace", _) => {
+            for (depth, frame) in vm.backtrace().iter().enumerate() {
+                writeln!(out, "#{depth} {frame}")?;
+            }
+        }
+        ("r" | "regs", _) => {
+            for (reg, value) in vm.st.r.iter().enumerate() {
+                if *value != 0 && reg != 255 {
+                    writeln!(out, "r{reg} = {value} ({value:#x})")?;
+                }
+            }
+        }
+        ("p" | "print", [reg]) => match parse_register(reg) {
+            Some(reg) => {
+                let value = vm.st.r[reg];
+                writeln!(out, "r{reg} = {value} ({value:#x})")?;
+            }
+            None => return usage(out, "print rN"),
+        },
+        ("set", [reg, value]) => match (parse_register(reg), parse_value(value)) {
+            (Some(0 | 255), Some(_)) => writeln!(out, "error: {reg} is a fixed register")?,
+            (Some(reg), Some(value)) => {
+                vm.st.r[reg] = value;
+                writeln!(out, "r{reg} = {value} ({value:#x})")?;
+            }
+            _ => return usage(out, "set rN VALUE"),
+        },
+        ("x", [id, offset, len]) => {
+            match (parse_value(id), parse_value(offset), parse_value(len)) {
+                (Some(id), Some(offset), Some(len)) => {
+                    match vm.read_heep(id, offset as usize, len as usize) {
+                        Ok(bytes) => {
+                            for (row, chunk) in bytes.chunks(16).enumerate() {
+                                let hex = chunk.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>();
+                                writeln!(out, "{:#06x}: {}", offset as usize + row * 16, hex.join(" "))?;
+                            }
+                        }
+                        Err(kind) => writeln!(out, "error: {kind}")?,
+                    }
+                }
+                _ => return usage(out, "x ID OFFSET LEN"),
+            }
+        }
+        _ => writeln!(out, "unknown command '{}' (try 'help')", line.trim())?,
+    }
+    Ok(true)
+}
+
+fn usage(out: &mut impl Write, usage: &str) -> io::Result<bool> {
+    writeln!(out, "usage: {usage}")?;
+    Ok(true)
+}
+
+fn report(vm: &VM, result: Result<StopReason, VmTrap>, out: &mut impl Write) -> io::Result<()> {
+    match result {
+        Ok(StopReason::Exited) => writeln!(out, "exited with code {}", vm.st.exit_code),
+        Ok(StopReason::OutOfFuel) => writeln!(out, "out of fuel"),
+        Ok(StopReason::Pending(token)) => writeln!(out, "waiting for host I/O (token {token})"),
+        Ok(StopReason::Breakpoint) => {
+            write!(out, "breakpoint ")?;
+            write_location(vm, out)
+        }
+        Ok(StopReason::Step) => write_location(vm, out),
+        Err(trap) => writeln!(out, "trap: {trap}"),
+    }
+}
+
+fn write_location(vm: &VM, out: &mut impl Write) -> io::Result<()> {
+    write!(out, "{}", vm.backtrace()[0])?;
+    match vm.current_instruction() {
+        Some(ins) => writeln!(out, "  {ins}"),
+        None => writeln!(out),
+    }
+}
+
+fn parse_register(token: &str) -> Option<usize> {
+    let reg = token.strip_prefix('r').or_else(|| token.strip_prefix('R'))?;
+    reg.parse::<u8>().ok().map(usize::from)
+}
+
+fn parse_value(token: &str) -> Option<u64> {
+    match token.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => token.parse::<u64>().ok().or_else(|| token.parse::<i64>().ok().map(|v| v as u64)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::code_manager::CodeManager;
+
+    const SOURCE: &str = r#"
+MAIN
+ALLOC r0 r9 8
+LOAD_U64_IMMEDIATE r1 0x0102
+STORE_U64 r9 r0 r1 0
+CALL WORK
+EXIT 0
+
+WORK
+ADD_U64_IMMEDIATE r1 1
+ADD_U64_IMMEDIATE r1 1
+RET
+"#;
+
+    fn vm() -> VM {
+        let cm = CodeManager::new("test".into());
+        cm.load_source("test.peeledmikan".into(), SOURCE).expect("load succeeds");
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm);
+        vm
+    }
+
+    #[test]
+    fn breakpoint_backtrace_and_heep() {
+        let mut vm = vm();
+        assert!(vm.add_breakpoint("work", 1));
+        assert!(!vm.add_breakpoint("missing", 0));
+
+        assert_eq!(vm.continue_until_breakpoint(), Ok(StopReason::Breakpoint));
+        assert_eq!(vm.st.r[1], 0x0103);
+        assert_eq!(vm.current_instruction().as_deref(), Some("ADD_U64_IMMEDIATE r1 1"));
+
+        let backtrace = vm.backtrace();
+        assert_eq!(
+            backtrace
+                .iter()
+                .map(|f| (f.name.as_deref(), f.pc, f.line))
+                .collect::<Vec<_>>(),
+            [(Some("WORK"), 1, Some(11)), (Some("MAIN"), 3, Some(6))]
+        );
+        assert_eq!(backtrace[0].to_string(), "WORK:1 (test.peeledmikan:11)");
+
+        assert_eq!(vm.read_heep(0, 0, 2), Ok(vec![0x02, 0x01]));
+        assert!(vm.read_heep(0, 4, 8).is_err());
+
+        vm.st.r[1] = 100;
+        assert_eq!(vm.step(), Ok(StopReason::Step));
+        assert_eq!(vm.continue_until_breakpoint(), Ok(StopReason::Exited));
+        assert_eq!(vm.st.r[1], 101);
+    }
+
+    #[test]
+    fn cli_commands() {
+        let mut vm = vm();
+        let mut out = Vec::new();
+        for line in ["break WORK 0", "continue", "set r1 7", "step 2", "bt", "print r1", "c"] {
+            assert!(execute_command(&mut vm, line, &mut out).unwrap());
+        }
+        assert!(!execute_command(&mut vm, "quit", &mut out).unwrap());
+
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out,
+            "breakpoint at WORK:0\n\
+             breakpoint WORK:0 (test.peeledmikan:10)  ADD_U64_IMMEDIATE r1 1\n\
+             r1 = 7 (0x7)\n\
+             WORK:2 (test.peeledmikan:12)  RET\n\
+             #0 WORK:2 (test.peeledmikan:12)\n\
+             #1 MAIN:3 (test.peeledmikan:6)\n\
+             r1 = 9 (0x9)\n\
+             exited with code 0\n"
+        );
+    }
+}
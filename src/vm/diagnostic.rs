@@ -0,0 +1,144 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::vm::pre_decoder::PreDecodeError;
+
+/// デコードエラーとソース上の位置
+/// `PreDecoder::decode_with_diagnostics` と `CodeManager::load_source` がまとめて返します
+///
+/// ```text
+/// error: unknown opcode 'ADD_U46'
+///  --> main.mikan:4:1
+///   |
+/// 4 | ADD_U46 r1 r2 r3
+///   | ^^^^^^^
+/// ```
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub error: PreDecodeError,
+    /// 1始まりの行番号 MissingMainのように行がないものはNone
+    pub line: Option<usize>,
+    /// 行内の列範囲 (1始まり、文字単位)
+    pub columns: Range<usize>,
+}
+
+impl Diagnostic {
+    /// `source` からエラー箇所の列を探します
+    pub fn new(error: PreDecodeError, source: &str) -> Self {
+        let line = error.line();
+        let columns = line
+            .and_then(|line| source.lines().nth(line - 1))
+            .map(|text| locate(text, error.token()))
+            .unwrap_or(1..1);
+        Self {
+            error,
+            line,
+            columns,
+        }
+    }
+
+    /// rustcと同じ形式で、エラーの行とキャレットを付けて表示します
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let mut out = String::new();
+        writeln!(out, "error: {}", self.error.message()).unwrap();
+
+        let Some((line, text)) = self
+            .line
+            .and_then(|line| Some((line, source.lines().nth(line - 1)?)))
+        else {
+            writeln!(out, " --> {path}").unwrap();
+            return out;
+        };
+
+        let gutter = " ".repeat(line.to_string().len());
+        writeln!(out, "{gutter}--> {path}:{line}:{}", self.columns.start).unwrap();
+        writeln!(out, "{gutter} |").unwrap();
+        writeln!(out, "{line} | {text}").unwrap();
+
+        // タブはそのまま残してキャレットの位置を揃える
+        let indent = text
+            .chars()
+            .take(self.columns.start - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let carets = "^".repeat(self.columns.len().max(1));
+        writeln!(out, "{gutter} | {indent}{carets}").unwrap();
+        out
+    }
+}
+
+/// 集めたエラーに位置を付け、行順に並べます
+pub fn collect(errors: Vec<PreDecodeError>, source: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = errors
+        .into_iter()
+        .map(|error| Diagnostic::new(error, source))
+        .collect::<Vec<_>>();
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line.unwrap_or(usize::MAX));
+    diagnostics
+}
+
+/// 複数の診断を空行区切りで表示します
+pub fn render_diagnostics(diagnostics: &[Diagnostic], source: &str, path: &str) -> String {
+    let mut out = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.render(source, path))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !diagnostics.is_empty() {
+        writeln!(out, "\nerror: could not decode due to {} error(s)", diagnostics.len()).unwrap();
+    }
+    out
+}
+
+/// `token` に一致するトークンの列範囲
+/// 見つからなければコメントを除いた行全体
+fn locate(text: &str, token: Option<&str>) -> Range<usize> {
+    let code = text.split(';').next().unwrap_or_default();
+    let byte_range = code
+        .split_whitespace()
+        .find(|candidate| {
+            token.is_some_and(|token| candidate.trim_end_matches(':').eq_ignore_ascii_case(token))
+        })
+        .map(|candidate| {
+            let start = candidate.as_ptr() as usize - code.as_ptr() as usize;
+            start..start + candidate.len()
+        })
+        .unwrap_or_else(|| {
+            let trimmed = code.trim();
+            let start = trimmed.as_ptr() as usize - code.as_ptr() as usize;
+            start..start + trimmed.len()
+        });
+
+    let start = code[..byte_range.start].chars().count() + 1;
+    start..start + code[byte_range].chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{native::NativeRegistry, pre_decoder::PreDecoder};
+
+    #[test]
+    fn renders_line_and_caret() {
+        let source = "MAIN\n\tCALL MISSING ; コメント\nEXIT 0\n";
+        let diagnostics = PreDecoder::new()
+            .decode_with_diagnostics(source, &NativeRegistry::new())
+            .err()
+            .expect("decode fails");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].columns.clone()), (Some(2), 7..14));
+        assert_eq!(
+            diagnostics[0].render(source, "test.mikan"),
+            "error: referenced function 'MISSING' is not defined\n --> test.mikan:2:7\n  |\n2 | \tCALL MISSING ; コメント\n  | \t     ^^^^^^^\n"
+        );
+
+        let missing_main = PreDecoder::new()
+            .decode_with_diagnostics("SUB\nRET\n", &NativeRegistry::new())
+            .err()
+            .expect("decode fails");
+        assert_eq!(
+            render_diagnostics(&missing_main, "SUB\nRET\n", "test.mikan"),
+            "error: MAIN function is required\n --> test.mikan\n\nerror: could not decode due to 1 error(s)\n"
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::vm::{
+    function::Function,
+    operations::{Instruction, MemoryOrdering},
+    pre_decoder::{OperandPlan, opcode_name, opcode_operands, opcode_orderings},
+};
+
+/// 逆アセンブラ
+/// `Function` をテキスト形式に戻します
+/// 出力は `PreDecoder::decode` でそのまま読み直せます
+/// 関数にデバッグ情報があれば、各命令にソースの行番号をコメントで付けます
+///
+/// ```text
+/// MAIN
+/// CALL SUB 0              ; 関数indexは関数名に戻す
+/// .L2:                    ; r0基準のジャンプ先はローカルラベルにする
+/// ATOMIC_ADD_U64 r8 r3 r0 r1 0
+/// LT_U64_JUMP r0 r1 r2 .L2
+/// ```
+pub struct Disassembler<'a> {
+    /// 関数テーブル上のindex -> 関数名
+    names: &'a [String],
+    /// ホスト関数index -> ホスト関数名
+    natives: &'a [String],
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(names: &'a [String]) -> Self {
+        Self { names, natives: &[] }
+    }
+
+    /// NATIVE_CALLのホスト関数名を設定します
+    pub fn with_natives(mut self, natives: &'a [String]) -> Self {
+        self.natives = natives;
+        self
+    }
+
+    /// `names` と同じ並びの関数列をまとめて逆アセンブルします
+    pub fn disassemble(&self, functions: &[Function]) -> String {
+        let mut out = String::new();
+        for (idx, function) in functions.iter().enumerate() {
+            if idx > 0 {
+                out.push('\n');
+            }
+            let name = self
+                .names
+                .get(idx)
+                .map(String::as_str)
+                .or_else(|| function.name())
+                .unwrap_or("UNKNOWN");
+            out.push_str(&self.disassemble_function(name, function));
+        }
+        out
+    }
+
+    /// 1関数分を逆アセンブルします
+    pub fn disassemble_function(&self, name: &str, function: &Function) -> String {
+        let labels = local_labels(function);
+
+        let mut out = String::new();
+        writeln!(out, "{name}").unwrap();
+        for (pc, ins) in function.instructions.iter().enumerate() {
+            if labels.contains(&pc) {
+                writeln!(out, ".L{pc}:").unwrap();
+            }
+            match function.line(pc) {
+                Some(line) => writeln!(out, "{} ; line {line}", self.instruction(ins, &labels)).unwrap(),
+                None => writeln!(out, "{}", self.instruction(ins, &labels)).unwrap(),
+            }
+        }
+        // 末尾を指すラベル
+        if labels.contains(&function.instructions.len()) {
+            writeln!(out, ".L{}:", function.instructions.len()).unwrap();
+        }
+        out
+    }
+
+    /// 1命令を逆アセンブルします
+    /// ローカルラベルは使わず、ジャンプ先は数値のままです
+    pub fn disassemble_instruction(&self, ins: &Instruction) -> String {
+        self.instruction(ins, &BTreeSet::new())
+    }
+
+    fn instruction(&self, ins: &Instruction, labels: &BTreeSet<usize>) -> String {
+        let name = opcode_name(ins);
+        let plans = opcode_operands(name).expect("opcode in table");
+        let (mut a, b) = ins.operands();
+
+        let mut out = String::from(name);
+        // オーダリングはオペコードの後ろに付ける
+        if !opcode_orderings(name).is_empty()
+            && let Some(ordering) = MemoryOrdering::from_operand(a)
+        {
+            if ordering != MemoryOrdering::SeqCst {
+                write!(out, ".{}", ordering.name()).unwrap();
+            }
+            a &= (1 << MemoryOrdering::SHIFT) - 1;
+        }
+        let mut packed_base = 0;
+        for (plan, value) in plans.iter().zip([a, b]) {
+            out.push(' ');
+            match plan {
+                OperandPlan::Value => out.push_str(&format_value(value)),
+                OperandPlan::Register => write!(out, "r{value}").unwrap(),
+                OperandPlan::PackedRegisters(count) => {
+                    let count = *count as u32;
+                    if value >> (8 * count) != 0 {
+                        // 8bitに収まらないものは数値のまま
+                        write!(out, "{value:#x}").unwrap();
+                    } else {
+                        let mut regs = (0..count)
+                            .rev()
+                            .map(|idx| format!("r{}", (value >> (8 * idx)) & 0xFF))
+                            .collect::<Vec<_>>();
+                        // NATIVE_CALL/CALL_ARGS/SPAWN/CO_CREATEの最後は引数の個数
+                        if matches!(
+                            ins,
+                            Instruction::NativeCall(..)
+                                | Instruction::NativeCallAsync(..)
+                                | Instruction::CallArgs(..)
+                                | Instruction::CallRegArgs(..)
+                                | Instruction::Spawn(..)
+                                | Instruction::CoCreate(..)
+                        ) {
+                            regs[count as usize - 1] = (value & 0xFF).to_string();
+                        }
+                        out.push_str(&regs.join(" "));
+                    }
+                    packed_base = (value >> (8 * (count - 1))) & 0xFF;
+                }
+                OperandPlan::Target => {
+                    let base = if matches!(ins, Instruction::Jump(..)) { a } else { packed_base };
+                    if base == 0 && labels.contains(&(value as usize)) {
+                        write!(out, ".L{value}").unwrap();
+                    } else {
+                        out.push_str(&format_value(value));
+                    }
+                }
+                OperandPlan::Function => match self.names.get(value as usize) {
+                    Some(name) => out.push_str(name),
+                    None => out.push_str(&format_value(value)),
+                },
+                OperandPlan::Native => match self.natives.get(value as usize) {
+                    Some(name) => out.push_str(name),
+                    None => out.push_str(&format_value(value)),
+                },
+            }
+        }
+        out
+    }
+}
+
+/// r0基準で関数内を指すジャンプ先
+fn local_labels(function: &Function) -> BTreeSet<usize> {
+    let len = function.instructions.len() as u64;
+    let mut labels = BTreeSet::new();
+    for ins in function.instructions.iter() {
+        let plans = opcode_operands(opcode_name(ins)).expect("opcode in table");
+        let (a, b) = ins.operands();
+        let base = match plans {
+            [OperandPlan::Register, OperandPlan::Target] => a,
+            [OperandPlan::PackedRegisters(count), OperandPlan::Target] => {
+                if a >> (8 * *count as u32) != 0 {
+                    continue;
+                }
+                (a >> (8 * (*count as u32 - 1))) & 0xFF
+            }
+            _ => continue,
+        };
+        if base == 0 && b <= len {
+            labels.insert(b as usize);
+        }
+    }
+    labels
+}
+
+/// 小さい負数は符号付き、大きい値は16進で表示する
+fn format_value(value: u64) -> String {
+    let signed = value as i64;
+    if (-0x1_0000..0).contains(&signed) {
+        format!("{signed}")
+    } else if value > 0xFFFF {
+        format!("{value:#x}")
+    } else {
+        format!("{value}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::pre_decoder::{PreDecoder, build_instruction, opcode_names};
+
+    fn names() -> Vec<String> {
+        vec!["MAIN".to_string(), "SUB".to_string()]
+    }
+
+    #[test]
+    fn unpacks_registers_and_restores_names() {
+        let names = names();
+        let disassembler = Disassembler::new(&names);
+        assert_eq!(
+            disassembler.disassemble_instruction(&Instruction::AtomicAddU64(0x08030001, 0)),
+            "ATOMIC_ADD_U64 r8 r3 r0 r1 0"
+        );
+        assert_eq!(disassembler.disassemble_instruction(&Instruction::Call(1, 0)), "CALL SUB 0");
+        assert_eq!(
+            disassembler.disassemble_instruction(&Instruction::LoadU64(0x010203, (-8i64) as u64)),
+            "LOAD_U64 r1 r2 r3 -8"
+        );
+        assert_eq!(
+            disassembler.disassemble_instruction(&Instruction::AtomicAddU64(
+                0x08030001 | MemoryOrdering::Relaxed.operand(),
+                0
+            )),
+            "ATOMIC_ADD_U64.RELAXED r8 r3 r0 r1 0"
+        );
+        assert_eq!(
+            disassembler.disassemble_instruction(&Instruction::Fence(MemoryOrdering::Acquire.operand(), 0)),
+            "FENCE.ACQUIRE"
+        );
+
+        let source = r#"
+MAIN
+.loop:
+ADD_U64_IMMEDIATE r1 1
+LT_U64_JUMP r0 r1 r2 .loop
+CALL SUB
+EXIT r0
+
+SUB
+RET
+"#;
+        let functions = PreDecoder::new().decode(source).unwrap();
+        assert_eq!(
+            disassembler.disassemble(&functions),
+            "MAIN\n.L0:\nADD_U64_IMMEDIATE r1 1 ; line 4\nLT_U64_JUMP r0 r1 r2 .L0 ; line 5\nCALL SUB 0 ; line 6\nEXIT r0 0 ; line 7\n\nSUB\nRET ; line 10\n"
+        );
+    }
+
+    #[test]
+    fn round_trips_every_opcode() {
+        let mut main = Vec::new();
+        for name in opcode_names() {
+            let plans = opcode_operands(name).unwrap();
+            let mut operands = [0u64; 2];
+            for (idx, plan) in plans.iter().enumerate() {
+                operands[idx] = match plan {
+                    OperandPlan::Value => 0x1234_5678_9abc,
+                    OperandPlan::Register => 7,
+                    OperandPlan::PackedRegisters(count) => {
+                        (0..*count as u64).fold(0, |acc, reg| (acc << 8) | (reg + 1))
+                    }
+                    OperandPlan::Target => 3,
+                    OperandPlan::Function => 1,
+                    OperandPlan::Native => 0,
+                };
+            }
+            main.push(build_instruction(name, operands[0], operands[1]).unwrap());
+            // オーダリング付きも読み直せる
+            if let Some(ordering) = opcode_orderings(name).last() {
+                main.push(build_instruction(name, operands[0] | ordering.operand(), operands[1]).unwrap());
+            }
+            // r0基準のジャンプはラベルとして出力される
+            if plans.last().is_some_and(|plan| matches!(plan, OperandPlan::Target)) {
+                let base = match plans[0] {
+                    OperandPlan::PackedRegisters(_) => 0x0102,
+                    _ => 0,
+                };
+                main.push(build_instruction(name, base, 2).unwrap());
+            }
+        }
+        let functions = vec![
+            Function::new(main.into_boxed_slice()),
+            Function::new(Box::new([Instruction::Ret(0, 0)])),
+        ];
+
+        let names = names();
+        let text = Disassembler::new(&names).disassemble(&functions);
+        assert!(text.contains(".L2:"));
+
+        let decoded = PreDecoder::new().decode(&text).expect("disassembly decodes");
+        assert_eq!(decoded.len(), functions.len());
+        for (decoded, original) in decoded.iter().zip(&functions) {
+            assert_eq!(decoded.instructions[..], original.instructions[..]);
+        }
+    }
+}
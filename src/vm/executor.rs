@@ -0,0 +1,210 @@
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use crate::vm::{
+    trap::VmTrap,
+    vm::{RunStatus, VM},
+};
+
+/// ホストI/Oの完了通知
+struct Completion {
+    task: usize,
+    token: u64,
+    value: u64,
+}
+
+/// I/Oの完了をExecutorに知らせるハンドル
+/// Executorに入れたVMごとに作られ、`VM::completer` から取れます 別スレッドに渡せます
+#[derive(Clone)]
+pub struct Completer {
+    task: usize,
+    sender: Sender<Completion>,
+}
+
+impl Completer {
+    /// NATIVE_CALL_ASYNCのトークンの操作が value で完了したことを知らせます
+    /// Executorが既に無ければfalse
+    pub fn complete(&self, token: u64, value: u64) -> bool {
+        self.sender
+            .send(Completion {
+                task: self.task,
+                token,
+                value,
+            })
+            .is_ok()
+    }
+
+    /// 通知先のタスク
+    pub fn task(&self) -> usize {
+        self.task
+    }
+}
+
+struct Task {
+    vm: VM,
+    /// 終わったら結果 I/O待ちの間はNone
+    result: Option<Result<RunStatus, VmTrap>>,
+}
+
+/// 1つのスレッドで多数のVMを動かす実行器
+/// NATIVE_CALL_ASYNCで止まったVMは脇に置いてほかのVMを進め、完了通知が届いたら続きから再開します
+/// 通知はチャネルで受けるので、I/Oはどのスレッドで完了させても構いません
+pub struct Executor {
+    tasks: Vec<Task>,
+    ready: VecDeque<usize>,
+    sender: Sender<Completion>,
+    receiver: Receiver<Completion>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Executor {
+            tasks: Vec::new(),
+            ready: VecDeque::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// VMを実行待ちに加えてタスク番号を返します
+    /// VMは `now_call_index` の関数から実行を始めます
+    pub fn spawn(&mut self, mut vm: VM) -> usize {
+        let task = self.tasks.len();
+        vm.completer = Some(Completer {
+            task,
+            sender: self.sender.clone(),
+        });
+        self.tasks.push(Task { vm, result: None });
+        self.ready.push_back(task);
+        task
+    }
+
+    /// すべてのVMが終わるまで実行します
+    /// 実行できるVMが無いときは完了通知が届くまでスレッドを止めます
+    /// 完了しないI/Oを待っているVMがあると戻りません
+    pub fn run(&mut self) {
+        loop {
+            while let Some(task) = self.ready.pop_front() {
+                let task = &mut self.tasks[task];
+                match task.vm.run() {
+                    Ok(RunStatus::Pending(_)) => {}
+                    result => task.result = Some(result),
+                }
+            }
+            if self.tasks.iter().all(|task| task.result.is_some()) {
+                return;
+            }
+            // 届いている通知をまとめて反映し、無ければ待つ
+            let completion = self.receiver.recv().expect("executor holds a sender");
+            self.complete(completion);
+            while let Ok(completion) = self.receiver.try_recv() {
+                self.complete(completion);
+            }
+        }
+    }
+
+    /// タスクのVM
+    pub fn vm(&self, task: usize) -> Option<&VM> {
+        self.tasks.get(task).map(|task| &task.vm)
+    }
+
+    /// 終わったタスクの結果
+    pub fn result(&self, task: usize) -> Option<&Result<RunStatus, VmTrap>> {
+        self.tasks.get(task).and_then(|task| task.result.as_ref())
+    }
+
+    /// トークンの違う古い通知は捨てます
+    fn complete(&mut self, completion: Completion) {
+        if let Some(task) = self.tasks.get_mut(completion.task)
+            && task.vm.complete(completion.token, completion.value)
+        {
+            self.ready.push_back(completion.task);
+        }
+    }
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::{AtomicU64, Ordering}, thread, time::Duration};
+
+    use super::*;
+    use crate::vm::{code_manager::CodeManager, trap::TrapKind};
+
+    static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+    /// 別スレッドで少し待ってから *arg * 2 で完了する
+    fn double_later(vm: &mut VM, args: &[u64]) -> Result<u64, TrapKind> {
+        let completer = vm.completer.clone().ok_or_else(|| TrapKind::Native("not in an executor".into()))?;
+        let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+        let value = args[0] * 2;
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(value));
+            completer.complete(token, value);
+        });
+        Ok(token)
+    }
+
+    const SOURCE: &str = r#"
+MAIN
+LOAD_U64_IMMEDIATE r3 3
+.loop:
+NATIVE_CALL_ASYNC double_later r2 r1 1
+ADD_U64 r4 r2
+SUB_U64_IMMEDIATE r3 1
+NEQ_JUMP r0 r3 r0 .loop
+EXIT r4
+"#;
+
+    #[test]
+    fn executor_multiplexes_parked_vms() {
+        let cm = CodeManager::new("test".into());
+        cm.register_native("double_later", double_later).unwrap();
+        cm.load_source("test".into(), SOURCE).expect("load succeeds");
+
+        let mut executor = Executor::new();
+        for arg in 1..=8 {
+            let mut vm = VM::new();
+            vm.replace_code_manager(cm.clone_shared());
+            vm.st.r[1] = arg;
+            executor.spawn(vm);
+        }
+        executor.run();
+
+        for task in 0..8 {
+            let vm = executor.vm(task).unwrap();
+            assert_eq!(executor.result(task), Some(&Ok(RunStatus::Exited)));
+            assert_eq!(vm.st.exit_code, (task as u64 + 1) * 6);
+            assert_eq!(vm.pending(), None);
+        }
+    }
+
+    #[test]
+    fn pending_vm_resumes_after_complete() {
+        fn start_io(_: &mut VM, args: &[u64]) -> Result<u64, TrapKind> {
+            Ok(args[0] + 100)
+        }
+
+        let cm = CodeManager::new("test".into());
+        cm.register_native("START_IO", start_io).unwrap();
+        cm.load_source("test".into(), "MAIN\nLOAD_U64_IMMEDIATE r1 7\nNATIVE_CALL_ASYNC START_IO r2 r1 1\nEXIT r2\n").unwrap();
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm);
+
+        assert_eq!(vm.run(), Ok(RunStatus::Pending(107)));
+        // 完了するまでは何度runしても止まったまま
+        assert_eq!(vm.run(), Ok(RunStatus::Pending(107)));
+        assert!(!vm.complete(1, 5));
+        assert!(vm.complete(107, 5));
+        assert_eq!(vm.run(), Ok(RunStatus::Exited));
+        assert_eq!(vm.st.exit_code, 5);
+    }
+}
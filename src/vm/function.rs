@@ -1,4 +1,4 @@
-use std::{ops::Deref, pin::Pin};
+use std::{ops::Deref, path::PathBuf, pin::Pin, sync::Arc};
 
 use crate::vm::operations::Instruction;
 
@@ -6,11 +6,48 @@ use crate::vm::operations::Instruction;
 #[derive(Clone)]
 pub struct Function {
     pub instructions: Pin<Box<[Instruction]>>,
+    /// デバッグ情報
+    /// trapやバックトレース、逆アセンブルでソースの位置を示すのに使います
+    pub debug: Option<Arc<DebugInfo>>,
+}
+
+/// 関数のデバッグ情報
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// 関数名
+    pub name: String,
+    /// ソースのパス
+    pub source_path: Option<PathBuf>,
+    /// pc -> ソースの行番号 (空なら行情報なし)
+    pub lines: Box<[usize]>,
 }
 
 impl Function {
     pub fn new(instructions: Box<[Instruction]>) -> Self {
-        Function { instructions: Pin::new(instructions) }
+        Function { instructions: Pin::new(instructions), debug: None }
+    }
+
+    pub fn with_debug_info(mut self, debug: DebugInfo) -> Self {
+        self.debug = Some(Arc::new(debug));
+        self
+    }
+
+    /// ソースのパスを設定します
+    /// デバッグ情報がなければ何もしません
+    pub fn set_source_path(&mut self, path: PathBuf) {
+        if let Some(debug) = &mut self.debug {
+            Arc::make_mut(debug).source_path = Some(path);
+        }
+    }
+
+    /// 関数名
+    pub fn name(&self) -> Option<&str> {
+        self.debug.as_ref().map(|debug| debug.name.as_str())
+    }
+
+    /// pcに対応するソースの行番号
+    pub fn line(&self, pc: usize) -> Option<usize> {
+        self.debug.as_ref()?.lines.get(pc).copied()
     }
 
     #[inline(always)]
@@ -1,12 +1,21 @@
 use std::{
     alloc,
-    ops::{Deref, DerefMut},
+    ops::Deref,
     ptr::NonNull,
+    sync::{Arc, Mutex, Weak},
 };
 
+use crate::vm::trap::TrapKind;
+
 pub struct Memory {
     pub data: Vec<Heep>,
     pub reuse_list: Vec<usize>,
+    /// trueならLOAD/STORE/ATOMICのアクセス範囲とアラインメントを検査します
+    /// ベンチマーク用にデフォルトは無検査
+    pub bounds_check: bool,
+    /// VM間で共有するheepの表
+    /// VMPoolで動かすとプール全体で同じ表になります
+    pub shared: Arc<SharedHeeps>,
 }
 
 impl Memory {
@@ -14,88 +23,241 @@ impl Memory {
         Memory {
             data: Vec::new(),
             reuse_list: Vec::new(),
+            bounds_check: false,
+            shared: Arc::new(SharedHeeps::new()),
         }
     }
 
     /// 新しいHeepとそのid
     #[inline(always)]
-    pub fn alloc_heep(&mut self, size: usize) -> u64 {
+    pub fn alloc_heep(&mut self, size: usize) -> Result<u64, TrapKind> {
+        let heep = Heep::new(size)?;
+        Ok(self.insert_heep(heep))
+    }
+
+    #[inline(always)]
+    fn insert_heep(&mut self, heep: Heep) -> u64 {
         if let Some(id) = self.reuse_list.pop() {
-            let heep = &mut self.data[id as usize];
-            heep.alloc(size);
-            return id as u64;
+            self.data[id] = heep;
+            id as u64
         } else {
-            let id = self.data.len() as u64;
-            let heep = Heep::new(size);
             self.data.push(heep);
-            return id;
+            (self.data.len() - 1) as u64
         }
     }
 
+    /// 共有heepは他のVMがアドレスを持っているのでサイズを変えられません
     #[inline(always)]
-    pub fn realloc_heep(&mut self, id: u64, new_size: usize) {
-        if let Some(heep) = self.data.get_mut(id as usize) {
-            heep.realloc(new_size);
-        } else {
-            std::process::exit(-9998);
+    pub fn realloc_heep(&mut self, id: u64, new_size: usize) -> Result<(), TrapKind> {
+        match self.data.get_mut(id as usize) {
+            Some(Heep { storage: HeepStorage::Owned(raw), live: true }) => raw.realloc(new_size),
+            Some(Heep { storage: HeepStorage::Shared { .. }, live: true }) => Err(TrapKind::ResizeSharedHeep(id)),
+            _ => Err(TrapKind::InvalidHeepId(id)),
         }
     }
 
+    /// 共有heepの場合はこのVMからのmapを外します
+    /// どのVMからもmapされなくなった共有heepは解放されます
     #[inline(always)]
-    pub fn dealloc_heep(&mut self, id: u64) {
-        if let Some(heep) = self.data.get_mut(id as usize) {
-            heep.dealloc();
-            self.reuse_list.push(id as usize);
-        } else {
-            std::process::exit(-9998);
+    pub fn dealloc_heep(&mut self, id: u64) -> Result<(), TrapKind> {
+        match self.data.get_mut(id as usize) {
+            Some(heep) if heep.live => {
+                *heep = Heep::dead();
+                self.reuse_list.push(id as usize);
+                Ok(())
+            }
+            _ => Err(TrapKind::InvalidHeepId(id)),
+        }
+    }
+
+    /// heepをこのVMから取り出します 取り出したidは解放済みになります
+    /// CHAN_SEND_HEEPで他のVMに移すためのもの
+    pub fn take_heep(&mut self, id: u64) -> Result<HeepStorage, TrapKind> {
+        match self.data.get_mut(id as usize) {
+            Some(heep) if heep.live => {
+                let heep = std::mem::replace(heep, Heep::dead());
+                self.reuse_list.push(id as usize);
+                Ok(heep.storage)
+            }
+            _ => Err(TrapKind::InvalidHeepId(id)),
+        }
+    }
+
+    /// `take_heep` で取り出したheepを同じidに戻します
+    pub fn restore_heep(&mut self, id: u64, storage: HeepStorage) {
+        self.reuse_list.retain(|reuse| *reuse != id as usize);
+        self.data[id as usize] = Heep { storage, live: true };
+    }
+
+    /// 他のVMから受け取ったheepを追加してそのid
+    pub fn insert_storage(&mut self, storage: HeepStorage) -> u64 {
+        self.insert_heep(Heep { storage, live: true })
+    }
+
+    /// 新しい共有heepを作ってこのVMにmapします
+    /// 中身は0で初期化されます
+    pub fn alloc_shared_heep(&mut self, size: usize) -> Result<u64, TrapKind> {
+        let (handle, raw) = self.shared.alloc(size)?;
+        Ok(self.insert_heep(Heep::shared(handle, raw)))
+    }
+
+    /// 共有heepのハンドルをこのVMのheep idにmapします
+    /// 同じハンドルを何度mapしても別のidになり、それぞれDEALLOCで外します
+    pub fn map_shared_heep(&mut self, handle: u64) -> Result<u64, TrapKind> {
+        let raw = self.shared.get(handle)?;
+        Ok(self.insert_heep(Heep::shared(handle, raw)))
+    }
+
+    /// mapされた共有heepのハンドル
+    pub fn shared_handle(&self, id: u64) -> Result<u64, TrapKind> {
+        match self.data.get(id as usize) {
+            Some(Heep { storage: HeepStorage::Shared { handle, .. }, live: true }) => Ok(*handle),
+            Some(Heep { live: true, .. }) => Err(TrapKind::NotSharedHeep(id)),
+            _ => Err(TrapKind::InvalidHeepId(id)),
         }
     }
 
+    /// heep内 offset の位置に width byte アクセスするためのアドレス
+    /// `bounds_check` が有効ならheepのサイズとアラインメントを検査します
     #[inline(always)]
-    pub fn head_ptr(&mut self, id: u64) -> usize {
-        if let Some(heep) = self.data.get(id as usize) {
-            let ptr = heep.ptr();
-            ptr
-        } else {
-            std::process::exit(-9998);
+    pub fn heep_addr(&self, id: u64, offset: usize, width: usize) -> Result<usize, TrapKind> {
+        let heep = match self.data.get(id as usize) {
+            Some(heep) if heep.live => heep,
+            _ => return Err(TrapKind::InvalidHeepId(id)),
+        };
+        if self.bounds_check {
+            if offset.checked_add(width).is_none_or(|end| end > heep.size) {
+                return Err(TrapKind::OutOfBounds { id, offset, width, size: heep.size });
+            }
+            // heepの先頭は64byteアラインなのでoffsetだけ見ればよい
+            if !offset.is_multiple_of(width) {
+                return Err(TrapKind::Misaligned { id, offset, align: width });
+            }
+        }
+        Ok(heep.ptr().wrapping_add(offset))
+    }
+
+    /// heepの内容をコピーして返します
+    /// 常に範囲を検査します
+    pub fn read_bytes(&self, id: u64, offset: usize, len: usize) -> Result<Vec<u8>, TrapKind> {
+        let heep = match self.data.get(id as usize) {
+            Some(heep) if heep.live => heep,
+            _ => return Err(TrapKind::InvalidHeepId(id)),
+        };
+        if offset.checked_add(len).is_none_or(|end| end > heep.size) {
+            return Err(TrapKind::OutOfBounds { id, offset, width: len, size: heep.size });
+        }
+        let bytes = unsafe { std::slice::from_raw_parts((heep.ptr() as *const u8).add(offset), len) };
+        Ok(bytes.to_vec())
+    }
+
+    #[inline(always)]
+    pub fn head_ptr(&self, id: u64) -> Result<usize, TrapKind> {
+        match self.data.get(id as usize) {
+            Some(heep) if heep.live => Ok(heep.ptr()),
+            _ => Err(TrapKind::InvalidHeepId(id)),
         }
     }
 }
 
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// VM間で共有するheepの表
+/// ハンドルは表上のindexで、VMはハンドルを自分のheep idにmapして使います
+/// 表は弱参照だけを持つので、heepはmapしているVMがいなくなると解放されます
+pub struct SharedHeeps {
+    heeps: Mutex<Vec<Weak<RawHeep>>>,
+}
+
+impl SharedHeeps {
+    pub fn new() -> Self {
+        SharedHeeps {
+            heeps: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 0で初期化した共有heepとそのハンドル
+    /// 解放済みのハンドルは古い参照と混ざらないよう再利用しません
+    pub fn alloc(&self, size: usize) -> Result<(u64, Arc<RawHeep>), TrapKind> {
+        let raw = Arc::new(RawHeep::new_zeroed(size)?);
+        let mut heeps = self.heeps.lock().unwrap();
+        heeps.push(Arc::downgrade(&raw));
+        Ok(((heeps.len() - 1) as u64, raw))
+    }
+
+    /// ハンドルの共有heep
+    pub fn get(&self, handle: u64) -> Result<Arc<RawHeep>, TrapKind> {
+        self.heeps
+            .lock()
+            .unwrap()
+            .get(handle as usize)
+            .and_then(Weak::upgrade)
+            .ok_or(TrapKind::InvalidSharedHandle(handle))
+    }
+}
+
+impl Default for SharedHeeps {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct Heep {
-    pub raw: RawHeep,
+    pub storage: HeepStorage,
+    /// dealloc済みならfalse
+    pub live: bool,
+}
+
+pub enum HeepStorage {
+    /// このVMだけのheep
+    Owned(RawHeep),
+    /// `SharedHeeps` の共有heep
+    Shared { handle: u64, raw: Arc<RawHeep> },
 }
 
 impl Heep {
     #[inline(always)]
-    pub fn new(size: usize) -> Self {
+    pub fn new(size: usize) -> Result<Self, TrapKind> {
+        Ok(Heep {
+            storage: HeepStorage::Owned(RawHeep::new(size)?),
+            live: true,
+        })
+    }
+
+    fn shared(handle: u64, raw: Arc<RawHeep>) -> Self {
+        Heep {
+            storage: HeepStorage::Shared { handle, raw },
+            live: true,
+        }
+    }
+
+    /// 解放済みのheep 中身は持たない
+    fn dead() -> Self {
         Heep {
-            raw: RawHeep::new(size),
+            storage: HeepStorage::Owned(RawHeep::empty()),
+            live: false,
         }
     }
 
     #[inline(always)]
     pub fn ptr(&self) -> usize {
-        self.raw.ptr() as usize
+        self.deref().ptr() as usize
     }
 }
 
-unsafe impl Send for Heep {}
-unsafe impl Sync for Heep {}
-
 impl Deref for Heep {
     type Target = RawHeep;
 
     #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        &self.raw
-    }
-}
-
-impl DerefMut for Heep {
-    #[inline(always)]
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.raw
+        match &self.storage {
+            HeepStorage::Owned(raw) => raw,
+            HeepStorage::Shared { raw, .. } => raw,
+        }
     }
 }
 
@@ -104,59 +266,78 @@ pub struct RawHeep {
     pub size: usize,
 }
 
+// 共有heepは複数スレッドのVMから触るので、アクセスの同期はスクリプト側 (atomic命令) に任せる
+unsafe impl Send for RawHeep {}
+unsafe impl Sync for RawHeep {}
+
 impl RawHeep {
     const ALIGN: usize = 64;
 
+    /// size 0 でもallocできるように最低1byte確保する
     #[inline(always)]
-    fn new(size: usize) -> Self {
-        let layout = alloc::Layout::from_size_align(size, Self::ALIGN).unwrap();
+    fn layout(size: usize) -> Result<alloc::Layout, TrapKind> {
+        alloc::Layout::from_size_align(size.max(1), Self::ALIGN)
+            .map_err(|_| TrapKind::OutOfMemory { size })
+    }
+
+    #[inline(always)]
+    fn new(size: usize) -> Result<Self, TrapKind> {
+        let layout = Self::layout(size)?;
         let uncheck_ptr = unsafe { alloc::alloc(layout) };
-        if uncheck_ptr.is_null() {
-            oom();
-        }
-        let ptr = unsafe { NonNull::new_unchecked(uncheck_ptr) };
+        let ptr = NonNull::new(uncheck_ptr).ok_or(TrapKind::OutOfMemory { size })?;
 
-        RawHeep { ptr, size }
+        Ok(RawHeep { ptr, size })
     }
 
-    #[inline(always)]
-    fn ptr(&self) -> *mut u8 {
-        self.ptr.as_ptr()
+    fn new_zeroed(size: usize) -> Result<Self, TrapKind> {
+        let layout = Self::layout(size)?;
+        let uncheck_ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(uncheck_ptr).ok_or(TrapKind::OutOfMemory { size })?;
+
+        Ok(RawHeep { ptr, size })
     }
 
-    #[inline(always)]
-    fn alloc(&mut self, size: usize) {
-        let layout = alloc::Layout::from_size_align(size, Self::ALIGN).unwrap();
-        let uncheck_ptr = unsafe { alloc::alloc(layout) };
-        if uncheck_ptr.is_null() {
-            oom();
+    /// 何も確保していないheep
+    fn empty() -> Self {
+        RawHeep {
+            ptr: NonNull::dangling(),
+            size: 0,
         }
-        self.ptr = unsafe { NonNull::new_unchecked(uncheck_ptr) };
-        self.size = size;
     }
 
     #[inline(always)]
-    fn realloc(&mut self, new_size: usize) {
-        let layout = alloc::Layout::from_size_align(self.size, Self::ALIGN).unwrap();
-        let uncheck_ptr = unsafe { alloc::realloc(self.ptr(), layout, new_size) };
-        if uncheck_ptr.is_null() {
-            oom();
-        }
-        self.ptr = unsafe { NonNull::new_unchecked(uncheck_ptr) };
+    fn ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    #[inline(always)]
+    fn realloc(&mut self, new_size: usize) -> Result<(), TrapKind> {
+        let layout = Self::layout(self.size)?;
+        Self::layout(new_size)?;
+        let uncheck_ptr = unsafe { alloc::realloc(self.ptr(), layout, new_size.max(1)) };
+        self.ptr = NonNull::new(uncheck_ptr).ok_or(TrapKind::OutOfMemory { size: new_size })?;
         self.size = new_size;
+        Ok(())
     }
 
+    /// 解放後はダングリングポインタにしておき、二重解放を防ぐ
     #[inline(always)]
     fn dealloc(&mut self) {
-        let layout = alloc::Layout::from_size_align(self.size, Self::ALIGN).unwrap();
+        if self.ptr == NonNull::dangling() {
+            return;
+        }
+        let layout = Self::layout(self.size).unwrap();
         unsafe {
             alloc::dealloc(self.ptr(), layout);
         }
+        self.ptr = NonNull::dangling();
+        self.size = 0;
     }
 
     #[inline(always)]
     fn deep_copy(&self) -> Self {
-        let new_struct = RawHeep::new(self.size);
+        let layout = Self::layout(self.size).unwrap();
+        let new_struct = RawHeep::new(self.size).unwrap_or_else(|_| alloc::handle_alloc_error(layout));
         unsafe {
             std::ptr::copy_nonoverlapping(self.ptr(), new_struct.ptr(), self.size);
         }
@@ -177,8 +358,3 @@ impl Drop for RawHeep {
         self.dealloc();
     }
 }
-
-#[cold]
-fn oom() {
-    ::std::process::exit(-9999);
-}
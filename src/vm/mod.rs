@@ -1,28 +1,46 @@
 use std::{
-    path::PathBuf, sync::{Arc, RwLock}, thread::{self, JoinHandle}
+    path::PathBuf, sync::{Arc, RwLock}
 };
 
-use crate::vm::{code_manager::CodeManager, vm::VM};
+use crate::vm::{channel::Channels, code_manager::CodeManager, memory::SharedHeeps, native::{DuplicateNative, NativeFunction}, threads::{VmResult, VmThreads}, vm::VM};
 
+pub mod channel;
 pub mod code_manager;
+pub mod coroutine;
 pub mod memory;
 pub mod operations;
 pub mod pre_decoder;
+pub mod bytecode;
+pub mod debugger;
+pub mod diagnostic;
+pub mod disassembler;
+pub mod executor;
+#[allow(clippy::module_inception)]
 pub mod vm;
 pub mod function;
+pub mod native;
+pub mod threads;
+pub mod trap;
+pub mod verifier;
 
 pub struct VMPool {
-    pub vms: Vec<Arc<RwLock<VM>>>,
-    handles: Vec<JoinHandle<()>>,
+    /// 起動したVMとスレッド スクリプトのSPAWNで増えることもあります
+    pub threads: Arc<VmThreads>,
     pub code_manager: CodeManager,
+    /// プール内のVMで共有するheepの表
+    /// ホスト側で確保したハンドルをレジスタに入れて渡すこともできます
+    pub shared: Arc<SharedHeeps>,
+    /// プール内のVMで共有するチャネルの表
+    pub channels: Arc<Channels>,
 }
 
 impl VMPool {
     pub fn new() -> Self {
         VMPool {
-            vms: Vec::new(),
-            handles: Vec::new(),
+            threads: Arc::new(VmThreads::new()),
             code_manager: CodeManager::new("none".into()),
+            shared: Arc::new(SharedHeeps::new()),
+            channels: Arc::new(Channels::new()),
         }
     }
 
@@ -30,6 +48,13 @@ impl VMPool {
         self.code_manager = CodeManager::new(PathBuf::from(path));
     }
 
+    /// ホスト関数を登録します
+    /// スクリプトからは `NATIVE_CALL 名前 r_ret r_arg 引数の個数` で呼び出せます
+    /// 同名の関数は登録できません
+    pub fn register_native(&self, name: &str, function: NativeFunction) -> Result<usize, DuplicateNative> {
+        self.code_manager.register_native(name, function)
+    }
+
     pub fn run(&mut self) {
         let vm = VM::new();
         self.push_and_run_threaded(vm,false);
@@ -40,30 +65,242 @@ impl VMPool {
         self.push_and_run_threaded(vm,true);
     }
 
-    pub fn push_and_run_threaded(&mut self, mut vm: VM, use_core_affinity: bool) {
-        let index = self.vms.len();
-        vm.vm_id = index as u64;
+    /// VMをスレッドで起動してそのvm_id
+    pub fn push_and_run_threaded(&mut self, mut vm: VM, use_core_affinity: bool) -> u64 {
         vm.cm = self.code_manager.clone_shared();
-        let vm_arc = Arc::new(RwLock::new(vm));
-        self.vms.push(vm_arc.clone());
-
-        let handle = thread::spawn(move || {
-            if use_core_affinity {
-                if let Some(cores) = core_affinity::get_core_ids() {
-                    let core = &cores[index % cores.len()];
-                    core_affinity::set_for_current(*core);
-                }
-            }
-            let mut vm = vm_arc.write().unwrap();
-            vm.run();
-        });
-
-        self.handles.push(handle);
-    }
-
-    pub fn wait_all(&mut self) {
-        while let Some(handle) = self.handles.pop() {
-            handle.join().unwrap();
+        vm.st.mem.shared = self.shared.clone();
+        vm.channels = self.channels.clone();
+        self.threads.spawn(vm, use_core_affinity)
+    }
+
+    /// 起動したすべてのVM (vm_id順)
+    pub fn vms(&self) -> Vec<Arc<RwLock<VM>>> {
+        self.threads.vms()
+    }
+
+    /// すべてのVMの終了を待ち、vm_id順に終了コード・trap・レジスタを返します
+    /// 実行中にSPAWNされたVMも含みます
+    pub fn wait_all(&mut self) -> Vec<VmResult> {
+        self.threads.wait_all()
+    }
+
+    /// VMの終了を待って結果を返します 存在しないvm_idならNone
+    pub fn join(&self, vm_id: u64) -> Option<VmResult> {
+        self.threads.join(vm_id)
+    }
+}
+
+impl Default for VMPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vms_share_heeps_through_handles() {
+        let source = r#"
+MAIN
+SHARED_MAP r2 r1
+LOAD_U64_IMMEDIATE r3 1
+LOAD_U64_IMMEDIATE r4 1000
+.loop:
+ATOMIC_ADD_U64.RELAXED r254 r2 r0 r3 0
+SUB_U64_IMMEDIATE r4 1
+NEQ_JUMP r0 r4 r0 .loop
+DEALLOC r2
+EXIT 0
+"#;
+        let mut pool = VMPool::new();
+        pool.code_manager.load_source("main".into(), source).unwrap();
+        let (handle, counter) = pool.shared.alloc(8).unwrap();
+        for _ in 0..4 {
+            let mut vm = VM::new();
+            vm.st.r[1] = handle;
+            pool.push_and_run_threaded(vm, false);
+        }
+        pool.wait_all();
+
+        let total = unsafe { *(counter.ptr.as_ptr() as *const u64) };
+        assert_eq!(total, 4000);
+        for vm in pool.vms() {
+            assert!(vm.read().unwrap().st.trap.is_none());
+        }
+
+        // どこからもmapされなくなると解放される
+        drop(counter);
+        assert!(matches!(pool.shared.get(handle), Err(trap::TrapKind::InvalidSharedHandle(_))));
+    }
+
+    #[test]
+    fn vms_exchange_values_and_heeps_over_channels() {
+        let source = r#"
+MAIN
+NEQ_JUMP r0 r2 r0 .consumer
+; producer: heepを1つ送ってから 1..=100 を送って閉じる
+ALLOC r0 r3 8
+LOAD_U64_IMMEDIATE r4 42
+STORE_U64 r3 r0 r4 0
+CHAN_SEND_HEEP r5 r1 r3
+LOAD_U64_IMMEDIATE r4 100
+.send:
+CHAN_SEND r5 r1 r4
+SUB_U64_IMMEDIATE r4 1
+NEQ_JUMP r0 r4 r0 .send
+CHAN_CLOSE r1
+EXIT 0
+.consumer:
+CHAN_RECV r5 r3 r1
+LOAD_U64 r3 r0 r6 0
+.recv:
+CHAN_RECV r5 r4 r1
+NEQ_JUMP r0 r5 r0 .done
+ADD_U64 r7 r4
+JUMP r0 .recv
+.done:
+EXIT 0
+"#;
+        let mut pool = VMPool::new();
+        pool.code_manager.load_source("main".into(), source).unwrap();
+        let channel = pool.channels.create(4);
+        for role in 0..2 {
+            let mut vm = VM::new();
+            vm.st.r[1] = channel;
+            vm.st.r[2] = role;
+            pool.push_and_run_threaded(vm, false);
+        }
+        pool.wait_all();
+
+        let consumer = pool.threads.vm(1).unwrap();
+        let consumer = consumer.read().unwrap();
+        assert!(consumer.st.trap.is_none());
+        assert_eq!(consumer.st.r[6], 42);
+        assert_eq!(consumer.st.r[7], 5050);
+        assert_eq!(consumer.st.r[5], channel::status::CLOSED);
+    }
+
+
+    #[test]
+    fn scripts_fork_and_join_workers() {
+        let source = r#"
+MAIN
+VM_ID r10
+LOAD_U64_IMMEDIATE r1 100
+LOAD_U64_IMMEDIATE r2 200
+SPAWN SUM r3 r1 1
+SPAWN SUM r4 r2 1
+SPAWN FAIL r5 r0 0
+JOIN r6 r3
+JOIN r7 r4
+JOIN r8 r5
+; 同じVMを2回JOINしても同じ値
+JOIN r9 r3
+EXIT 0
+
+SUM
+VM_ID r3
+.loop:
+ADD_U64 r2 r1
+SUB_U64_IMMEDIATE r1 1
+NEQ_JUMP r0 r1 r0 .loop
+EXIT r2
+
+FAIL
+LOAD_U64_IMMEDIATE r1 1
+DIV_U64 r1 r0
+EXIT 0
+"#;
+        let mut pool = VMPool::new();
+        pool.code_manager.load_source("main".into(), source).unwrap();
+        pool.run();
+        let results = pool.wait_all();
+
+        assert_eq!(results.len(), 4);
+        let main = &results[0].registers;
+        assert_eq!(main[10], 0);
+        assert_eq!((main[3], main[4], main[5]), (1, 2, 3));
+        assert_eq!((main[6], main[7], main[9]), (5050, 20100, 5050));
+        assert_eq!(main[8], threads::TRAPPED_EXIT_CODE);
+        assert_eq!(results[2].registers[3], 2);
+
+        // 終了コードとtrapも残る
+        assert_eq!((results[1].vm_id, results[1].exit_code), (1, 5050));
+        assert!(results[1].trap.is_none() && results[1].backtrace.is_empty());
+        assert_eq!(results[3].trap.as_ref().unwrap().kind, trap::TrapKind::DivisionByZero);
+        assert_eq!(results[3].backtrace[0].to_string(), "FAIL:1 (main:26)");
+        assert_eq!(pool.join(2).unwrap().exit_code, 20100);
+        assert!(pool.join(4).is_none());
+    }
+
+    #[test]
+    fn panicking_vm_is_joined_as_trapped() {
+        fn boom(_: &mut VM, _: &[u64]) -> Result<u64, trap::TrapKind> {
+            panic!("boom");
+        }
+
+        let mut pool = VMPool::new();
+        pool.register_native("BOOM", boom).unwrap();
+        pool.code_manager
+            .load_source("main".into(), "MAIN\nSPAWN BAD r1 r0 0\nJOIN r2 r1\nEXIT 0\n\nBAD\nNATIVE_CALL BOOM r1 r0 0\nEXIT 0\n")
+            .unwrap();
+        pool.run();
+        let results = pool.wait_all();
+
+        assert_eq!(results[0].registers[2], threads::TRAPPED_EXIT_CODE);
+        assert_eq!(results[1].exit_code, threads::TRAPPED_EXIT_CODE);
+        let trap = results[1].trap.as_ref().unwrap();
+        assert_eq!((&trap.kind, trap.pc), (&trap::TrapKind::Panicked("boom".into()), 0));
+    }
+
+    #[test]
+    fn async_native_call_traps_in_pool() {
+        fn start_io(_: &mut VM, _: &[u64]) -> Result<u64, trap::TrapKind> {
+            Ok(1)
         }
+
+        let mut pool = VMPool::new();
+        pool.register_native("START_IO", start_io).unwrap();
+        pool.code_manager
+            .load_source("main".into(), "MAIN\nNATIVE_CALL_ASYNC START_IO r1 r0 0\nEXIT 0\n")
+            .unwrap();
+        pool.run();
+        let results = pool.wait_all();
+
+        assert_eq!(results[0].exit_code, threads::TRAPPED_EXIT_CODE);
+        let trap = results[0].trap.as_ref().unwrap();
+        assert_eq!((&trap.kind, trap.pc), (&trap::TrapKind::NotInExecutor, 0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn join_cycle_traps_instead_of_deadlocking() {
+        let source = r#"
+MAIN
+VM_ID r1
+SPAWN WAIT r2 r1 1
+JOIN r3 r2
+EXIT 0
+
+WAIT
+JOIN r2 r1
+EXIT 0
+"#;
+        let mut pool = VMPool::new();
+        pool.code_manager.load_source("main".into(), source).unwrap();
+        pool.run();
+        let results = pool.wait_all();
+
+        // 後からJOINした方だけがtrapする
+        let cycles = results
+            .iter()
+            .filter(|result| matches!(result.trap.as_ref().map(|trap| &trap.kind), Some(trap::TrapKind::JoinCycle(_))))
+            .count();
+        assert_eq!(cycles, 1);
+        // もう一方はtrapしたVMの終了コードを受け取って終わる
+        let other = results.iter().find(|result| result.trap.is_none()).unwrap();
+        let joined = if other.vm_id == 0 { other.registers[3] } else { other.registers[2] };
+        assert_eq!(joined, threads::TRAPPED_EXIT_CODE);
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+
+use rustc_hash::FxHashMap;
+
+use crate::vm::{trap::TrapKind, vm::VM};
+
+/// ホスト関数
+/// 引数レジスタの値を受け取り、戻り値は結果レジスタに書き込まれます
+/// Errを返すとそのVMはtrapします
+pub type NativeFunction = fn(&mut VM, args: &[u64]) -> Result<u64, TrapKind>;
+
+/// ホスト関数の登録表
+/// NATIVE_CALLの関数名はデコード時にここのindexへ解決されます
+pub struct NativeRegistry {
+    functions: Vec<NativeFunction>,
+    names: Vec<String>,
+    name_to_index: FxHashMap<String, usize>,
+}
+
+impl NativeRegistry {
+    pub fn new() -> Self {
+        NativeRegistry {
+            functions: Vec::new(),
+            names: Vec::new(),
+            name_to_index: FxHashMap::default(),
+        }
+    }
+
+    /// ホスト関数を登録してindexを返します
+    /// VMは登録表のスナップショットを持つので差し替えはできず、同名の関数はエラー
+    pub fn register(&mut self, name: &str, function: NativeFunction) -> Result<usize, DuplicateNative> {
+        let name = name.to_ascii_uppercase();
+        if self.name_to_index.contains_key(&name) {
+            return Err(DuplicateNative(name));
+        }
+        let index = self.functions.len();
+        self.functions.push(function);
+        self.names.push(name.clone());
+        self.name_to_index.insert(name, index);
+        Ok(index)
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.name_to_index.get(name).copied()
+    }
+
+    pub fn get(&self, index: usize) -> Option<NativeFunction> {
+        self.functions.get(index).copied()
+    }
+
+    /// index順の関数名
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// VMが保持するためのスナップショット
+    pub fn functions(&self) -> Box<[NativeFunction]> {
+        self.functions.clone().into_boxed_slice()
+    }
+}
+
+impl Default for NativeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 既に登録されているホスト関数名 (大文字化済み)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateNative(pub String);
+
+impl fmt::Display for DuplicateNative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "native function '{}' is already registered", self.0)
+    }
+}
+
+impl std::error::Error for DuplicateNative {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{
+        bytecode,
+        code_manager::{CodeLoadError, CodeManager},
+        diagnostic::Diagnostic,
+        pre_decoder::PreDecodeError,
+        vm::RunStatus,
+    };
+
+    const SOURCE: &str = r#"
+MAIN
+LOAD_U64_IMMEDIATE r2 40
+LOAD_U64_IMMEDIATE r3 2
+NATIVE_CALL add r1 r2 2
+NATIVE_CALL fail r254 r0 0
+EXIT 0
+"#;
+
+    fn add(_: &mut VM, args: &[u64]) -> Result<u64, TrapKind> {
+        Ok(args.iter().sum())
+    }
+
+    fn fail(_: &mut VM, _: &[u64]) -> Result<u64, TrapKind> {
+        Err(TrapKind::Native("host refused".into()))
+    }
+
+    fn run(cm: CodeManager) -> (VM, Result<RunStatus, crate::vm::trap::VmTrap>) {
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm);
+        let result = vm.run();
+        (vm, result)
+    }
+
+    #[test]
+    fn native_call_passes_arguments_and_traps_on_error() {
+        let cm = CodeManager::new("test".into());
+        cm.register_native("unused", fail).unwrap();
+        cm.register_native("ADD", add).unwrap();
+        cm.register_native("FAIL", fail).unwrap();
+        cm.load_source("test".into(), SOURCE).expect("load succeeds");
+
+        let (vm, result) = run(cm);
+        assert_eq!(vm.st.r[1], 42);
+        let trap = result.unwrap_err();
+        assert_eq!((trap.kind, trap.pc), (TrapKind::Native("host refused".into()), 3));
+    }
+
+    #[test]
+    fn duplicate_native_is_rejected() {
+        let cm = CodeManager::new("test".into());
+        assert_eq!(cm.register_native("ADD", add), Ok(0));
+        // 大文字小文字は区別しない
+        assert_eq!(cm.register_native("add", fail), Err(DuplicateNative("ADD".into())));
+        cm.register_native("FAIL", fail).unwrap();
+        cm.load_source("test".into(), SOURCE).expect("load succeeds");
+
+        // 元の関数のまま呼ばれる
+        let (vm, _) = run(cm);
+        assert_eq!(vm.st.r[1], 42);
+    }
+
+    #[test]
+    fn unregistered_native_fails_to_decode() {
+        let cm = CodeManager::new("test".into());
+        cm.register_native("ADD", add).unwrap();
+        let err = cm.load_source("test".into(), SOURCE).unwrap_err();
+        let CodeLoadError::Diagnostics(diagnostics) = err else {
+            panic!("expected diagnostics, got {err}");
+        };
+        assert!(matches!(
+            &diagnostics[..],
+            [Diagnostic { error: PreDecodeError::UnknownNative { name, line: 6 }, .. }] if name == "FAIL"
+        ));
+    }
+
+    #[test]
+    fn bytecode_relocates_native_indices_by_name() {
+        let mut natives = NativeRegistry::new();
+        natives.register("FAIL", fail).unwrap();
+        natives.register("ADD", add).unwrap();
+        let bytes = bytecode::compile_with_natives(SOURCE, false, &natives).expect("compile succeeds");
+
+        // 登録順が違っても名前で解決される
+        let cm = CodeManager::new("test".into());
+        cm.register_native("ADD", add).unwrap();
+        cm.register_native("FAIL", fail).unwrap();
+        cm.load_bytecode("test.bin".into(), bytes.clone().into()).expect("load succeeds");
+        let (vm, result) = run(cm);
+        assert_eq!(vm.st.r[1], 42);
+        assert_eq!(result.unwrap_err().kind, TrapKind::Native("host refused".into()));
+
+        let cm = CodeManager::new("test".into());
+        cm.register_native("ADD", add).unwrap();
+        assert!(cm.load_bytecode("test.bin".into(), bytes.into()).is_err());
+    }
+}
@@ -1,6 +1,6 @@
-use std::sync::atomic::{AtomicU8, AtomicU16, AtomicU32, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicI8, AtomicI16, AtomicI32, AtomicI64, AtomicU8, AtomicU16, AtomicU32, AtomicU64, Ordering};
 
-use crate::vm::{VM, vm::state_flag};
+use crate::vm::{VM, function::FunctionPtr, channel::{Message, status}, coroutine, trap::TrapKind, vm::{Frame, Pending, state_flag}};
 
 pub struct Operations;
 
@@ -158,6 +158,235 @@ pub enum Instruction {
     // Special control / code management
     GetDecode(u64, u64),
     GetDecoded(u64, u64),
+
+    // Host functions
+    NativeCall(u64, u64),
+
+    // Calling convention
+    CallArgs(u64, u64),
+    RetValue(u64, u64),
+
+    // Indirect calls
+    CallReg(u64, u64),
+    CallRegArgs(u64, u64),
+    LoadFuncIndex(u64, u64),
+
+    // Float jumps
+    LtF64Jump(u64, u64),
+    LteF64Jump(u64, u64),
+    GtF64Jump(u64, u64),
+    GteF64Jump(u64, u64),
+    EqF64Jump(u64, u64),
+    UnorderedF64Jump(u64, u64),
+
+    // Compare and set / conditional move
+    SetEq(u64, u64),
+    SetNeq(u64, u64),
+    SetLtU64(u64, u64),
+    SetLteU64(u64, u64),
+    SetLtI64(u64, u64),
+    SetLteI64(u64, u64),
+    SetGtU64(u64, u64),
+    SetGteU64(u64, u64),
+    SetGtI64(u64, u64),
+    SetGteI64(u64, u64),
+    SetLtF64(u64, u64),
+    SetLteF64(u64, u64),
+    SetGtF64(u64, u64),
+    SetGteF64(u64, u64),
+    SetEqF64(u64, u64),
+    SetUnorderedF64(u64, u64),
+    CmovNz(u64, u64),
+    CmovZ(u64, u64),
+
+    // Atomic read-modify-write
+    AtomicCasU64(u64, u64),
+    AtomicCasU32(u64, u64),
+    AtomicCasU16(u64, u64),
+    AtomicCasU8(u64, u64),
+    AtomicSwapU64(u64, u64),
+    AtomicSwapU32(u64, u64),
+    AtomicSwapU16(u64, u64),
+    AtomicSwapU8(u64, u64),
+    AtomicAndU64(u64, u64),
+    AtomicAndU32(u64, u64),
+    AtomicAndU16(u64, u64),
+    AtomicAndU8(u64, u64),
+    AtomicOrU64(u64, u64),
+    AtomicOrU32(u64, u64),
+    AtomicOrU16(u64, u64),
+    AtomicOrU8(u64, u64),
+    AtomicXorU64(u64, u64),
+    AtomicXorU32(u64, u64),
+    AtomicXorU16(u64, u64),
+    AtomicXorU8(u64, u64),
+    AtomicMinU64(u64, u64),
+    AtomicMinU32(u64, u64),
+    AtomicMinU16(u64, u64),
+    AtomicMinU8(u64, u64),
+    AtomicMaxU64(u64, u64),
+    AtomicMaxU32(u64, u64),
+    AtomicMaxU16(u64, u64),
+    AtomicMaxU8(u64, u64),
+    AtomicMinI64(u64, u64),
+    AtomicMinI32(u64, u64),
+    AtomicMinI16(u64, u64),
+    AtomicMinI8(u64, u64),
+    AtomicMaxI64(u64, u64),
+    AtomicMaxI32(u64, u64),
+    AtomicMaxI16(u64, u64),
+    AtomicMaxI8(u64, u64),
+
+    // Memory fence
+    Fence(u64, u64),
+
+    // Shared heep
+    SharedAlloc(u64, u64),
+    SharedMap(u64, u64),
+    SharedHandle(u64, u64),
+
+    // Channel
+    ChanNew(u64, u64),
+    ChanSend(u64, u64),
+    ChanTrySend(u64, u64),
+    ChanSendHeep(u64, u64),
+    ChanTrySendHeep(u64, u64),
+    ChanRecv(u64, u64),
+    ChanTryRecv(u64, u64),
+    ChanClose(u64, u64),
+
+    // VM
+    Spawn(u64, u64),
+    Join(u64, u64),
+    VmId(u64, u64),
+
+    // Coroutine
+    CoCreate(u64, u64),
+    CoResume(u64, u64),
+    Yield(u64, u64),
+
+    // Async host call
+    NativeCallAsync(u64, u64),
+}
+
+/// `#[repr(u8)]` の列挙型は 判別子 + フィールド の `#[repr(C)]` 構造体と同じレイアウトになる
+#[repr(C)]
+struct RawInstruction {
+    opcode: u8,
+    a: u64,
+    b: u64,
+}
+
+impl Instruction {
+    /// 判別子
+    #[inline(always)]
+    pub fn opcode(&self) -> u8 {
+        unsafe { (*(self as *const Instruction as *const RawInstruction)).opcode }
+    }
+
+    /// 引数 (a, b)
+    #[inline(always)]
+    pub fn operands(&self) -> (u64, u64) {
+        let raw = unsafe { &*(self as *const Instruction as *const RawInstruction) };
+        (raw.a, raw.b)
+    }
+}
+
+/// atomic命令とFENCEのメモリオーダリング
+/// パックされたレジスタ引数の最上位byteに入ります 0 (指定なし) はSeqCst
+/// テキストでは `ATOMIC_ADD_U64.RELAXED` のようにオペコードの後ろに付けます
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MemoryOrdering {
+    SeqCst = 0,
+    Relaxed = 1,
+    Acquire = 2,
+    Release = 3,
+    AcqRel = 4,
+}
+
+impl MemoryOrdering {
+    /// 引数aの中での位置
+    pub const SHIFT: u32 = 56;
+    pub const ALL: [MemoryOrdering; 5] = [
+        MemoryOrdering::SeqCst,
+        MemoryOrdering::Relaxed,
+        MemoryOrdering::Acquire,
+        MemoryOrdering::Release,
+        MemoryOrdering::AcqRel,
+    ];
+
+    /// 引数aの最上位byteから取り出します
+    pub fn from_operand(a: u64) -> Option<Self> {
+        Self::ALL.get((a >> Self::SHIFT) as usize).copied()
+    }
+
+    /// 引数aに重ねるビット
+    pub fn operand(self) -> u64 {
+        (self as u64) << Self::SHIFT
+    }
+
+    /// オペコードの後ろに付ける名前
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryOrdering::SeqCst => "SEQCST",
+            MemoryOrdering::Relaxed => "RELAXED",
+            MemoryOrdering::Acquire => "ACQUIRE",
+            MemoryOrdering::Release => "RELEASE",
+            MemoryOrdering::AcqRel => "ACQREL",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ordering| ordering.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// 引数aに入っているオーダリング
+/// 読み込みだけ/書き込みだけの命令で使えない組み合わせは検証で弾くので、ここではSeqCstに倒します
+#[inline(always)]
+fn ordering(a: u64) -> Ordering {
+    match a >> MemoryOrdering::SHIFT {
+        1 => Ordering::Relaxed,
+        2 => Ordering::Acquire,
+        3 => Ordering::Release,
+        4 => Ordering::AcqRel,
+        _ => Ordering::SeqCst,
+    }
+}
+
+#[inline(always)]
+fn load_ordering(a: u64) -> Ordering {
+    match ordering(a) {
+        Ordering::Release | Ordering::AcqRel => Ordering::SeqCst,
+        ordering => ordering,
+    }
+}
+
+#[inline(always)]
+fn store_ordering(a: u64) -> Ordering {
+    match ordering(a) {
+        Ordering::Acquire | Ordering::AcqRel => Ordering::SeqCst,
+        ordering => ordering,
+    }
+}
+
+/// CASが失敗したときのオーダリング (書き込みがないのでReleaseを外す)
+#[inline(always)]
+fn failure_ordering(a: u64) -> Ordering {
+    match ordering(a) {
+        Ordering::Release => Ordering::Relaxed,
+        Ordering::AcqRel => Ordering::Acquire,
+        ordering => ordering,
+    }
+}
+
+#[inline(always)]
+fn fence_ordering(a: u64) -> Ordering {
+    match ordering(a) {
+        Ordering::Relaxed => Ordering::SeqCst,
+        ordering => ordering,
+    }
 }
 
 impl Instruction {
@@ -385,7 +614,15 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    *r.add(dst as usize) = *r.add(dst as usize) / *r.add(src as usize);
+                    let divisor = *r.add(src as usize);
+
+                    if divisor == 0 {
+
+                        return vm.trap(TrapKind::DivisionByZero);
+
+                    }
+
+                    *r.add(dst as usize) = *r.add(dst as usize) / divisor;
 
                 }
 
@@ -398,6 +635,12 @@ impl Instruction {
 
                 let imm = *b;
 
+                if imm == 0 {
+
+                    return vm.trap(TrapKind::DivisionByZero);
+
+                }
+
                 unsafe {
 
                     let r = vm.st.r.as_mut_ptr();
@@ -419,9 +662,15 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    *r.add(dst as usize) =
+                    let divisor = *r.add(src as usize) as i64;
+
+                    if divisor == 0 {
 
-                        (*r.add(dst as usize) as i64 / *r.add(src as usize) as i64) as u64;
+                        return vm.trap(TrapKind::DivisionByZero);
+
+                    }
+
+                    *r.add(dst as usize) = (*r.add(dst as usize) as i64).wrapping_div(divisor) as u64;
 
                 }
 
@@ -434,11 +683,17 @@ impl Instruction {
 
                 let imm = *b;
 
+                if imm == 0 {
+
+                    return vm.trap(TrapKind::DivisionByZero);
+
+                }
+
                 unsafe {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    *r.add(dst as usize) = (*r.add(dst as usize) as i64 / imm as i64) as u64;
+                    *r.add(dst as usize) = (*r.add(dst as usize) as i64).wrapping_div(imm as i64) as u64;
 
                 }
 
@@ -472,9 +727,15 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    *r.add(dst as usize) =
+                    let divisor = *r.add(src as usize) as i64;
+
+                    if divisor == 0 {
+
+                        return vm.trap(TrapKind::DivisionByZero);
+
+                    }
 
-                        ((*r.add(dst as usize) as i64).wrapping_rem(*r.add(src as usize) as i64)) as u64;
+                    *r.add(dst as usize) = ((*r.add(dst as usize) as i64).wrapping_rem(divisor)) as u64;
 
                 }
 
@@ -508,7 +769,7 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    *r.add(dst as usize) = f64::to_bits(*r.add(src as usize) as u64 as f64);
+                    *r.add(dst as usize) = f64::to_bits(*r.add(src as usize) as f64);
 
                 }
 
@@ -1312,25 +1573,20 @@ impl Instruction {
 
             Instruction::Jump(a, b) => {
 
-
                 let dst = *a;
 
-
                 let offset = *b;
 
-
                 unsafe {
 
-
                     let r = vm.st.r.as_mut_ptr();
 
+                    let addr = (*r.add(dst as usize)).wrapping_add(offset) as usize;
 
-                    vm.st.pc = (*r.add(dst as usize)).wrapping_add(offset) as usize;
-
+                    Operations::jump_to(vm, dst as usize, addr);
 
                 }
 
-
             },
             Instruction::EqJump(a, b) => {
 
@@ -1352,7 +1608,7 @@ impl Instruction {
 
                     if *r.add(a) == *r.add(b) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1383,7 +1639,7 @@ impl Instruction {
 
                     if *r.add(a) != *r.add(b) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1414,7 +1670,7 @@ impl Instruction {
 
                     if *r.add(a) < *r.add(b) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1445,7 +1701,7 @@ impl Instruction {
 
                     if *r.add(a) <= *r.add(b) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1476,7 +1732,7 @@ impl Instruction {
 
                     if (*r.add(a) as i64) < (*r.add(b) as i64) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1507,7 +1763,7 @@ impl Instruction {
 
                     if (*r.add(a) as i64) <= (*r.add(b) as i64) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1538,7 +1794,7 @@ impl Instruction {
 
                     if *r.add(a) > *r.add(b) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1569,7 +1825,7 @@ impl Instruction {
 
                     if *r.add(a) >= *r.add(b) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1600,7 +1856,7 @@ impl Instruction {
 
                     if (*r.add(a) as i64) > (*r.add(b) as i64) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1631,7 +1887,7 @@ impl Instruction {
 
                     if (*r.add(a) as i64) >= (*r.add(b) as i64) {
 
-                        vm.st.pc = addr;
+                        Operations::jump_to(vm, addr_reg, addr);
 
                     } else {
 
@@ -1648,15 +1904,41 @@ impl Instruction {
 
                 let pc = *b;
 
+                let Some(mut function_ptr) = vm.function_ptr(func_index) else {
+
+                    return vm.trap(TrapKind::BadFunctionIndex(func_index));
+
+                };
+
+                if pc as usize >= function_ptr.instructions.len() {
+
+                    let Some(decoded) = Operations::decode_before_entry(vm, func_index, function_ptr) else {
+
+                        return;
+
+                    };
+
+                    // 呼び出し先のpcは検証できないので、ここで範囲を検査する
+
+                    if pc as usize >= decoded.instructions.len() {
+
+                        return vm.bad_jump_target(pc as usize);
+
+                    }
+
+                    function_ptr = decoded;
+
+                }
+
                 vm.st.call_stack.push(vm.st.pc);
 
                 vm.st.call_stack.push(vm.st.now_call_index);
 
                 vm.st.pc = pc as usize;
 
-                vm.st.now_call_index = func_index as usize; 
+                vm.st.now_call_index = func_index as usize;
 
-                vm.st.now_function_ptr = vm.function_table[vm.st.now_call_index];
+                vm.st.now_function_ptr = function_ptr;
 
             },
             Instruction::Ret(a, b) => {
@@ -1665,7 +1947,23 @@ impl Instruction {
 
                 let _ = *b;
 
-                vm.st.now_call_index = vm.st.call_stack.pop().expect("Call stack underflow on return");
+                if vm.st.call_stack.len() < 2 {
+
+                    return Operations::finish_coroutine(vm, 0);
+
+                }
+
+                if let Some(frame) = vm.st.frames.last()
+
+                    && frame.depth + 2 == vm.st.call_stack.len()
+
+                {
+
+                    vm.st.r = vm.st.frames.pop().unwrap().regs;
+
+                }
+
+                vm.st.now_call_index = vm.st.call_stack.pop().unwrap();
 
                 vm.st.pc = vm.st.call_stack.pop().unwrap() + 1;
 
@@ -1714,9 +2012,13 @@ impl Instruction {
 
                     let size = (*r.add(size_reg)).wrapping_add(add_size) as usize;
 
-                    let id = vm.st.mem.alloc_heep(size);
+                    match vm.st.mem.alloc_heep(size) {
 
-                    *r.add(id_res_reg) = id;
+                        Ok(id) => *r.add(id_res_reg) = id,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    }
 
                 }
 
@@ -1737,7 +2039,11 @@ impl Instruction {
 
                     let id = *r.add(id as usize);
 
-                    vm.st.mem.realloc_heep(id, size);
+                    if let Err(kind) = vm.st.mem.realloc_heep(id, size) {
+
+                        return vm.trap(kind);
+
+                    }
 
                 }
 
@@ -1756,7 +2062,11 @@ impl Instruction {
 
                     let id = *r.add(id as usize);
 
-                    vm.st.mem.dealloc_heep(id);
+                    if let Err(kind) = vm.st.mem.dealloc_heep(id) {
+
+                        return vm.trap(kind);
+
+                    }
 
                 }
 
@@ -1775,7 +2085,7 @@ impl Instruction {
 
                     let code = *r.add(code_reg as usize);
 
-                    vm.st.r[0] = code; // return code
+                    vm.st.exit_code = code; // r0は固定値なので別に持つ
 
                     vm.st.state_flag |= state_flag::PAUSE;
 
@@ -1785,43 +2095,34 @@ impl Instruction {
 
             Instruction::LoadU64(a, b) => {
 
-
                 let idr_ptr_res = *a;
 
-
                 let offset = *b;
 
-
                 let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
 
-
                 let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
 
-
                 let result_reg = (idr_ptr_res & 0xFF) as usize;
 
-
                 unsafe {
 
-
                     let r = vm.st.r.as_mut_ptr();
 
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                        Ok(addr) => addr,
 
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-
+                    };
 
                     *r.add(result_reg) = *(addr as *const u64);
 
-
                 }
 
-
                 vm.st.pc += 1; // fallthrough
 
-
             },
             Instruction::LoadU32(a, b) => {
 
@@ -1839,9 +2140,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     *r.add(result_reg) = *(addr as *const u32) as u64;
 
@@ -1866,9 +2171,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     *r.add(result_reg) = *(addr as *const u16) as u64;
 
@@ -1893,9 +2202,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     *r.add(result_reg) = *(addr as *const u8) as u64;
 
@@ -1920,9 +2233,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     *(addr as *mut u64) = *r.add(src_reg);
 
@@ -1947,9 +2264,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     *(addr as *mut u32) = *r.add(src_reg) as u32;
 
@@ -1974,9 +2295,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     *(addr as *mut u16) = *r.add(src_reg) as u16;
 
@@ -2001,9 +2326,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     *(addr as *mut u8) = *r.add(src_reg) as u8;
 
@@ -2015,46 +2344,36 @@ impl Instruction {
 
             Instruction::AtomicLoadU64(a, b) => {
 
-
                 let idr_ptr_res = *a;
 
-
                 let offset = *b;
 
-
                 let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
 
-
                 let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
 
-
                 let result_reg = (idr_ptr_res & 0xFF) as usize;
 
-
                 unsafe {
 
-
                     let r = vm.st.r.as_mut_ptr();
 
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
 
+                    };
 
                     let atomic_ptr = addr as *const AtomicU64;
 
-
-                    *r.add(result_reg) = (*atomic_ptr).load(Ordering::SeqCst);
-
+                    *r.add(result_reg) = (*atomic_ptr).load(load_ordering(idr_ptr_res));
 
                 }
 
-
                 vm.st.pc += 1; // fallthrough
 
-
             },
             Instruction::AtomicStoreU64(a, b) => {
 
@@ -2072,13 +2391,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU64;
 
-                    (*atomic_ptr).store(*r.add(src_reg), Ordering::SeqCst);
+                    (*atomic_ptr).store(*r.add(src_reg), store_ordering(idr_ptr_src));
 
                 }
 
@@ -2103,13 +2426,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU64;
 
-                    *r.add(result_reg) = (*atomic_ptr).fetch_add(*r.add(src_reg), Ordering::SeqCst);
+                    *r.add(result_reg) = (*atomic_ptr).fetch_add(*r.add(src_reg), ordering(res_idr_ptr_src));
 
                 }
 
@@ -2134,13 +2461,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU64;
 
-                    *r.add(result_reg) = (*atomic_ptr).fetch_sub(*r.add(src_reg), Ordering::SeqCst);
+                    *r.add(result_reg) = (*atomic_ptr).fetch_sub(*r.add(src_reg), ordering(res_idr_ptr_src));
 
                 }
 
@@ -2150,46 +2481,36 @@ impl Instruction {
 
             Instruction::AtomicLoadU32(a, b) => {
 
-
                 let idr_ptr_res = *a;
 
-
                 let offset = *b;
 
-
                 let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
 
-
                 let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
 
-
                 let result_reg = (idr_ptr_res & 0xFF) as usize;
 
-
                 unsafe {
 
-
                     let r = vm.st.r.as_mut_ptr();
 
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
 
+                    };
 
                     let atomic_ptr = addr as *const AtomicU32;
 
-
-                    *r.add(result_reg) = (*atomic_ptr).load(Ordering::SeqCst) as u64;
-
+                    *r.add(result_reg) = (*atomic_ptr).load(load_ordering(idr_ptr_res)) as u64;
 
                 }
 
-
                 vm.st.pc += 1; // fallthrough
 
-
             },
             Instruction::AtomicStoreU32(a, b) => {
 
@@ -2207,13 +2528,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU32;
 
-                    (*atomic_ptr).store(*r.add(src_reg) as u32, Ordering::SeqCst);
+                    (*atomic_ptr).store(*r.add(src_reg) as u32, store_ordering(idr_ptr_src));
 
                 }
 
@@ -2238,15 +2563,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU32;
 
                     *r.add(result_reg) =
 
-                        (*atomic_ptr).fetch_add(*r.add(src_reg) as u32, Ordering::SeqCst) as u64;
+                        (*atomic_ptr).fetch_add(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
 
                 }
 
@@ -2271,15 +2600,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU32;
 
                     *r.add(result_reg) =
 
-                        (*atomic_ptr).fetch_sub(*r.add(src_reg) as u32, Ordering::SeqCst) as u64;
+                        (*atomic_ptr).fetch_sub(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
 
                 }
 
@@ -2289,46 +2622,36 @@ impl Instruction {
 
             Instruction::AtomicLoadU16(a, b) => {
 
-
                 let idr_ptr_res = *a;
 
-
                 let offset = *b;
 
-
                 let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
 
-
                 let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
 
-
                 let result_reg = (idr_ptr_res & 0xFF) as usize;
 
-
                 unsafe {
 
-
                     let r = vm.st.r.as_mut_ptr();
 
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
 
+                    };
 
                     let atomic_ptr = addr as *const AtomicU16;
 
-
-                    *r.add(result_reg) = (*atomic_ptr).load(Ordering::SeqCst) as u64;
-
+                    *r.add(result_reg) = (*atomic_ptr).load(load_ordering(idr_ptr_res)) as u64;
 
                 }
 
-
                 vm.st.pc += 1; // fallthrough
 
-
             },
             Instruction::AtomicStoreU16(a, b) => {
 
@@ -2346,13 +2669,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU16;
 
-                    (*atomic_ptr).store(*r.add(src_reg) as u16, Ordering::SeqCst);
+                    (*atomic_ptr).store(*r.add(src_reg) as u16, store_ordering(idr_ptr_src));
 
                 }
 
@@ -2377,15 +2704,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU16;
 
                     *r.add(result_reg) =
 
-                        (*atomic_ptr).fetch_add(*r.add(src_reg) as u16, Ordering::SeqCst) as u64;
+                        (*atomic_ptr).fetch_add(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
 
                 }
 
@@ -2410,15 +2741,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU16;
 
                     *r.add(result_reg) =
 
-                        (*atomic_ptr).fetch_sub(*r.add(src_reg) as u16, Ordering::SeqCst) as u64;
+                        (*atomic_ptr).fetch_sub(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
 
                 }
 
@@ -2428,46 +2763,36 @@ impl Instruction {
 
             Instruction::AtomicLoadU8(a, b) => {
 
-
                 let idr_ptr_res = *a;
 
-
                 let offset = *b;
 
-
                 let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
 
-
                 let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
 
-
                 let result_reg = (idr_ptr_res & 0xFF) as usize;
 
-
                 unsafe {
 
-
                     let r = vm.st.r.as_mut_ptr();
 
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
 
+                    };
 
                     let atomic_ptr = addr as *const AtomicU8;
 
-
-                    *r.add(result_reg) = (*atomic_ptr).load(Ordering::SeqCst) as u64;
-
+                    *r.add(result_reg) = (*atomic_ptr).load(load_ordering(idr_ptr_res)) as u64;
 
                 }
 
-
                 vm.st.pc += 1; // fallthrough
 
-
             },
             Instruction::AtomicStoreU8(a, b) => {
 
@@ -2485,13 +2810,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU8;
 
-                    (*atomic_ptr).store(*r.add(src_reg) as u8, Ordering::SeqCst);
+                    (*atomic_ptr).store(*r.add(src_reg) as u8, store_ordering(idr_ptr_src));
 
                 }
 
@@ -2516,15 +2845,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU8;
 
                     *r.add(result_reg) =
 
-                        (*atomic_ptr).fetch_add(*r.add(src_reg) as u8, Ordering::SeqCst) as u64;
+                        (*atomic_ptr).fetch_add(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
 
                 }
 
@@ -2549,15 +2882,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU8;
 
                     *r.add(result_reg) =
 
-                        (*atomic_ptr).fetch_sub(*r.add(src_reg) as u8, Ordering::SeqCst) as u64;
+                        (*atomic_ptr).fetch_sub(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
 
                 }
 
@@ -2567,46 +2904,36 @@ impl Instruction {
 
             Instruction::AtomicLoadI8(a, b) => {
 
-
                 let idr_ptr_res = *a;
 
-
                 let offset = *b;
 
-
                 let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
 
-
                 let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
 
-
                 let result_reg = (idr_ptr_res & 0xFF) as usize;
 
-
                 unsafe {
 
-
                     let r = vm.st.r.as_mut_ptr();
 
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
 
+                    };
 
                     let atomic_ptr = addr as *const AtomicU8;
 
-
-                    *r.add(result_reg) = ((*atomic_ptr).load(Ordering::SeqCst) as i8) as i64 as u64;
-
+                    *r.add(result_reg) = ((*atomic_ptr).load(load_ordering(idr_ptr_res)) as i8) as i64 as u64;
 
                 }
 
-
                 vm.st.pc += 1; // fallthrough
 
-
             },
             Instruction::AtomicLoadI16(a, b) => {
 
@@ -2624,13 +2951,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *const AtomicU16;
 
-                    *r.add(result_reg) = ((*atomic_ptr).load(Ordering::SeqCst) as i16) as i64 as u64;
+                    *r.add(result_reg) = ((*atomic_ptr).load(load_ordering(idr_ptr_res)) as i16) as i64 as u64;
 
                 }
 
@@ -2653,13 +2984,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *const AtomicU32;
 
-                    *r.add(result_reg) = ((*atomic_ptr).load(Ordering::SeqCst) as i32) as i64 as u64;
+                    *r.add(result_reg) = ((*atomic_ptr).load(load_ordering(idr_ptr_res)) as i32) as i64 as u64;
 
                 }
 
@@ -2682,13 +3017,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *const AtomicU64;
 
-                    *r.add(result_reg) = ((*atomic_ptr).load(Ordering::SeqCst) as i64) as i64 as u64;
+                    *r.add(result_reg) = ((*atomic_ptr).load(load_ordering(idr_ptr_res)) as i64) as u64;
 
                 }
 
@@ -2711,13 +3050,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU8;
 
-                    (*atomic_ptr).store(*r.add(src_reg) as i8 as u8, Ordering::SeqCst);
+                    (*atomic_ptr).store(*r.add(src_reg) as i8 as u8, store_ordering(idr_ptr_src));
 
                 }
 
@@ -2740,13 +3083,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU16;
 
-                    (*atomic_ptr).store(*r.add(src_reg) as i16 as u16, Ordering::SeqCst);
+                    (*atomic_ptr).store(*r.add(src_reg) as i16 as u16, store_ordering(idr_ptr_src));
 
                 }
 
@@ -2769,13 +3116,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU32;
 
-                    (*atomic_ptr).store(*r.add(src_reg) as i32 as u32, Ordering::SeqCst);
+                    (*atomic_ptr).store(*r.add(src_reg) as i32 as u32, store_ordering(idr_ptr_src));
 
                 }
 
@@ -2798,13 +3149,17 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU64;
 
-                    (*atomic_ptr).store(*r.add(src_reg) as i64 as u64, Ordering::SeqCst);
+                    (*atomic_ptr).store(*r.add(src_reg) as i64 as u64, store_ordering(idr_ptr_src));
 
                 }
 
@@ -2829,15 +3184,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU8;
 
                     *r.add(result_reg) = (*atomic_ptr)
 
-                        .fetch_add(*r.add(src_reg) as i8 as u8, Ordering::SeqCst)
+                        .fetch_add(*r.add(src_reg) as i8 as u8, ordering(res_idr_ptr_src))
 
                         as i8 as i64 as u64;
 
@@ -2864,15 +3223,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU16;
 
                     *r.add(result_reg) = (*atomic_ptr)
 
-                        .fetch_add(*r.add(src_reg) as i16 as u16, Ordering::SeqCst)
+                        .fetch_add(*r.add(src_reg) as i16 as u16, ordering(res_idr_ptr_src))
 
                         as i16 as i64 as u64;
 
@@ -2899,15 +3262,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU32;
 
                     *r.add(result_reg) = (*atomic_ptr)
 
-                        .fetch_add(*r.add(src_reg) as i32 as u32, Ordering::SeqCst)
+                        .fetch_add(*r.add(src_reg) as i32 as u32, ordering(res_idr_ptr_src))
 
                         as i32 as i64 as u64;
 
@@ -2934,15 +3301,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU64;
 
                     *r.add(result_reg) = (*atomic_ptr)
 
-                        .fetch_add(*r.add(src_reg) as i64 as u64, Ordering::SeqCst)
+                        .fetch_add(*r.add(src_reg) as i64 as u64, ordering(res_idr_ptr_src))
 
                         as i64 as u64;
 
@@ -2969,15 +3340,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU8;
 
                     *r.add(result_reg) = (*atomic_ptr)
 
-                        .fetch_sub(*r.add(src_reg) as i8 as u8, Ordering::SeqCst)
+                        .fetch_sub(*r.add(src_reg) as i8 as u8, ordering(res_idr_ptr_src))
 
                         as i8 as i64 as u64;
 
@@ -3004,15 +3379,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU16;
 
                     *r.add(result_reg) = (*atomic_ptr)
 
-                        .fetch_sub(*r.add(src_reg) as i16 as u16, Ordering::SeqCst)
+                        .fetch_sub(*r.add(src_reg) as i16 as u16, ordering(res_idr_ptr_src))
 
                         as i16 as i64 as u64;
 
@@ -3039,15 +3418,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU32;
 
                     *r.add(result_reg) = (*atomic_ptr)
 
-                        .fetch_sub(*r.add(src_reg) as i32 as u32, Ordering::SeqCst)
+                        .fetch_sub(*r.add(src_reg) as i32 as u32, ordering(res_idr_ptr_src))
 
                         as i32 as i64 as u64;
 
@@ -3074,15 +3457,19 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                    };
 
                     let atomic_ptr = addr as *mut AtomicU64;
 
                     *r.add(result_reg) = (*atomic_ptr)
 
-                        .fetch_sub(*r.add(src_reg) as i64 as u64, Ordering::SeqCst)
+                        .fetch_sub(*r.add(src_reg) as i64 as u64, ordering(res_idr_ptr_src))
 
                         as i64 as u64;
 
@@ -3094,43 +3481,34 @@ impl Instruction {
 
             Instruction::LoadI8(a, b) => {
 
-
                 let idr_ptr_res = *a;
 
-
                 let offset = *b;
 
-
                 let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
 
-
                 let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
 
-
                 let result_reg = (idr_ptr_res & 0xFF) as usize;
 
-
                 unsafe {
 
-
                     let r = vm.st.r.as_mut_ptr();
 
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
 
+                    };
 
                     *r.add(result_reg) = (*(addr as *const i8) as i64) as u64;
 
-
                 }
 
-
                 vm.st.pc += 1; // fallthrough
 
-
             },
             Instruction::LoadI16(a, b) => {
 
@@ -3148,11 +3526,15 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Ok(addr) => addr,
 
-                    *r.add(result_reg) = (*(addr as *const i16) as i64) as u64;
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    *r.add(result_reg) = (*(addr as *const i16) as i64) as u64;
 
                 }
 
@@ -3175,9 +3557,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     *r.add(result_reg) = (*(addr as *const i32) as i64) as u64;
 
@@ -3202,11 +3588,15 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
 
-                    *r.add(result_reg) = (*(addr as *const i64) as i64) as u64;
+                    };
+
+                    *r.add(result_reg) = *(addr as *const i64) as u64;
 
                 }
 
@@ -3229,9 +3619,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     *(addr as *mut i8) = *r.add(src_reg) as i8;
 
@@ -3256,9 +3650,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     *(addr as *mut i16) = *r.add(src_reg) as i16;
 
@@ -3283,9 +3681,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     *(addr as *mut i32) = *r.add(src_reg) as i32;
 
@@ -3310,9 +3712,13 @@ impl Instruction {
 
                     let r = vm.st.r.as_mut_ptr();
 
-                    let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
 
-                    let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+                        Err(kind) => return vm.trap(kind),
+
+                    };
 
                     *(addr as *mut i64) = *r.add(src_reg) as i64;
 
@@ -3324,18 +3730,19 @@ impl Instruction {
 
             Instruction::GetDecode(a, b) => {
 
-
                 let decode_id = *a;
 
-
                 let deep = *b;
 
+                match vm.cm.get_decode(decode_id, deep) {
 
-                vm.function_table = vm.cm.get_decode(decode_id, vm.st.now_call_index as u64, deep);
+                    Ok(function_table) => vm.function_table = function_table,
 
+                    Err(err) => return vm.trap(TrapKind::DecodeFailed(err.to_string())),
 
-                vm.st.pc += 1; // fallthrough
+                }
 
+                vm.st.now_function_ptr = vm.function_table[vm.st.now_call_index];
 
             },
             Instruction::GetDecoded(a, b) => {
@@ -3349,1930 +3756,6232 @@ impl Instruction {
                 vm.st.pc += 1; // fallthrough
 
             },
-        }
-    }
-}
+            Instruction::CallArgs(a, b) => {
 
-/// 整数演算
-impl Operations {
-    /// 64bit符号なし整数加算
-    /// *dst = *dst + *src
-    #[inline(always)]
-    pub fn add_u64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_add(*r.add(src as usize));
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                let func_index = *a;
 
-    /// 64bit符号なし整数加算
-    /// *dst = *dst + imm
-    #[inline(always)]
-    pub fn add_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_add(imm);
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                let ret_arg_count = *b;
 
-    /// 64bit符号付き整数加算
-    /// *dst = *dst + *src
-    #[inline(always)]
-    pub fn add_i64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) =
-                ((*r.add(dst as usize) as i64).wrapping_add(*r.add(src as usize) as i64)) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                let ret_reg = ((ret_arg_count >> 16) & 0xFF) as usize;
 
-    /// 64bit符号付き整数加算
-    /// *dst = *dst + imm
-    #[inline(always)]
-    pub fn add_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = ((*r.add(dst as usize) as i64).wrapping_add(imm as i64)) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                let arg_reg = ((ret_arg_count >> 8) & 0xFF) as usize;
 
-    /// 64bit符号なし整数減算
-    /// *dst = *dst - *src
-    #[inline(always)]
-    pub fn sub_u64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_sub(*r.add(src as usize));
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                let count = (ret_arg_count & 0xFF) as usize;
 
-    /// 64bit符号なし整数減算
-    /// *dst = *dst - imm
-    #[inline(always)]
-    pub fn sub_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_sub(imm);
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                let Some(function_ptr) = vm.function_ptr(func_index) else {
 
-    /// 64bit符号付き整数減算
-    /// *dst = *dst - *src
-    #[inline(always)]
-    pub fn sub_i64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) =
-                ((*r.add(dst as usize) as i64).wrapping_sub(*r.add(src as usize) as i64)) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                    return vm.trap(TrapKind::BadFunctionIndex(func_index));
 
-    /// 64bit符号付き整数減算
-    /// *dst = *dst - imm
-    #[inline(always)]
-    pub fn sub_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = ((*r.add(dst as usize) as i64).wrapping_sub(imm as i64)) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                };
 
-    /// 64bit符号なし整数乗算
-    /// *dst = *dst * *src
-    #[inline(always)]
-    pub fn mul_u64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_mul(*r.add(src as usize));
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                if vm.st.frames.len() >= vm.st.max_frames {
 
-    /// 64bit符号なし整数乗算
-    /// *dst = *dst * imm
-    #[inline(always)]
-    pub fn mul_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_mul(imm);
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                    return vm.trap(TrapKind::CallStackOverflow);
 
-    /// 64bit符号付き整数乗算
-    /// *dst = *dst * *src
-    #[inline(always)]
-    pub fn mul_i64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) =
-                ((*r.add(dst as usize) as i64).wrapping_mul(*r.add(src as usize) as i64)) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                }
 
-    /// 64bit符号付き整数乗算
-    /// *dst = *dst * imm
-    #[inline(always)]
-    pub fn mul_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = ((*r.add(dst as usize) as i64).wrapping_mul(imm as i64)) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                vm.st.frames.push(Frame {
 
-    /// 64bit符号なし整数除算
-    /// *dst = *dst / *src
-    #[inline(always)]
-    pub fn div_u64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) / *r.add(src as usize);
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                    regs: vm.st.r,
 
-    /// 64bit符号なし整数除算
-    /// *dst = *dst / imm
-    #[inline(always)]
-    pub fn div_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(dst as usize)) / imm;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                    ret_reg,
 
-    /// 64bit符号付き整数除算
-    /// *dst = *dst / *src
-    #[inline(always)]
-    pub fn div_i64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) =
-                (*r.add(dst as usize) as i64 / *r.add(src as usize) as i64) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                    depth: vm.st.call_stack.len(),
 
-    /// 64bit符号付き整数除算
-    /// *dst = *dst / imm
-    #[inline(always)]
-    pub fn div_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(dst as usize) as i64 / imm as i64) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                });
 
-    /// 64bit符号付き整数絶対値
-    /// *dst = abs(*src)
-    #[inline(always)]
-    pub fn abs(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(src as usize) as i64).wrapping_abs() as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                vm.st.r.copy_within(arg_reg..arg_reg + count, 1);
 
-    /// 64bit符号付き整数剰余
-    /// *dst = *dst % *src
-    #[inline(always)]
-    pub fn mod_i64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) =
-                ((*r.add(dst as usize) as i64).wrapping_rem(*r.add(src as usize) as i64)) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                vm.st.call_stack.push(vm.st.pc);
 
-    /// 64bit符号付き 符号反転
-    /// *dst = -(*src)
-    #[inline(always)]
-    pub fn neg_i64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (-(*r.add(src as usize) as i64)).wrapping_abs() as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                vm.st.call_stack.push(vm.st.now_call_index);
 
-    /// 64bit符号なし整数浮動小数点数変換
-    /// *dst = (*src as f64)
-    #[inline(always)]
-    pub fn u64_to_f64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = f64::to_bits(*r.add(src as usize) as u64 as f64);
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                vm.st.pc = 0;
 
-    /// 64bit符号あり整数浮動小数点数変換
-    /// *dst = (*src as i64) as f64
-    #[inline(always)]
-    pub fn i64_to_f64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = f64::to_bits(*r.add(src as usize) as i64 as f64);
-        }
-        vm.st.pc += 1; // fallthrough
-    }
-}
+                vm.st.now_call_index = func_index as usize;
 
-/// 浮動小数点演算
-impl Operations {
-    /// 64bit浮動小数点加算
-    /// *dst = *dst + *src
-    #[inline(always)]
-    pub fn add_f64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let result =
-                f64::from_bits(*r.add(dst as usize)) + f64::from_bits(*r.add(src as usize));
-            *r.add(dst as usize) = result.to_bits();
-        }
-        vm.st.pc += 1; // fallthrough
-    }
+                vm.st.now_function_ptr = function_ptr;
 
-    /// 64bit浮動小数点加算
-    /// *dst = *dst + imm
+            },
+            Instruction::RetValue(a, b) => {
+
+                let src = *a;
+
+                let _ = *b;
+
+                if vm.st.call_stack.len() < 2 {
+
+                    return Operations::finish_coroutine(vm, vm.st.r[src as usize]);
+
+                }
+
+                let value = vm.st.r[src as usize];
+
+                if let Some(frame) = vm.st.frames.last()
+
+                    && frame.depth + 2 == vm.st.call_stack.len()
+
+                {
+
+                    let frame = vm.st.frames.pop().unwrap();
+
+                    vm.st.r = frame.regs;
+
+                    vm.st.r[frame.ret_reg] = value;
+
+                }
+
+                vm.st.now_call_index = vm.st.call_stack.pop().unwrap();
+
+                vm.st.pc = vm.st.call_stack.pop().unwrap() + 1;
+
+                vm.st.now_function_ptr = vm.function_table[vm.st.now_call_index];
+
+            },
+            Instruction::CallReg(a, b) => {
+
+                let func_reg = *a;
+
+                let pc = *b;
+
+                let func_index = vm.st.r[func_reg as usize];
+
+                Operations::call(vm, func_index, pc);
+
+            },
+            Instruction::CallRegArgs(a, b) => {
+
+                let func_reg = *a;
+
+                let ret_arg_count = *b;
+
+                let func_index = vm.st.r[func_reg as usize];
+
+                Operations::call_args(vm, func_index, ret_arg_count);
+
+            },
+            Instruction::LtF64Jump(a, b) => {
+
+                let addr_a_b = *a;
+
+                let offset = *b;
+
+                let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((addr_a_b >> 8) & 0xFF) as usize;
+
+                let b = (addr_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+
+                    if f64::from_bits(*r.add(a)) < f64::from_bits(*r.add(b)) {
+
+                        Operations::jump_to(vm, addr_reg, addr);
+
+                    } else {
+
+                        vm.st.pc += 1; // fallthrough
+
+                    }
+
+                }
+
+            },
+            Instruction::LteF64Jump(a, b) => {
+
+                let addr_a_b = *a;
+
+                let offset = *b;
+
+                let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((addr_a_b >> 8) & 0xFF) as usize;
+
+                let b = (addr_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+
+                    if f64::from_bits(*r.add(a)) <= f64::from_bits(*r.add(b)) {
+
+                        Operations::jump_to(vm, addr_reg, addr);
+
+                    } else {
+
+                        vm.st.pc += 1; // fallthrough
+
+                    }
+
+                }
+
+            },
+            Instruction::GtF64Jump(a, b) => {
+
+                let addr_a_b = *a;
+
+                let offset = *b;
+
+                let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((addr_a_b >> 8) & 0xFF) as usize;
+
+                let b = (addr_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+
+                    if f64::from_bits(*r.add(a)) > f64::from_bits(*r.add(b)) {
+
+                        Operations::jump_to(vm, addr_reg, addr);
+
+                    } else {
+
+                        vm.st.pc += 1; // fallthrough
+
+                    }
+
+                }
+
+            },
+            Instruction::GteF64Jump(a, b) => {
+
+                let addr_a_b = *a;
+
+                let offset = *b;
+
+                let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((addr_a_b >> 8) & 0xFF) as usize;
+
+                let b = (addr_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+
+                    if f64::from_bits(*r.add(a)) >= f64::from_bits(*r.add(b)) {
+
+                        Operations::jump_to(vm, addr_reg, addr);
+
+                    } else {
+
+                        vm.st.pc += 1; // fallthrough
+
+                    }
+
+                }
+
+            },
+            Instruction::EqF64Jump(a, b) => {
+
+                let addr_a_b = *a;
+
+                let offset = *b;
+
+                let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((addr_a_b >> 8) & 0xFF) as usize;
+
+                let b = (addr_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+
+                    if f64::from_bits(*r.add(a)) == f64::from_bits(*r.add(b)) {
+
+                        Operations::jump_to(vm, addr_reg, addr);
+
+                    } else {
+
+                        vm.st.pc += 1; // fallthrough
+
+                    }
+
+                }
+
+            },
+            Instruction::UnorderedF64Jump(a, b) => {
+
+                let addr_a_b = *a;
+
+                let offset = *b;
+
+                let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((addr_a_b >> 8) & 0xFF) as usize;
+
+                let b = (addr_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+
+                    if f64::from_bits(*r.add(a)).is_nan() || f64::from_bits(*r.add(b)).is_nan() {
+
+                        Operations::jump_to(vm, addr_reg, addr);
+
+                    } else {
+
+                        vm.st.pc += 1; // fallthrough
+
+                    }
+
+                }
+
+            },
+            Instruction::SetEq(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (*r.add(a) == *r.add(b)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetNeq(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (*r.add(a) != *r.add(b)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetLtU64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (*r.add(a) < *r.add(b)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetLteU64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (*r.add(a) <= *r.add(b)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetLtI64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = ((*r.add(a) as i64) < (*r.add(b) as i64)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetLteI64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = ((*r.add(a) as i64) <= (*r.add(b) as i64)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetGtU64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (*r.add(a) > *r.add(b)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetGteU64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (*r.add(a) >= *r.add(b)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetGtI64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = ((*r.add(a) as i64) > (*r.add(b) as i64)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetGteI64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = ((*r.add(a) as i64) >= (*r.add(b) as i64)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetLtF64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (f64::from_bits(*r.add(a)) < f64::from_bits(*r.add(b))) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetLteF64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (f64::from_bits(*r.add(a)) <= f64::from_bits(*r.add(b))) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetGtF64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (f64::from_bits(*r.add(a)) > f64::from_bits(*r.add(b))) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetGteF64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (f64::from_bits(*r.add(a)) >= f64::from_bits(*r.add(b))) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetEqF64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (f64::from_bits(*r.add(a)) == f64::from_bits(*r.add(b))) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SetUnorderedF64(a, b) => {
+
+                let dst_a_b = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+
+                let a = ((dst_a_b >> 8) & 0xFF) as usize;
+
+                let b = (dst_a_b & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    *r.add(dst) = (f64::from_bits(*r.add(a)).is_nan() || f64::from_bits(*r.add(b)).is_nan()) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::CmovNz(a, b) => {
+
+                let dst_cond_src = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_cond_src >> 16) & 0xFF) as usize;
+
+                let cond = ((dst_cond_src >> 8) & 0xFF) as usize;
+
+                let src = (dst_cond_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    if *r.add(cond) != 0 {
+
+                        *r.add(dst) = *r.add(src);
+
+                    }
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::CmovZ(a, b) => {
+
+                let dst_cond_src = *a;
+
+                let _ = *b;
+
+                let dst = ((dst_cond_src >> 16) & 0xFF) as usize;
+
+                let cond = ((dst_cond_src >> 8) & 0xFF) as usize;
+
+                let src = (dst_cond_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    if *r.add(cond) == 0 {
+
+                        *r.add(dst) = *r.add(src);
+
+                    }
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicCasU64(a, b) => {
+
+                let ok_res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let ok_reg = ((ok_res_idr_ptr_src >> 32) & 0xFF) as usize;
+
+                let result_reg = ((ok_res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((ok_res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((ok_res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (ok_res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU64;
+
+                    let (old, ok) = match (*atomic_ptr).compare_exchange(
+
+                        *r.add(result_reg),
+
+                        *r.add(src_reg),
+
+                        ordering(ok_res_idr_ptr_src),
+
+                        failure_ordering(ok_res_idr_ptr_src),
+
+                    ) {
+
+                        Ok(old) => (old, 1),
+
+                        Err(old) => (old, 0),
+
+                    };
+
+                    *r.add(result_reg) = old;
+
+                    *r.add(ok_reg) = ok;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicCasU32(a, b) => {
+
+                let ok_res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let ok_reg = ((ok_res_idr_ptr_src >> 32) & 0xFF) as usize;
+
+                let result_reg = ((ok_res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((ok_res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((ok_res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (ok_res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU32;
+
+                    let (old, ok) = match (*atomic_ptr).compare_exchange(
+
+                        *r.add(result_reg) as u32,
+
+                        *r.add(src_reg) as u32,
+
+                        ordering(ok_res_idr_ptr_src),
+
+                        failure_ordering(ok_res_idr_ptr_src),
+
+                    ) {
+
+                        Ok(old) => (old, 1),
+
+                        Err(old) => (old, 0),
+
+                    };
+
+                    *r.add(result_reg) = old as u64;
+
+                    *r.add(ok_reg) = ok;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicCasU16(a, b) => {
+
+                let ok_res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let ok_reg = ((ok_res_idr_ptr_src >> 32) & 0xFF) as usize;
+
+                let result_reg = ((ok_res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((ok_res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((ok_res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (ok_res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU16;
+
+                    let (old, ok) = match (*atomic_ptr).compare_exchange(
+
+                        *r.add(result_reg) as u16,
+
+                        *r.add(src_reg) as u16,
+
+                        ordering(ok_res_idr_ptr_src),
+
+                        failure_ordering(ok_res_idr_ptr_src),
+
+                    ) {
+
+                        Ok(old) => (old, 1),
+
+                        Err(old) => (old, 0),
+
+                    };
+
+                    *r.add(result_reg) = old as u64;
+
+                    *r.add(ok_reg) = ok;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicCasU8(a, b) => {
+
+                let ok_res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let ok_reg = ((ok_res_idr_ptr_src >> 32) & 0xFF) as usize;
+
+                let result_reg = ((ok_res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((ok_res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((ok_res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (ok_res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU8;
+
+                    let (old, ok) = match (*atomic_ptr).compare_exchange(
+
+                        *r.add(result_reg) as u8,
+
+                        *r.add(src_reg) as u8,
+
+                        ordering(ok_res_idr_ptr_src),
+
+                        failure_ordering(ok_res_idr_ptr_src),
+
+                    ) {
+
+                        Ok(old) => (old, 1),
+
+                        Err(old) => (old, 0),
+
+                    };
+
+                    *r.add(result_reg) = old as u64;
+
+                    *r.add(ok_reg) = ok;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicSwapU64(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU64;
+
+                    *r.add(result_reg) = (*atomic_ptr).swap(*r.add(src_reg), ordering(res_idr_ptr_src));
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicSwapU32(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU32;
+
+                    *r.add(result_reg) = (*atomic_ptr).swap(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicSwapU16(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU16;
+
+                    *r.add(result_reg) = (*atomic_ptr).swap(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicSwapU8(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU8;
+
+                    *r.add(result_reg) = (*atomic_ptr).swap(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicAndU64(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU64;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_and(*r.add(src_reg), ordering(res_idr_ptr_src));
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicAndU32(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU32;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_and(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicAndU16(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU16;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_and(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicAndU8(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU8;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_and(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicOrU64(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU64;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_or(*r.add(src_reg), ordering(res_idr_ptr_src));
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicOrU32(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU32;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_or(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicOrU16(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU16;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_or(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicOrU8(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU8;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_or(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicXorU64(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU64;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_xor(*r.add(src_reg), ordering(res_idr_ptr_src));
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicXorU32(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU32;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_xor(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicXorU16(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU16;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_xor(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicXorU8(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU8;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_xor(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMinU64(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU64;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg), ordering(res_idr_ptr_src));
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMinU32(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU32;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMinU16(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU16;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMinU8(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU8;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMaxU64(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU64;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg), ordering(res_idr_ptr_src));
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMaxU32(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU32;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMaxU16(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU16;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMaxU8(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicU8;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMinI64(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicI64;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as i64, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMinI32(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicI32;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as i32, ordering(res_idr_ptr_src)) as i64 as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMinI16(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicI16;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as i16, ordering(res_idr_ptr_src)) as i64 as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMinI8(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicI8;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as i8, ordering(res_idr_ptr_src)) as i64 as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMaxI64(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicI64;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as i64, ordering(res_idr_ptr_src)) as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMaxI32(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicI32;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as i32, ordering(res_idr_ptr_src)) as i64 as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMaxI16(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicI16;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as i16, ordering(res_idr_ptr_src)) as i64 as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::AtomicMaxI8(a, b) => {
+
+                let res_idr_ptr_src = *a;
+
+                let offset = *b;
+
+                let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+
+                let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+
+                let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+
+                let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+
+                        Ok(addr) => addr,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    };
+
+                    let atomic_ptr = addr as *mut AtomicI8;
+
+                    *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as i8, ordering(res_idr_ptr_src)) as i64 as u64;
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::Fence(a, b) => {
+
+                let ordering = *a;
+
+                let _ = *b;
+
+                std::sync::atomic::fence(fence_ordering(ordering));
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SharedAlloc(a, b) => {
+
+                let size_idr = *a;
+
+                let add_size = *b;
+
+                let size_reg = ((size_idr >> 8) & 0xFF) as usize;
+
+                let id_res_reg = (size_idr & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let size = (*r.add(size_reg)).wrapping_add(add_size) as usize;
+
+                    match vm.st.mem.alloc_shared_heep(size) {
+
+                        Ok(id) => *r.add(id_res_reg) = id,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    }
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SharedMap(a, b) => {
+
+                let dst = *a;
+
+                let handle = *b;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    match vm.st.mem.map_shared_heep(*r.add(handle as usize)) {
+
+                        Ok(id) => *r.add(dst as usize) = id,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    }
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::SharedHandle(a, b) => {
+
+                let dst = *a;
+
+                let id = *b;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    match vm.st.mem.shared_handle(*r.add(id as usize)) {
+
+                        Ok(handle) => *r.add(dst as usize) = handle,
+
+                        Err(kind) => return vm.trap(kind),
+
+                    }
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::ChanNew(a, b) => {
+
+                let cap_dst = *a;
+
+                let add_cap = *b;
+
+                let cap_reg = ((cap_dst >> 8) & 0xFF) as usize;
+
+                let dst_reg = (cap_dst & 0xFF) as usize;
+
+                unsafe {
+
+                    let r = vm.st.r.as_mut_ptr();
+
+                    let capacity = (*r.add(cap_reg)).wrapping_add(add_cap) as usize;
+
+                    *r.add(dst_reg) = vm.channels.create(capacity);
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::ChanSend(a, b) => {
+
+                let status_chan_src = *a;
+
+                let _ = *b;
+
+                Operations::send_message(vm, status_chan_src, false, true);
+
+            },
+            Instruction::ChanTrySend(a, b) => {
+
+                let status_chan_src = *a;
+
+                let _ = *b;
+
+                Operations::send_message(vm, status_chan_src, false, false);
+
+            },
+            Instruction::ChanSendHeep(a, b) => {
+
+                let status_chan_id = *a;
+
+                let _ = *b;
+
+                Operations::send_message(vm, status_chan_id, true, true);
+
+            },
+            Instruction::ChanTrySendHeep(a, b) => {
+
+                let status_chan_id = *a;
+
+                let _ = *b;
+
+                Operations::send_message(vm, status_chan_id, true, false);
+
+            },
+            Instruction::ChanRecv(a, b) => {
+
+                let status_dst_chan = *a;
+
+                let _ = *b;
+
+                Operations::recv_message(vm, status_dst_chan, true);
+
+            },
+            Instruction::ChanTryRecv(a, b) => {
+
+                let status_dst_chan = *a;
+
+                let _ = *b;
+
+                Operations::recv_message(vm, status_dst_chan, false);
+
+            },
+            Instruction::ChanClose(a, b) => {
+
+                let chan_reg = *a;
+
+                let _ = *b;
+
+                let handle = vm.st.r[chan_reg as usize];
+
+                match vm.channels.get(handle) {
+
+                    Ok(channel) => channel.close(),
+
+                    Err(kind) => return vm.trap(kind),
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::Spawn(a, b) => {
+
+                let func_index = *a;
+
+                let id_arg_count = *b;
+
+                let id_reg = ((id_arg_count >> 16) & 0xFF) as usize;
+
+                let arg_reg = ((id_arg_count >> 8) & 0xFF) as usize;
+
+                let count = (id_arg_count & 0xFF) as usize;
+
+                let Some(threads) = vm.threads.upgrade() else {
+
+                    return vm.trap(TrapKind::NotInPool);
+
+                };
+
+                if vm.function_ptr(func_index).is_none() {
+
+                    return vm.trap(TrapKind::BadFunctionIndex(func_index));
+
+                }
+
+                let mut child = VM::new();
+
+                child.cm = vm.cm.clone_shared();
+
+                child.channels = vm.channels.clone();
+
+                child.st.mem.shared = vm.st.mem.shared.clone();
+
+                child.st.mem.bounds_check = vm.st.mem.bounds_check;
+
+                child.st.r[1..1 + count].copy_from_slice(&vm.st.r[arg_reg..arg_reg + count]);
+
+                child.st.now_call_index = func_index as usize;
+
+                let use_core_affinity = threads.uses_core_affinity(vm.vm_id);
+
+                vm.st.r[id_reg] = threads.spawn(child, use_core_affinity);
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::Join(a, b) => {
+
+                let dst = *a;
+
+                let id = *b;
+
+                let Some(threads) = vm.threads.upgrade() else {
+
+                    return vm.trap(TrapKind::NotInPool);
+
+                };
+
+                let vm_id = vm.st.r[id as usize];
+
+                match threads.join_from(vm.vm_id, vm_id) {
+
+                    Ok(result) => vm.st.r[dst as usize] = result.exit_code,
+
+                    Err(kind) => return vm.trap(kind),
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::VmId(a, b) => {
+
+                let dst = *a;
+
+                let _ = *b;
+
+                vm.st.r[dst as usize] = vm.vm_id;
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::CoCreate(a, b) => {
+
+                let func_index = *a;
+
+                let id_arg_count = *b;
+
+                let id_reg = ((id_arg_count >> 16) & 0xFF) as usize;
+
+                let arg_reg = ((id_arg_count >> 8) & 0xFF) as usize;
+
+                let count = (id_arg_count & 0xFF) as usize;
+
+                if vm.function_ptr(func_index).is_none() {
+
+                    return vm.trap(TrapKind::BadFunctionIndex(func_index));
+
+                }
+
+                let id = vm.st.coroutines.create(func_index as usize, &vm.st.r[arg_reg..arg_reg + count]);
+
+                vm.st.r[id_reg] = id;
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::CoResume(a, b) => {
+
+                let status_value_co = *a;
+
+                let _ = *b;
+
+                let status_reg = ((status_value_co >> 16) & 0xFF) as usize;
+
+                let value_reg = ((status_value_co >> 8) & 0xFF) as usize;
+
+                let co_reg = (status_value_co & 0xFF) as usize;
+
+                let id = vm.st.r[co_reg];
+
+                match vm.st.coroutines.resume(id, vm.st.r[value_reg], status_reg, value_reg) {
+
+                    Ok(true) => vm.st.state_flag |= state_flag::SWITCH,
+
+                    Ok(false) => vm.st.r[status_reg] = coroutine::status::DEAD,
+
+                    Err(kind) => return vm.trap(kind),
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::Yield(a, b) => {
+
+                let reg = *a;
+
+                let _ = *b;
+
+                if let Err(kind) = vm.st.coroutines.yield_value(vm.st.r[reg as usize], reg as usize) {
+
+                    return vm.trap(kind);
+
+                }
+
+                vm.st.state_flag |= state_flag::SWITCH;
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::NativeCallAsync(a, b) => {
+
+                let native_index = *a;
+
+                let ret_arg_count = *b;
+
+                if vm.threads.upgrade().is_some() {
+
+                    return vm.trap(TrapKind::NotInExecutor);
+
+                }
+
+                let ret_reg = ((ret_arg_count >> 16) & 0xFF) as usize;
+
+                let arg_reg = ((ret_arg_count >> 8) & 0xFF) as usize;
+
+                let count = (ret_arg_count & 0xFF) as usize;
+
+                let Some(native) = vm.native(native_index) else {
+
+                    return vm.trap(TrapKind::BadNativeIndex(native_index));
+
+                };
+
+                let mut args = [0u64; 256];
+
+                args[..count].copy_from_slice(&vm.st.r[arg_reg..arg_reg + count]);
+
+                match native(vm, &args[..count]) {
+
+                    Ok(token) => vm.st.pending = Some(Pending { token, ret_reg }),
+
+                    Err(kind) => return vm.trap(kind),
+
+                }
+
+                vm.st.state_flag |= state_flag::PAUSE;
+
+                vm.st.pc += 1; // 再開したら次の命令から
+
+            },
+            Instruction::LoadFuncIndex(a, b) => {
+
+                let dst = *a;
+
+                let func_index = *b;
+
+                vm.st.r[dst as usize] = func_index;
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+            Instruction::NativeCall(a, b) => {
+
+                let native_index = *a;
+
+                let ret_arg_count = *b;
+
+                let ret_reg = ((ret_arg_count >> 16) & 0xFF) as usize;
+
+                let arg_reg = ((ret_arg_count >> 8) & 0xFF) as usize;
+
+                let count = (ret_arg_count & 0xFF) as usize;
+
+                let Some(native) = vm.native(native_index) else {
+
+                    return vm.trap(TrapKind::BadNativeIndex(native_index));
+
+                };
+
+                // nativeは &mut VM を取るので、引数はスタックにコピーして渡す
+
+                let mut args = [0u64; 256];
+
+                args[..count].copy_from_slice(&vm.st.r[arg_reg..arg_reg + count]);
+
+                match native(vm, &args[..count]) {
+
+                    Ok(value) => vm.st.r[ret_reg] = value,
+
+                    Err(kind) => return vm.trap(kind),
+
+                }
+
+                vm.st.pc += 1; // fallthrough
+
+            },
+        }
+    }
+}
+
+/// 整数演算
+impl Operations {
+    /// 64bit符号なし整数加算
+    /// *dst = *dst + *src
+    #[inline(always)]
+    pub fn add_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_add(*r.add(src as usize));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号なし整数加算
+    /// *dst = *dst + imm
+    #[inline(always)]
+    pub fn add_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_add(imm);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数加算
+    /// *dst = *dst + *src
+    #[inline(always)]
+    pub fn add_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) =
+                ((*r.add(dst as usize) as i64).wrapping_add(*r.add(src as usize) as i64)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数加算
+    /// *dst = *dst + imm
+    #[inline(always)]
+    pub fn add_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = ((*r.add(dst as usize) as i64).wrapping_add(imm as i64)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号なし整数減算
+    /// *dst = *dst - *src
+    #[inline(always)]
+    pub fn sub_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_sub(*r.add(src as usize));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号なし整数減算
+    /// *dst = *dst - imm
+    #[inline(always)]
+    pub fn sub_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_sub(imm);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数減算
+    /// *dst = *dst - *src
+    #[inline(always)]
+    pub fn sub_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) =
+                ((*r.add(dst as usize) as i64).wrapping_sub(*r.add(src as usize) as i64)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数減算
+    /// *dst = *dst - imm
+    #[inline(always)]
+    pub fn sub_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = ((*r.add(dst as usize) as i64).wrapping_sub(imm as i64)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号なし整数乗算
+    /// *dst = *dst * *src
+    #[inline(always)]
+    pub fn mul_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_mul(*r.add(src as usize));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号なし整数乗算
+    /// *dst = *dst * imm
+    #[inline(always)]
+    pub fn mul_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(dst as usize)).wrapping_mul(imm);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数乗算
+    /// *dst = *dst * *src
+    #[inline(always)]
+    pub fn mul_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) =
+                ((*r.add(dst as usize) as i64).wrapping_mul(*r.add(src as usize) as i64)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数乗算
+    /// *dst = *dst * imm
+    #[inline(always)]
+    pub fn mul_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = ((*r.add(dst as usize) as i64).wrapping_mul(imm as i64)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号なし整数除算
+    /// *dst = *dst / *src
+    #[inline(always)]
+    pub fn div_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let divisor = *r.add(src as usize);
+            if divisor == 0 {
+                return vm.trap(TrapKind::DivisionByZero);
+            }
+            *r.add(dst as usize) = *r.add(dst as usize) / divisor;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号なし整数除算
+    /// *dst = *dst / imm
+    #[inline(always)]
+    pub fn div_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        if imm == 0 {
+            return vm.trap(TrapKind::DivisionByZero);
+        }
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(dst as usize)) / imm;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数除算
+    /// *dst = *dst / *src
+    #[inline(always)]
+    pub fn div_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let divisor = *r.add(src as usize) as i64;
+            if divisor == 0 {
+                return vm.trap(TrapKind::DivisionByZero);
+            }
+            *r.add(dst as usize) = (*r.add(dst as usize) as i64).wrapping_div(divisor) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数除算
+    /// *dst = *dst / imm
+    #[inline(always)]
+    pub fn div_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        if imm == 0 {
+            return vm.trap(TrapKind::DivisionByZero);
+        }
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(dst as usize) as i64).wrapping_div(imm as i64) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数絶対値
+    /// *dst = abs(*src)
+    #[inline(always)]
+    pub fn abs(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(src as usize) as i64).wrapping_abs() as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き整数剰余
+    /// *dst = *dst % *src
+    #[inline(always)]
+    pub fn mod_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let divisor = *r.add(src as usize) as i64;
+            if divisor == 0 {
+                return vm.trap(TrapKind::DivisionByZero);
+            }
+            *r.add(dst as usize) = ((*r.add(dst as usize) as i64).wrapping_rem(divisor)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号付き 符号反転
+    /// *dst = -(*src)
+    #[inline(always)]
+    pub fn neg_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (-(*r.add(src as usize) as i64)).wrapping_abs() as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号なし整数浮動小数点数変換
+    /// *dst = (*src as f64)
+    #[inline(always)]
+    pub fn u64_to_f64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = f64::to_bits(*r.add(src as usize) as f64);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit符号あり整数浮動小数点数変換
+    /// *dst = (*src as i64) as f64
+    #[inline(always)]
+    pub fn i64_to_f64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = f64::to_bits(*r.add(src as usize) as i64 as f64);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+}
+
+/// 浮動小数点演算
+impl Operations {
+    /// 64bit浮動小数点加算
+    /// *dst = *dst + *src
+    #[inline(always)]
+    pub fn add_f64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result =
+                f64::from_bits(*r.add(dst as usize)) + f64::from_bits(*r.add(src as usize));
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点加算
+    /// *dst = *dst + imm
+    #[inline(always)]
+    pub fn add_f64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result = f64::from_bits(*r.add(dst as usize)) + f64::from_bits(imm);
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点減算
+    /// *dst = *dst - *src
+    #[inline(always)]
+    pub fn sub_f64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result =
+                f64::from_bits(*r.add(dst as usize)) - f64::from_bits(*r.add(src as usize));
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点減算
+    /// *dst = *dst - imm
+    #[inline(always)]
+    pub fn sub_f64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result = f64::from_bits(*r.add(dst as usize)) - f64::from_bits(imm);
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点乗算
+    /// *dst = *dst * *src
+    #[inline(always)]
+    pub fn mul_f64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result =
+                f64::from_bits(*r.add(dst as usize)) * f64::from_bits(*r.add(src as usize));
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点乗算
+    /// *dst = *dst * imm
+    #[inline(always)]
+    pub fn mul_f64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result = f64::from_bits(*r.add(dst as usize)) * f64::from_bits(imm);
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点除算
+    /// *dst = *dst / *src
+    #[inline(always)]
+    pub fn div_f64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result =
+                f64::from_bits(*r.add(dst as usize)) / f64::from_bits(*r.add(src as usize));
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点除算
+    /// *dst = *dst / imm
+    #[inline(always)]
+    pub fn div_f64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result = f64::from_bits(*r.add(dst as usize)) / f64::from_bits(imm);
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点絶対値
+    /// *dst = abs(*src)
+    #[inline(always)]
+    pub fn abs_f64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result = f64::from_bits(*r.add(src as usize)).abs();
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点符号反転
+    /// *dst = -(*src)
+    #[inline(always)]
+    pub fn neg_f64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let result = -f64::from_bits(*r.add(src as usize));
+            *r.add(dst as usize) = result.to_bits();
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit浮動小数点整数変換
+    #[inline(always)]
+    pub fn to_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = f64::from_bits(*r.add(src as usize)) as i64 as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+}
+
+/// 論理演算
+impl Operations {
+    /// 64bit論理積
+    /// *dst = *dst & *src
+    #[inline(always)]
+    pub fn and_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) & *r.add(src as usize);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理積
+    /// *dst = *dst & imm
+    #[inline(always)]
+    pub fn and_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) & imm;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理和
+    /// *dst = *dst | *src
+    #[inline(always)]
+    pub fn or_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) | *r.add(src as usize);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理和
+    /// *dst = *dst | imm
+    #[inline(always)]
+    pub fn or_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) | imm;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit排他的論理和
+    /// *dst = *dst ^ *src
+    #[inline(always)]
+    pub fn xor_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) ^ *r.add(src as usize);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit排他的論理和
+    /// *dst = *dst ^ imm
+    #[inline(always)]
+    pub fn xor_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) ^ imm;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理否定
+    /// *dst = !*src
+    #[inline(always)]
+    pub fn not_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = !*r.add(src as usize);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理左シフト
+    /// *dst = *dst << *src
+    #[inline(always)]
+    pub fn shl_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) << (*r.add(src as usize) as u32);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理左シフト
+    /// *dst = *dst << imm
+    #[inline(always)]
+    pub fn shl_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) << (imm as u32);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit算術左シフト
+    /// *dst = *dst << *src
+    #[inline(always)]
+    pub fn shl_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) =
+                ((*r.add(dst as usize) as i64) << (*r.add(src as usize) as u32)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit算術左シフト
+    /// *dst = *dst << imm
+    #[inline(always)]
+    pub fn shl_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = ((*r.add(dst as usize) as i64) << (imm as u32)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理右シフト
+    /// *dst = *dst >> *src
+    #[inline(always)]
+    pub fn shr_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) >> (*r.add(src as usize) as u32);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理右シフト
+    /// *dst = *dst >> imm
+    #[inline(always)]
+    pub fn shr_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(dst as usize) >> (imm as u32);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit算術右シフト
+    /// *dst = *dst >> *src
+    #[inline(always)]
+    pub fn shr_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) =
+                ((*r.add(dst as usize) as i64) >> (*r.add(src as usize) as u32)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit算術右シフト
+    /// *dst = *dst >> imm
+    #[inline(always)]
+    pub fn shr_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = ((*r.add(dst as usize) as i64) >> (imm as u32)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理左ローテート
+    /// *dst = rol(*dst, *src)
+    #[inline(always)]
+    pub fn rol_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let value = *r.add(dst as usize);
+            let shift = (*r.add(src as usize) & 0b111_1111) as u32;
+            *r.add(dst as usize) = value.rotate_left(shift);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理左ローテート
+    /// *dst = rol(*dst, imm)
+    #[inline(always)]
+    pub fn rol_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let value = *r.add(dst as usize);
+            let shift = (imm & 0b111_1111) as u32;
+            *r.add(dst as usize) = value.rotate_left(shift);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit算術左ローテート
+    /// *dst = rol(*dst, *src)
+    #[inline(always)]
+    pub fn rol_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let value = *r.add(dst as usize) as i64;
+            let shift = (*r.add(src as usize) & 0b111_1111) as u32;
+            *r.add(dst as usize) = value.rotate_left(shift) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit算術左ローテート
+    /// *dst = rol(*dst, imm)
+    #[inline(always)]
+    pub fn rol_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let value = *r.add(dst as usize) as i64;
+            let shift = (imm & 0b111_1111) as u32;
+            *r.add(dst as usize) = value.rotate_left(shift) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理右ローテート
+    /// *dst = ror(*dst, *src)
+    #[inline(always)]
+    pub fn ror_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let value = *r.add(dst as usize);
+            let shift = (*r.add(src as usize) & 0b111_1111) as u32;
+            *r.add(dst as usize) = value.rotate_right(shift);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit論理右ローテート
+    /// *dst = ror(*dst, imm)
+    #[inline(always)]
+    pub fn ror_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let value = *r.add(dst as usize);
+            let shift = (imm & 0b111_1111) as u32;
+            *r.add(dst as usize) = value.rotate_right(shift);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit算術右ローテート
+    /// *dst = ror(*dst, *src)
+    #[inline(always)]
+    pub fn ror_i64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let value = *r.add(dst as usize) as i64;
+            let shift = (*r.add(src as usize) & 0b111_1111) as u32;
+            *r.add(dst as usize) = value.rotate_right(shift) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 64bit算術右ローテート
+    /// *dst = ror(*dst, imm)
+    #[inline(always)]
+    pub fn ror_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let value = *r.add(dst as usize) as i64;
+            let shift = (imm & 0b111_1111) as u32;
+            *r.add(dst as usize) = value.rotate_right(shift) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// bitcount 1
+    /// *dst = count_ones(*src)
+    #[inline(always)]
+    pub fn count_ones_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(src as usize)).count_ones() as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// bitcount 0
+    /// *dst = count_zeros(*src)
+    #[inline(always)]
+    pub fn count_zeros_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(src as usize)).count_zeros() as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// trailing zeros
+    /// *dst = trailing_zeros(*src)
+    #[inline(always)]
+    pub fn trailing_zeros_u64(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = (*r.add(src as usize)).trailing_zeros() as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+}
+
+/// レジスタ操作系
+impl Operations {
+    /// レジスタ間値コピー
+    /// *dst = *src
+    #[inline(always)]
+    pub fn mov(vm: &mut VM, dst: u64, src: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = *r.add(src as usize);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 即値ロード
+    /// *dst = imm
+    #[inline(always)]
+    pub fn load_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            *r.add(dst as usize) = imm;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// 交換
+    /// *reg_a, *reg_b = *reg_b, *reg_a
+    #[inline(always)]
+    pub fn swap(vm: &mut VM, reg_a: u64, reg_b: u64) {
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let temp = *r.add(reg_a as usize);
+            *r.add(reg_a as usize) = *r.add(reg_b as usize);
+            *r.add(reg_b as usize) = temp;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+}
+
+/// メモリ操作
+impl Operations {
+    /// u64ロード
+    /// *result_reg = *(heep_ptr(*id_reg) + *addr_reg + offset)
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn load_u64(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *r.add(result_reg) = *(addr as *const u64);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// u32ロード
+    /// *result_reg = *(heep_ptr(*id_reg) + *addr_reg + offset)
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn load_u32(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *r.add(result_reg) = *(addr as *const u32) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// u16ロード
+    /// *result_reg = *(heep_ptr(*id_reg) + *addr_reg + offset)
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn load_u16(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *r.add(result_reg) = *(addr as *const u16) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// u8ロード
+    /// *result_reg = *(heep_ptr(*id_reg) + *addr_reg + offset)
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn load_u8(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *r.add(result_reg) = *(addr as *const u8) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// u64ストア
+    /// *(heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn store_u64(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *(addr as *mut u64) = *r.add(src_reg);
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// u32ストア
+    /// *(heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn store_u32(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *(addr as *mut u32) = *r.add(src_reg) as u32;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// u16ストア
+    /// *(heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn store_u16(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *(addr as *mut u16) = *r.add(src_reg) as u16;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// u8ストア
+    /// *(heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn store_u8(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *(addr as *mut u8) = *r.add(src_reg) as u8;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u64 ロード
+    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset)
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_load_u64(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *const AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr).load(load_ordering(idr_ptr_res));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u64 ストア
+    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_store_u64(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            (*atomic_ptr).store(*r.add(src_reg), store_ordering(idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u64 加算
+    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_add_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_add(*r.add(src_reg), ordering(res_idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u64 減算
+    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_sub_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_sub(*r.add(src_reg), ordering(res_idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u32 ロード
+    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset)
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_load_u32(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *const AtomicU32;
+            *r.add(result_reg) = (*atomic_ptr).load(load_ordering(idr_ptr_res)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u32 ストア
+    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_store_u32(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            (*atomic_ptr).store(*r.add(src_reg) as u32, store_ordering(idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u32 加算
+    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_add_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            *r.add(result_reg) =
+                (*atomic_ptr).fetch_add(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u32 減算
+    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_sub_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            *r.add(result_reg) =
+                (*atomic_ptr).fetch_sub(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u16 ロード
+    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset)
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_load_u16(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *const AtomicU16;
+            *r.add(result_reg) = (*atomic_ptr).load(load_ordering(idr_ptr_res)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u16 ストア
+    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_store_u16(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            (*atomic_ptr).store(*r.add(src_reg) as u16, store_ordering(idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u16 加算
+    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_add_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            *r.add(result_reg) =
+                (*atomic_ptr).fetch_add(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u16 減算
+    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_sub_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            *r.add(result_reg) =
+                (*atomic_ptr).fetch_sub(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u8 ロード
+    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset)
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_load_u8(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *const AtomicU8;
+            *r.add(result_reg) = (*atomic_ptr).load(load_ordering(idr_ptr_res)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u8 ストア
+    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_store_u8(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            (*atomic_ptr).store(*r.add(src_reg) as u8, store_ordering(idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u8 加算
+    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_add_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            *r.add(result_reg) =
+                (*atomic_ptr).fetch_add(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic u8 減算
+    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_sub_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            *r.add(result_reg) =
+                (*atomic_ptr).fetch_sub(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// i8ロード（符号拡張）
+    /// *result_reg = (*(heep_ptr(*id_reg) + *addr_reg + offset) as i8) as i64
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn load_i8(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *r.add(result_reg) = (*(addr as *const i8) as i64) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// i16ロード（符号拡張）
+    /// *result_reg = (*(heep_ptr(*id_reg) + *addr_reg + offset) as i16) as i64
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn load_i16(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *r.add(result_reg) = (*(addr as *const i16) as i64) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// i32ロード（符号拡張）
+    /// *result_reg = (*(heep_ptr(*id_reg) + *addr_reg + offset) as i32) as i64
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn load_i32(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *r.add(result_reg) = (*(addr as *const i32) as i64) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// i64ロード（符号拡張）
+    /// *result_reg = (*(heep_ptr(*id_reg) + *addr_reg + offset) as i64) as u64
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn load_i64(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *r.add(result_reg) = *(addr as *const i64) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// i8ストア（符号拡張）
+    /// *(heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg as i8
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn store_i8(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *(addr as *mut i8) = *r.add(src_reg) as i8;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// i16ストア（符号拡張）
+    /// *(heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg as i16
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn store_i16(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *(addr as *mut i16) = *r.add(src_reg) as i16;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// i32ストア（符号拡張）
+    /// *(heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg as i32
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn store_i32(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *(addr as *mut i32) = *r.add(src_reg) as i32;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// i64ストア（符号拡張）
+    /// *(heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg as i64
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn store_i64(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            *(addr as *mut i64) = *r.add(src_reg) as i64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i8 ロード（符号拡張）
+    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset) as i8 as i64
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_load_i8(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *const AtomicU8;
+            *r.add(result_reg) = ((*atomic_ptr).load(load_ordering(idr_ptr_res)) as i8) as i64 as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i16 ロード（符号拡張）
+    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset) as i16 as i64
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_load_i16(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *const AtomicU16;
+            *r.add(result_reg) = ((*atomic_ptr).load(load_ordering(idr_ptr_res)) as i16) as i64 as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i32 ロード（符号拡張）
+    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset) as i32 as i64
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_load_i32(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *const AtomicU32;
+            *r.add(result_reg) = ((*atomic_ptr).load(load_ordering(idr_ptr_res)) as i32) as i64 as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i64 ロード（符号拡張）
+    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset) as i64 as u64
+    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_load_i64(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
+        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
+        let result_reg = (idr_ptr_res & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *const AtomicU64;
+            *r.add(result_reg) = ((*atomic_ptr).load(load_ordering(idr_ptr_res)) as i64) as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i8 ストア（符号拡張）
+    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_store_i8(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            (*atomic_ptr).store(*r.add(src_reg) as i8 as u8, store_ordering(idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i16 ストア（符号拡張）
+    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_store_i16(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            (*atomic_ptr).store(*r.add(src_reg) as i16 as u16, store_ordering(idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i32 ストア（符号拡張）
+    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_store_i32(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            (*atomic_ptr).store(*r.add(src_reg) as i32 as u32, store_ordering(idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i64 ストア（符号拡張）
+    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_store_i64(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
+        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            (*atomic_ptr).store(*r.add(src_reg) as i64 as u64, store_ordering(idr_ptr_src));
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i8 加算（符号拡張）
+    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i8 as i64
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_add_i8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            *r.add(result_reg) = (*atomic_ptr)
+                .fetch_add(*r.add(src_reg) as i8 as u8, ordering(res_idr_ptr_src))
+                as i8 as i64 as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i16 加算（符号拡張）
+    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i16 as i64
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_add_i16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            *r.add(result_reg) = (*atomic_ptr)
+                .fetch_add(*r.add(src_reg) as i16 as u16, ordering(res_idr_ptr_src))
+                as i16 as i64 as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i32 加算（符号拡張）
+    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i32 as i64
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_add_i32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            *r.add(result_reg) = (*atomic_ptr)
+                .fetch_add(*r.add(src_reg) as i32 as u32, ordering(res_idr_ptr_src))
+                as i32 as i64 as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i64 加算（符号拡張）
+    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i64 as u64
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_add_i64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr)
+                .fetch_add(*r.add(src_reg) as i64 as u64, ordering(res_idr_ptr_src))
+                as i64 as u64;
+        }
+        vm.st.pc += 1; // fallthrough
+    }
+
+    /// atomic i8 減算（符号拡張）
+    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i8 as i64
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn add_f64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn atomic_sub_i8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let result = f64::from_bits(*r.add(dst as usize)) + f64::from_bits(imm);
-            *r.add(dst as usize) = result.to_bits();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            *r.add(result_reg) = (*atomic_ptr)
+                .fetch_sub(*r.add(src_reg) as i8 as u8, ordering(res_idr_ptr_src))
+                as i8 as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit浮動小数点減算
-    /// *dst = *dst - *src
+    /// atomic i16 減算（符号拡張）
+    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i16 as i64
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn sub_f64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn atomic_sub_i16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let result =
-                f64::from_bits(*r.add(dst as usize)) - f64::from_bits(*r.add(src as usize));
-            *r.add(dst as usize) = result.to_bits();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            *r.add(result_reg) = (*atomic_ptr)
+                .fetch_sub(*r.add(src_reg) as i16 as u16, ordering(res_idr_ptr_src))
+                as i16 as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit浮動小数点減算
-    /// *dst = *dst - imm
+    /// atomic i32 減算（符号拡張）
+    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i32 as i64
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn sub_f64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn atomic_sub_i32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let result = f64::from_bits(*r.add(dst as usize)) - f64::from_bits(imm);
-            *r.add(dst as usize) = result.to_bits();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            *r.add(result_reg) = (*atomic_ptr)
+                .fetch_sub(*r.add(src_reg) as i32 as u32, ordering(res_idr_ptr_src))
+                as i32 as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit浮動小数点乗算
-    /// *dst = *dst * *src
+    /// atomic i64 減算（符号拡張）
+    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i64 as u64
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn mul_f64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn atomic_sub_i64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let result =
-                f64::from_bits(*r.add(dst as usize)) * f64::from_bits(*r.add(src as usize));
-            *r.add(dst as usize) = result.to_bits();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr)
+                .fetch_sub(*r.add(src_reg) as i64 as u64, ordering(res_idr_ptr_src))
+                as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
+}
 
-    /// 64bit浮動小数点乗算
-    /// *dst = *dst * imm
+/// 制御系
+impl Operations {
+    /// ジャンプ先に移ります
+    /// r0基準のジャンプ先は検証済みなので、レジスタ基準のときだけ関数の範囲を検査します
     #[inline(always)]
-    pub fn mul_f64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    fn jump_to(vm: &mut VM, base_reg: usize, addr: usize) {
+        if base_reg != 0 && addr >= vm.st.now_function_ptr.instructions.len() {
+            return vm.bad_jump_target(addr);
+        }
+        vm.st.pc = addr;
+    }
+
+    /// ジャンプ
+    /// pc = *dst + offset
+    #[inline(always)]
+    pub fn jump(vm: &mut VM, dst: u64, offset: u64) {
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let result = f64::from_bits(*r.add(dst as usize)) * f64::from_bits(imm);
-            *r.add(dst as usize) = result.to_bits();
+            let addr = (*r.add(dst as usize)).wrapping_add(offset) as usize;
+            Operations::jump_to(vm, dst as usize, addr);
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit浮動小数点除算
-    /// *dst = *dst / *src
+    /// 等しい場合のジャンプ
+    /// if *a == *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn div_f64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn eq_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let result =
-                f64::from_bits(*r.add(dst as usize)) / f64::from_bits(*r.add(src as usize));
-            *r.add(dst as usize) = result.to_bits();
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if *r.add(a) == *r.add(b) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit浮動小数点除算
-    /// *dst = *dst / imm
+    /// 等しくない場合のジャンプ
+    /// if *a != *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn div_f64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn neq_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let result = f64::from_bits(*r.add(dst as usize)) / f64::from_bits(imm);
-            *r.add(dst as usize) = result.to_bits();
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if *r.add(a) != *r.add(b) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit浮動小数点絶対値
-    /// *dst = abs(*src)
+    /// より小さい場合のジャンプ (符号なし)
+    /// if *a < *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn abs_f64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn lt_u64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let result = f64::from_bits(*r.add(src as usize)).abs();
-            *r.add(dst as usize) = result.to_bits();
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if *r.add(a) < *r.add(b) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit浮動小数点符号反転
-    /// *dst = -(*src)
+    /// より小さいか等しい場合のジャンプ (符号なし)
+    /// if *a <= *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn neg_f64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn lte_u64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let result = -f64::from_bits(*r.add(src as usize));
-            *r.add(dst as usize) = result.to_bits();
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if *r.add(a) <= *r.add(b) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit浮動小数点整数変換
+    /// より小さい場合のジャンプ (符号付き)
+    /// if *a < *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn to_i64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn lt_i64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = f64::from_bits(*r.add(src as usize)) as i64 as u64;
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if (*r.add(a) as i64) < (*r.add(b) as i64) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
-}
 
-/// 論理演算
-impl Operations {
-    /// 64bit論理積
-    /// *dst = *dst & *src
+    /// より小さいか等しい場合のジャンプ (符号付き)
+    /// if *a <= *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn and_u64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn lte_i64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if (*r.add(a) as i64) <= (*r.add(b) as i64) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
+        }
+    }
+
+    /// より大きい場合のジャンプ (符号なし)
+    /// if *a > *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    #[inline(always)]
+    pub fn gt_u64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if *r.add(a) > *r.add(b) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
+        }
+    }
+
+    /// より大きいか等しい場合のジャンプ (符号なし)
+    /// if *a >= *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    #[inline(always)]
+    pub fn gte_u64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if *r.add(a) >= *r.add(b) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
+        }
+    }
+
+    /// より大きい場合のジャンプ (符号付き)
+    /// if *a > *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    #[inline(always)]
+    pub fn gt_i64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
+        unsafe {
+            let r = vm.st.r.as_mut_ptr();
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if (*r.add(a) as i64) > (*r.add(b) as i64) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
+        }
+    }
+
+    /// より大きいか等しい場合のジャンプ (符号付き)
+    /// if *a >= *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    #[inline(always)]
+    pub fn gte_i64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) & *r.add(src as usize);
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if (*r.add(a) as i64) >= (*r.add(b) as i64) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit論理積
-    /// *dst = *dst & imm
+    /// より小さい場合のジャンプ (f64)
+    /// if *a < *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// どちらかがNaNならジャンプしない
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn and_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn lt_f64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) & imm;
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if f64::from_bits(*r.add(a)) < f64::from_bits(*r.add(b)) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit論理和
-    /// *dst = *dst | *src
+    /// より小さいか等しい場合のジャンプ (f64)
+    /// if *a <= *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// どちらかがNaNならジャンプしない
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn or_u64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn lte_f64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) | *r.add(src as usize);
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if f64::from_bits(*r.add(a)) <= f64::from_bits(*r.add(b)) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit論理和
-    /// *dst = *dst | imm
+    /// より大きい場合のジャンプ (f64)
+    /// if *a > *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// どちらかがNaNならジャンプしない
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn or_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn gt_f64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) | imm;
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if f64::from_bits(*r.add(a)) > f64::from_bits(*r.add(b)) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit排他的論理和
-    /// *dst = *dst ^ *src
+    /// より大きいか等しい場合のジャンプ (f64)
+    /// if *a >= *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// どちらかがNaNならジャンプしない
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn xor_u64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn gte_f64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) ^ *r.add(src as usize);
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if f64::from_bits(*r.add(a)) >= f64::from_bits(*r.add(b)) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit排他的論理和
-    /// *dst = *dst ^ imm
+    /// 等しい場合のジャンプ (f64)
+    /// if *a == *b { pc = *addr_reg + offset } else { pc += 1 }
+    /// どちらかがNaNならジャンプしない
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn xor_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn eq_f64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) ^ imm;
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if f64::from_bits(*r.add(a)) == f64::from_bits(*r.add(b)) {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit論理否定
-    /// *dst = !*src
+    /// どちらかがNaNの場合のジャンプ (f64)
+    /// if isnan(*a) || isnan(*b) { pc = *addr_reg + offset } else { pc += 1 }
+    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn not_u64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn unordered_f64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
+        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
+        let a = ((addr_a_b >> 8) & 0xFF) as usize;
+        let b = (addr_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = !*r.add(src as usize);
+            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
+            if f64::from_bits(*r.add(a)).is_nan() || f64::from_bits(*r.add(b)).is_nan() {
+                Operations::jump_to(vm, addr_reg, addr);
+            } else {
+                vm.st.pc += 1; // fallthrough
+            }
         }
-        vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit論理左シフト
-    /// *dst = *dst << *src
+    /// 関数呼び出し
+    /// call func_index
+    /// set pc ( 普通は関数先頭アドレスで0 )
+    /// 未デコードの関数の途中に入る場合は先にデコードします
     #[inline(always)]
-    pub fn shl_u64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) << (*r.add(src as usize) as u32);
+    pub fn call(vm: &mut VM, func_index: u64, pc: u64) {
+        let Some(mut function_ptr) = vm.function_ptr(func_index) else {
+            return vm.trap(TrapKind::BadFunctionIndex(func_index));
+        };
+        if pc as usize >= function_ptr.instructions.len() {
+            let Some(decoded) = Operations::decode_before_entry(vm, func_index, function_ptr) else {
+                return;
+            };
+            // 呼び出し先のpcは検証できないので、ここで範囲を検査する
+            if pc as usize >= decoded.instructions.len() {
+                return vm.bad_jump_target(pc as usize);
+            }
+            function_ptr = decoded;
         }
-        vm.st.pc += 1; // fallthrough
+        vm.st.call_stack.push(vm.st.pc);
+        vm.st.call_stack.push(vm.st.now_call_index);
+        vm.st.pc = pc as usize;
+        vm.st.now_call_index = func_index as usize;
+        vm.st.now_function_ptr = function_ptr;
     }
 
-    /// 64bit論理左シフト
-    /// *dst = *dst << imm
+    /// 関数リターン
+    /// ret
+    /// CALL_ARGSで呼ばれていた場合は呼び出し元のレジスタを復元します
+    /// コルーチンの最下段なら0を返してコルーチンを終了します
     #[inline(always)]
-    pub fn shl_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) << (imm as u32);
+    pub fn ret(vm: &mut VM, _: u64, _: u64) {
+        if vm.st.call_stack.len() < 2 {
+            return Operations::finish_coroutine(vm, 0);
         }
-        vm.st.pc += 1; // fallthrough
+        if let Some(frame) = vm.st.frames.last()
+            && frame.depth + 2 == vm.st.call_stack.len()
+        {
+            vm.st.r = vm.st.frames.pop().unwrap().regs;
+        }
+        vm.st.now_call_index = vm.st.call_stack.pop().unwrap();
+        vm.st.pc = vm.st.call_stack.pop().unwrap() + 1;
+        vm.st.now_function_ptr = vm.function_table[vm.st.now_call_index];
     }
 
-    /// 64bit算術左シフト
-    /// *dst = *dst << *src
-    #[inline(always)]
-    pub fn shl_i64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) =
-                ((*r.add(dst as usize) as i64) << (*r.add(src as usize) as u32)) as u64;
+    /// 差し替え関数は命令が1つしかないので、pc != 0 で入る前にデコードした関数を取ります
+    /// 差し替え関数でなければそのまま返します
+    #[cold]
+    fn decode_before_entry(vm: &mut VM, func_index: u64, function_ptr: FunctionPtr) -> Option<FunctionPtr> {
+        let Some(&Instruction::GetDecode(decode_id, deep)) = function_ptr.instructions.first() else {
+            return Some(function_ptr);
+        };
+        match vm.cm.get_decode(decode_id, deep) {
+            Ok(function_table) => vm.function_table = function_table,
+            Err(err) => {
+                vm.trap(TrapKind::DecodeFailed(err.to_string()));
+                return None;
+            }
         }
-        vm.st.pc += 1; // fallthrough
+        Some(vm.function_table[func_index as usize])
+    }
+
+    /// 引数付き関数呼び出し
+    /// 呼び出し元のレジスタを退避し、*arg_reg から count 個を callee の r1.. にコピーします
+    /// RET_VALUE の値は呼び出し元の ret_reg に返ります
+    #[inline(always)]
+    pub fn call_args(vm: &mut VM, func_index: u64, ret_arg_count: u64) {
+        let ret_reg = ((ret_arg_count >> 16) & 0xFF) as usize;
+        let arg_reg = ((ret_arg_count >> 8) & 0xFF) as usize;
+        let count = (ret_arg_count & 0xFF) as usize;
+        let Some(function_ptr) = vm.function_ptr(func_index) else {
+            return vm.trap(TrapKind::BadFunctionIndex(func_index));
+        };
+        if vm.st.frames.len() >= vm.st.max_frames {
+            return vm.trap(TrapKind::CallStackOverflow);
+        }
+        vm.st.frames.push(Frame {
+            regs: vm.st.r,
+            ret_reg,
+            depth: vm.st.call_stack.len(),
+        });
+        vm.st.r.copy_within(arg_reg..arg_reg + count, 1);
+        vm.st.call_stack.push(vm.st.pc);
+        vm.st.call_stack.push(vm.st.now_call_index);
+        vm.st.pc = 0;
+        vm.st.now_call_index = func_index as usize;
+        vm.st.now_function_ptr = function_ptr;
     }
 
-    /// 64bit算術左シフト
-    /// *dst = *dst << imm
+    /// レジスタ経由の関数呼び出し
+    /// call *func_reg
+    /// 関数indexが関数テーブルの範囲外ならtrapします
     #[inline(always)]
-    pub fn shl_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = ((*r.add(dst as usize) as i64) << (imm as u32)) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
+    pub fn call_reg(vm: &mut VM, func_reg: u64, pc: u64) {
+        let func_index = vm.st.r[func_reg as usize];
+        Operations::call(vm, func_index, pc);
     }
 
-    /// 64bit論理右シフト
-    /// *dst = *dst >> *src
+    /// レジスタ経由の引数付き関数呼び出し
+    /// *ret_reg = (*func_reg)(*arg_reg ..count)
     #[inline(always)]
-    pub fn shr_u64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) >> (*r.add(src as usize) as u32);
-        }
-        vm.st.pc += 1; // fallthrough
+    pub fn call_reg_args(vm: &mut VM, func_reg: u64, ret_arg_count: u64) {
+        let func_index = vm.st.r[func_reg as usize];
+        Operations::call_args(vm, func_index, ret_arg_count);
     }
 
-    /// 64bit論理右シフト
-    /// *dst = *dst >> imm
+    /// 関数indexのロード
+    /// *dst = func_index
     #[inline(always)]
-    pub fn shr_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(dst as usize) >> (imm as u32);
-        }
+    pub fn load_func_index(vm: &mut VM, dst: u64, func_index: u64) {
+        vm.st.r[dst as usize] = func_index;
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit算術右シフト
-    /// *dst = *dst >> *src
+    /// 値付き関数リターン
+    /// CALL_ARGSで呼ばれていた場合はレジスタを復元し、呼び出し元の ret_reg = *src
+    /// CALLで呼ばれていた場合はRETと同じです
+    /// コルーチンの最下段なら *src を返してコルーチンを終了します
     #[inline(always)]
-    pub fn shr_i64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) =
-                ((*r.add(dst as usize) as i64) >> (*r.add(src as usize) as u32)) as u64;
+    pub fn ret_value(vm: &mut VM, src: u64, _: u64) {
+        if vm.st.call_stack.len() < 2 {
+            return Operations::finish_coroutine(vm, vm.st.r[src as usize]);
         }
-        vm.st.pc += 1; // fallthrough
+        let value = vm.st.r[src as usize];
+        if let Some(frame) = vm.st.frames.last()
+            && frame.depth + 2 == vm.st.call_stack.len()
+        {
+            let frame = vm.st.frames.pop().unwrap();
+            vm.st.r = frame.regs;
+            vm.st.r[frame.ret_reg] = value;
+        }
+        vm.st.now_call_index = vm.st.call_stack.pop().unwrap();
+        vm.st.pc = vm.st.call_stack.pop().unwrap() + 1;
+        vm.st.now_function_ptr = vm.function_table[vm.st.now_call_index];
     }
+}
 
-    /// 64bit算術右シフト
-    /// *dst = *dst >> imm
+/// IO操作
+impl Operations {
+    /// 整数の出力
+    /// print_u64 *src
     #[inline(always)]
-    pub fn shr_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn print_u64(vm: &mut VM, src: u64, _: u64) {
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = ((*r.add(dst as usize) as i64) >> (imm as u32)) as u64;
+            println!("{}", *r.add(src as usize));
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit論理左ローテート
-    /// *dst = rol(*dst, *src)
+    /// allocate memory
+    /// allocate *size + add_size, store id in *id_res_reg
+    /// size_idr: [ size_reg(8bit) | id_res_reg(8bit) ]
     #[inline(always)]
-    pub fn rol_u64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn alloc(vm: &mut VM, size_idr: u64, add_size: u64) {
+        let size_reg = ((size_idr >> 8) & 0xFF) as usize;
+        let id_res_reg = (size_idr & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let value = *r.add(dst as usize);
-            let shift = (*r.add(src as usize) & 0b111_1111) as u32;
-            *r.add(dst as usize) = value.rotate_left(shift);
+            let size = (*r.add(size_reg)).wrapping_add(add_size) as usize;
+            match vm.st.mem.alloc_heep(size) {
+                Ok(id) => *r.add(id_res_reg) = id,
+                Err(kind) => return vm.trap(kind),
+            }
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit論理左ローテート
-    /// *dst = rol(*dst, imm)
+    /// reallocate memory
+    /// reallocate *size for *id
     #[inline(always)]
-    pub fn rol_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn realloc(vm: &mut VM, size: u64, id: u64) {
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let value = *r.add(dst as usize);
-            let shift = (imm & 0b111_1111) as u32;
-            *r.add(dst as usize) = value.rotate_left(shift);
+            let size = *r.add(size as usize) as usize;
+            let id = *r.add(id as usize);
+            if let Err(kind) = vm.st.mem.realloc_heep(id, size) {
+                return vm.trap(kind);
+            }
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit算術左ローテート
-    /// *dst = rol(*dst, *src)
+    /// deallocate memory
+    /// deallocate *id
     #[inline(always)]
-    pub fn rol_i64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn dealloc(vm: &mut VM, id: u64, _: u64) {
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let value = *r.add(dst as usize) as i64;
-            let shift = (*r.add(src as usize) & 0b111_1111) as u32;
-            *r.add(dst as usize) = value.rotate_left(shift) as u64;
+            let id = *r.add(id as usize);
+            if let Err(kind) = vm.st.mem.dealloc_heep(id) {
+                return vm.trap(kind);
+            }
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit算術左ローテート
-    /// *dst = rol(*dst, imm)
+    // read file to memory
+    // heep_id path_ptr path_size
+
+    /// プログラム終了
+    /// exit with code *code_reg
     #[inline(always)]
-    pub fn rol_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn exit(vm: &mut VM, code_reg: u64, _: u64) {
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let value = *r.add(dst as usize) as i64;
-            let shift = (imm & 0b111_1111) as u32;
-            *r.add(dst as usize) = value.rotate_left(shift) as u64;
+            let code = *r.add(code_reg as usize);
+            vm.st.exit_code = code; // r0は固定値なので別に持つ
+            vm.st.state_flag |= state_flag::PAUSE;
         }
-        vm.st.pc += 1; // fallthrough
     }
+}
 
-    /// 64bit論理右ローテート
-    /// *dst = ror(*dst, *src)
+/// 特殊制御
+impl Operations {
+    /// LocalDecodedByteCodeの更新
+    /// 呼び出された場合CodeManagerにデコードを依頼し、VMのFuctionTableを更新します
+    /// Code Manager は未デコードのfunctionをこれに置き換えます。
+    /// 差し替え後の関数を同じpcから実行し直すので pc は進めません
     #[inline(always)]
-    pub fn ror_u64(vm: &mut VM, dst: u64, src: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let value = *r.add(dst as usize);
-            let shift = (*r.add(src as usize) & 0b111_1111) as u32;
-            *r.add(dst as usize) = value.rotate_right(shift);
+    pub fn get_decode(vm: &mut VM, decode_id: u64, deep: u64) {
+        match vm.cm.get_decode(decode_id, deep) {
+            Ok(function_table) => vm.function_table = function_table,
+            Err(err) => return vm.trap(TrapKind::DecodeFailed(err.to_string())),
         }
-        vm.st.pc += 1; // fallthrough
+        vm.st.now_function_ptr = vm.function_table[vm.st.now_call_index];
     }
 
-    /// 64bit論理右ローテート
-    /// *dst = ror(*dst, imm)
+    /// 最新のデコード済みByteCodeを取得
     #[inline(always)]
-    pub fn ror_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let value = *r.add(dst as usize);
-            let shift = (imm & 0b111_1111) as u32;
-            *r.add(dst as usize) = value.rotate_right(shift);
+    pub fn get_decoded(vm: &mut VM, _:u64, _: u64) {
+        vm.function_table = vm.cm.get_decoded();
+        vm.st.pc += 1; // fallthrough
+    }
+}
+
+/// ホスト関数
+impl Operations {
+    /// ホスト関数呼び出し
+    /// *ret_reg = natives[native_index](*arg_reg, *(arg_reg + 1), .. count個)
+    /// ホスト関数がErrを返した場合はtrapします
+    #[inline(always)]
+    pub fn native_call(vm: &mut VM, native_index: u64, ret_arg_count: u64) {
+        let ret_reg = ((ret_arg_count >> 16) & 0xFF) as usize;
+        let arg_reg = ((ret_arg_count >> 8) & 0xFF) as usize;
+        let count = (ret_arg_count & 0xFF) as usize;
+        let Some(native) = vm.native(native_index) else {
+            return vm.trap(TrapKind::BadNativeIndex(native_index));
+        };
+        // nativeは &mut VM を取るので、引数はスタックにコピーして渡す
+        let mut args = [0u64; 256];
+        args[..count].copy_from_slice(&vm.st.r[arg_reg..arg_reg + count]);
+        match native(vm, &args[..count]) {
+            Ok(value) => vm.st.r[ret_reg] = value,
+            Err(kind) => return vm.trap(kind),
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit算術右ローテート
-    /// *dst = ror(*dst, *src)
+    /// 非同期ホスト関数呼び出し
+    /// ホスト関数はI/Oを始めて待ち合わせ用のトークンを返し、VMはPAUSEで止まって `RunStatus::Pending(token)` を返します
+    /// `VM::complete(token, value)` の後に再度runすると *ret_reg = value で続きから実行します
+    /// VMPoolのスレッドでは再開する人がいないのでtrapします
+    /// ret_arg_count: [ ret_reg(8bit) | arg_reg(8bit) | count(8bit) ]
     #[inline(always)]
-    pub fn ror_i64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn native_call_async(vm: &mut VM, native_index: u64, ret_arg_count: u64) {
+        if vm.threads.upgrade().is_some() {
+            return vm.trap(TrapKind::NotInExecutor);
+        }
+        let ret_reg = ((ret_arg_count >> 16) & 0xFF) as usize;
+        let arg_reg = ((ret_arg_count >> 8) & 0xFF) as usize;
+        let count = (ret_arg_count & 0xFF) as usize;
+        let Some(native) = vm.native(native_index) else {
+            return vm.trap(TrapKind::BadNativeIndex(native_index));
+        };
+        let mut args = [0u64; 256];
+        args[..count].copy_from_slice(&vm.st.r[arg_reg..arg_reg + count]);
+        match native(vm, &args[..count]) {
+            Ok(token) => vm.st.pending = Some(Pending { token, ret_reg }),
+            Err(kind) => return vm.trap(kind),
+        }
+        vm.st.state_flag |= state_flag::PAUSE;
+        vm.st.pc += 1; // 再開したら次の命令から
+    }
+}
+
+/// 比較結果のセットと条件付きmove
+/// 分岐なしで真偽値やmin/maxを計算するためのもの
+impl Operations {
+    /// 等しいかを0/1で書き込みます
+    /// *dst = (*a == *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
+    #[inline(always)]
+    pub fn set_eq(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let value = *r.add(dst as usize) as i64;
-            let shift = (*r.add(src as usize) & 0b111_1111) as u32;
-            *r.add(dst as usize) = value.rotate_right(shift) as u64;
+            *r.add(dst) = (*r.add(a) == *r.add(b)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 64bit算術右ローテート
-    /// *dst = ror(*dst, imm)
+    /// 等しくないかを0/1で書き込みます
+    /// *dst = (*a != *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn ror_i64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn set_neq(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let value = *r.add(dst as usize) as i64;
-            let shift = (imm & 0b111_1111) as u32;
-            *r.add(dst as usize) = value.rotate_right(shift) as u64;
+            *r.add(dst) = (*r.add(a) != *r.add(b)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// bitcount 1
-    /// *dst = count_ones(*src)
+    /// より小さいか (符号なし)を0/1で書き込みます
+    /// *dst = (*a < *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn count_ones_u64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn set_lt_u64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(src as usize)).count_ones() as u64;
+            *r.add(dst) = (*r.add(a) < *r.add(b)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// bitcount 0
-    /// *dst = count_zeros(*src)
+    /// より小さいか等しいか (符号なし)を0/1で書き込みます
+    /// *dst = (*a <= *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn count_zeros_u64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn set_lte_u64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(src as usize)).count_zeros() as u64;
+            *r.add(dst) = (*r.add(a) <= *r.add(b)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// trailing zeros
-    /// *dst = trailing_zeros(*src)
+    /// より小さいか (符号付き)を0/1で書き込みます
+    /// *dst = (*a < *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn trailing_zeros_u64(vm: &mut VM, dst: u64, src: u64) {
+    pub fn set_lt_i64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = (*r.add(src as usize)).trailing_zeros() as u64;
+            *r.add(dst) = ((*r.add(a) as i64) < (*r.add(b) as i64)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
-}
 
-/// レジスタ操作系
-impl Operations {
-    /// レジスタ間値コピー
-    /// *dst = *src
+    /// より小さいか等しいか (符号付き)を0/1で書き込みます
+    /// *dst = (*a <= *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn mov(vm: &mut VM, dst: u64, src: u64) {
+    pub fn set_lte_i64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = *r.add(src as usize);
+            *r.add(dst) = ((*r.add(a) as i64) <= (*r.add(b) as i64)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 即値ロード
-    /// *dst = imm
+    /// より大きいか (符号なし)を0/1で書き込みます
+    /// *dst = (*a > *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn load_u64_immediate(vm: &mut VM, dst: u64, imm: u64) {
+    pub fn set_gt_u64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            *r.add(dst as usize) = imm;
+            *r.add(dst) = (*r.add(a) > *r.add(b)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 交換
-    /// *reg_a, *reg_b = *reg_b, *reg_a
+    /// より大きいか等しいか (符号なし)を0/1で書き込みます
+    /// *dst = (*a >= *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn swap(vm: &mut VM, reg_a: u64, reg_b: u64) {
+    pub fn set_gte_u64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let temp = *r.add(reg_a as usize);
-            *r.add(reg_a as usize) = *r.add(reg_b as usize);
-            *r.add(reg_b as usize) = temp;
+            *r.add(dst) = (*r.add(a) >= *r.add(b)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
-}
 
-/// メモリ操作
-impl Operations {
-    /// u64ロード
-    /// *result_reg = *(heep_ptr(*id_reg) + *addr_reg + offset)
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// より大きいか (符号付き)を0/1で書き込みます
+    /// *dst = (*a > *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn load_u64(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn set_gt_i64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *r.add(result_reg) = *(addr as *const u64);
+            *r.add(dst) = ((*r.add(a) as i64) > (*r.add(b) as i64)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// u32ロード
-    /// *result_reg = *(heep_ptr(*id_reg) + *addr_reg + offset)
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// より大きいか等しいか (符号付き)を0/1で書き込みます
+    /// *dst = (*a >= *b) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn load_u32(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn set_gte_i64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *r.add(result_reg) = *(addr as *const u32) as u64;
+            *r.add(dst) = ((*r.add(a) as i64) >= (*r.add(b) as i64)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// u16ロード
-    /// *result_reg = *(heep_ptr(*id_reg) + *addr_reg + offset)
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// より小さいか (f64)を0/1で書き込みます
+    /// *dst = (*a < *b) as u64
+    /// NaNとの比較は0
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn load_u16(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn set_lt_f64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *r.add(result_reg) = *(addr as *const u16) as u64;
+            *r.add(dst) = (f64::from_bits(*r.add(a)) < f64::from_bits(*r.add(b))) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// u8ロード
-    /// *result_reg = *(heep_ptr(*id_reg) + *addr_reg + offset)
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// より小さいか等しいか (f64)を0/1で書き込みます
+    /// *dst = (*a <= *b) as u64
+    /// NaNとの比較は0
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn load_u8(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn set_lte_f64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *r.add(result_reg) = *(addr as *const u8) as u64;
+            *r.add(dst) = (f64::from_bits(*r.add(a)) <= f64::from_bits(*r.add(b))) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// u64ストア
-    /// * (heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// より大きいか (f64)を0/1で書き込みます
+    /// *dst = (*a > *b) as u64
+    /// NaNとの比較は0
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn store_u64(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn set_gt_f64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *(addr as *mut u64) = *r.add(src_reg);
+            *r.add(dst) = (f64::from_bits(*r.add(a)) > f64::from_bits(*r.add(b))) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// u32ストア
-    /// * (heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// より大きいか等しいか (f64)を0/1で書き込みます
+    /// *dst = (*a >= *b) as u64
+    /// NaNとの比較は0
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn store_u32(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn set_gte_f64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *(addr as *mut u32) = *r.add(src_reg) as u32;
+            *r.add(dst) = (f64::from_bits(*r.add(a)) >= f64::from_bits(*r.add(b))) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// u16ストア
-    /// * (heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// 等しいか (f64)を0/1で書き込みます
+    /// *dst = (*a == *b) as u64
+    /// NaNとの比較は0
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn store_u16(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn set_eq_f64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *(addr as *mut u16) = *r.add(src_reg) as u16;
+            *r.add(dst) = (f64::from_bits(*r.add(a)) == f64::from_bits(*r.add(b))) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// u8ストア
-    /// * (heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// どちらかがNaNか (f64)を0/1で書き込みます
+    /// *dst = (isnan(*a) || isnan(*b)) as u64
+    /// dst_a_b: [ dst(8bit) | a(8bit) | b(8bit) ]
     #[inline(always)]
-    pub fn store_u8(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn set_unordered_f64(vm: &mut VM, dst_a_b: u64, _: u64) {
+        let dst = ((dst_a_b >> 16) & 0xFF) as usize;
+        let a = ((dst_a_b >> 8) & 0xFF) as usize;
+        let b = (dst_a_b & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *(addr as *mut u8) = *r.add(src_reg) as u8;
+            *r.add(dst) = (f64::from_bits(*r.add(a)).is_nan() || f64::from_bits(*r.add(b)).is_nan()) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u64 ロード
-    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset)
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// 条件付きmove
+    /// if *cond != 0 { *dst = *src }
+    /// dst_cond_src: [ dst(8bit) | cond(8bit) | src(8bit) ]
     #[inline(always)]
-    pub fn atomic_load_u64(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn cmov_nz(vm: &mut VM, dst_cond_src: u64, _: u64) {
+        let dst = ((dst_cond_src >> 16) & 0xFF) as usize;
+        let cond = ((dst_cond_src >> 8) & 0xFF) as usize;
+        let src = (dst_cond_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *const AtomicU64;
-            *r.add(result_reg) = (*atomic_ptr).load(Ordering::SeqCst);
+            if *r.add(cond) != 0 {
+                *r.add(dst) = *r.add(src);
+            }
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u64 ストア
-    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// 条件付きmove
+    /// if *cond == 0 { *dst = *src }
+    /// dst_cond_src: [ dst(8bit) | cond(8bit) | src(8bit) ]
     #[inline(always)]
-    pub fn atomic_store_u64(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn cmov_z(vm: &mut VM, dst_cond_src: u64, _: u64) {
+        let dst = ((dst_cond_src >> 16) & 0xFF) as usize;
+        let cond = ((dst_cond_src >> 8) & 0xFF) as usize;
+        let src = (dst_cond_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU64;
-            (*atomic_ptr).store(*r.add(src_reg), Ordering::SeqCst);
+            if *r.add(cond) == 0 {
+                *r.add(dst) = *r.add(src);
+            }
         }
         vm.st.pc += 1; // fallthrough
     }
+}
 
-    /// atomic u64 加算
-    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
-    #[inline(always)]
-    pub fn atomic_add_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
-        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
-        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+/// atomic 読み込み-変更-書き込み
+/// CAS/SWAP/ビット演算/MIN/MAX 結果レジスタには変更前の値が入ります
+impl Operations {
+    /// atomic u64 比較交換
+    /// 値が *result_reg と等しければ *src_reg に置き換えます
+    /// *result_reg = 置き換え前の値, *ok_reg = 置き換えたら1
+    /// ok_res_idr_ptr_src: [ ok_reg(8bit) | result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    #[inline(always)]
+    pub fn atomic_cas_u64(vm: &mut VM, ok_res_idr_ptr_src: u64, offset: u64) {
+        let ok_reg = ((ok_res_idr_ptr_src >> 32) & 0xFF) as usize;
+        let result_reg = ((ok_res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((ok_res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((ok_res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (ok_res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
             let atomic_ptr = addr as *mut AtomicU64;
-            *r.add(result_reg) = (*atomic_ptr).fetch_add(*r.add(src_reg), Ordering::SeqCst);
+            let (old, ok) = match (*atomic_ptr).compare_exchange(
+                *r.add(result_reg),
+                *r.add(src_reg),
+                ordering(ok_res_idr_ptr_src),
+                failure_ordering(ok_res_idr_ptr_src),
+            ) {
+                Ok(old) => (old, 1),
+                Err(old) => (old, 0),
+            };
+            *r.add(result_reg) = old;
+            *r.add(ok_reg) = ok;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u64 減算
-    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u32 比較交換
+    /// 値が *result_reg と等しければ *src_reg に置き換えます
+    /// *result_reg = 置き換え前の値, *ok_reg = 置き換えたら1
+    /// ok_res_idr_ptr_src: [ ok_reg(8bit) | result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_sub_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
-        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
-        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_cas_u32(vm: &mut VM, ok_res_idr_ptr_src: u64, offset: u64) {
+        let ok_reg = ((ok_res_idr_ptr_src >> 32) & 0xFF) as usize;
+        let result_reg = ((ok_res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((ok_res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((ok_res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (ok_res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU64;
-            *r.add(result_reg) = (*atomic_ptr).fetch_sub(*r.add(src_reg), Ordering::SeqCst);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            let (old, ok) = match (*atomic_ptr).compare_exchange(
+                *r.add(result_reg) as u32,
+                *r.add(src_reg) as u32,
+                ordering(ok_res_idr_ptr_src),
+                failure_ordering(ok_res_idr_ptr_src),
+            ) {
+                Ok(old) => (old, 1),
+                Err(old) => (old, 0),
+            };
+            *r.add(result_reg) = old as u64;
+            *r.add(ok_reg) = ok;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u32 ロード
-    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset)
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u16 比較交換
+    /// 値が *result_reg と等しければ *src_reg に置き換えます
+    /// *result_reg = 置き換え前の値, *ok_reg = 置き換えたら1
+    /// ok_res_idr_ptr_src: [ ok_reg(8bit) | result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_load_u32(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_cas_u16(vm: &mut VM, ok_res_idr_ptr_src: u64, offset: u64) {
+        let ok_reg = ((ok_res_idr_ptr_src >> 32) & 0xFF) as usize;
+        let result_reg = ((ok_res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((ok_res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((ok_res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (ok_res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *const AtomicU32;
-            *r.add(result_reg) = (*atomic_ptr).load(Ordering::SeqCst) as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            let (old, ok) = match (*atomic_ptr).compare_exchange(
+                *r.add(result_reg) as u16,
+                *r.add(src_reg) as u16,
+                ordering(ok_res_idr_ptr_src),
+                failure_ordering(ok_res_idr_ptr_src),
+            ) {
+                Ok(old) => (old, 1),
+                Err(old) => (old, 0),
+            };
+            *r.add(result_reg) = old as u64;
+            *r.add(ok_reg) = ok;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u32 ストア
-    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u8 比較交換
+    /// 値が *result_reg と等しければ *src_reg に置き換えます
+    /// *result_reg = 置き換え前の値, *ok_reg = 置き換えたら1
+    /// ok_res_idr_ptr_src: [ ok_reg(8bit) | result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_store_u32(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_cas_u8(vm: &mut VM, ok_res_idr_ptr_src: u64, offset: u64) {
+        let ok_reg = ((ok_res_idr_ptr_src >> 32) & 0xFF) as usize;
+        let result_reg = ((ok_res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((ok_res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((ok_res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (ok_res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU32;
-            (*atomic_ptr).store(*r.add(src_reg) as u32, Ordering::SeqCst);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            let (old, ok) = match (*atomic_ptr).compare_exchange(
+                *r.add(result_reg) as u8,
+                *r.add(src_reg) as u8,
+                ordering(ok_res_idr_ptr_src),
+                failure_ordering(ok_res_idr_ptr_src),
+            ) {
+                Ok(old) => (old, 1),
+                Err(old) => (old, 0),
+            };
+            *r.add(result_reg) = old as u64;
+            *r.add(ok_reg) = ok;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u32 加算
-    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// atomic u64 交換
+    /// *result_reg = atomic_swap(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_add_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_swap_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU32;
-            *r.add(result_reg) =
-                (*atomic_ptr).fetch_add(*r.add(src_reg) as u32, Ordering::SeqCst) as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr).swap(*r.add(src_reg), ordering(res_idr_ptr_src));
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u32 減算
-    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// atomic u32 交換
+    /// *result_reg = atomic_swap(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_sub_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_swap_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
             let atomic_ptr = addr as *mut AtomicU32;
-            *r.add(result_reg) =
-                (*atomic_ptr).fetch_sub(*r.add(src_reg) as u32, Ordering::SeqCst) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
-
-    /// atomic u16 ロード
-    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset)
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
-    #[inline(always)]
-    pub fn atomic_load_u16(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *const AtomicU16;
-            *r.add(result_reg) = (*atomic_ptr).load(Ordering::SeqCst) as u64;
-        }
-        vm.st.pc += 1; // fallthrough
-    }
-
-    /// atomic u16 ストア
-    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
-    #[inline(always)]
-    pub fn atomic_store_u16(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU16;
-            (*atomic_ptr).store(*r.add(src_reg) as u16, Ordering::SeqCst);
+            *r.add(result_reg) = (*atomic_ptr).swap(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u16 加算
-    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// atomic u16 交換
+    /// *result_reg = atomic_swap(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_add_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_swap_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
             let atomic_ptr = addr as *mut AtomicU16;
-            *r.add(result_reg) =
-                (*atomic_ptr).fetch_add(*r.add(src_reg) as u16, Ordering::SeqCst) as u64;
+            *r.add(result_reg) = (*atomic_ptr).swap(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u16 減算
-    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// atomic u8 交換
+    /// *result_reg = atomic_swap(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_sub_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_swap_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU16;
-            *r.add(result_reg) =
-                (*atomic_ptr).fetch_sub(*r.add(src_reg) as u16, Ordering::SeqCst) as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            *r.add(result_reg) = (*atomic_ptr).swap(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u8 ロード
-    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset)
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u64 論理積
+    /// *result_reg = atomic_fetch_and(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_load_u8(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_and_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *const AtomicU8;
-            *r.add(result_reg) = (*atomic_ptr).load(Ordering::SeqCst) as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_and(*r.add(src_reg), ordering(res_idr_ptr_src));
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u8 ストア
-    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u32 論理積
+    /// *result_reg = atomic_fetch_and(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_store_u8(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_and_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU8;
-            (*atomic_ptr).store(*r.add(src_reg) as u8, Ordering::SeqCst);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            *r.add(result_reg) = (*atomic_ptr).fetch_and(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u8 加算
-    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// atomic u16 論理積
+    /// *result_reg = atomic_fetch_and(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_add_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_and_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU8;
-            *r.add(result_reg) =
-                (*atomic_ptr).fetch_add(*r.add(src_reg) as u8, Ordering::SeqCst) as u64;
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            *r.add(result_reg) = (*atomic_ptr).fetch_and(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic u8 減算
-    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// atomic u8 論理積
+    /// *result_reg = atomic_fetch_and(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_sub_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_and_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
             let atomic_ptr = addr as *mut AtomicU8;
-            *r.add(result_reg) =
-                (*atomic_ptr).fetch_sub(*r.add(src_reg) as u8, Ordering::SeqCst) as u64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_and(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// i8ロード（符号拡張）
-    /// *result_reg = (*(heep_ptr(*id_reg) + *addr_reg + offset) as i8) as i64
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u64 論理和
+    /// *result_reg = atomic_fetch_or(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn load_i8(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_or_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *r.add(result_reg) = (*(addr as *const i8) as i64) as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_or(*r.add(src_reg), ordering(res_idr_ptr_src));
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// i16ロード（符号拡張）
-    /// *result_reg = (*(heep_ptr(*id_reg) + *addr_reg + offset) as i16) as i64
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u32 論理和
+    /// *result_reg = atomic_fetch_or(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn load_i16(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_or_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *r.add(result_reg) = (*(addr as *const i16) as i64) as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            *r.add(result_reg) = (*atomic_ptr).fetch_or(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// i32ロード（符号拡張）
-    /// *result_reg = (*(heep_ptr(*id_reg) + *addr_reg + offset) as i32) as i64
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u16 論理和
+    /// *result_reg = atomic_fetch_or(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn load_i32(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_or_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *r.add(result_reg) = (*(addr as *const i32) as i64) as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            *r.add(result_reg) = (*atomic_ptr).fetch_or(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// i64ロード（符号拡張）
-    /// *result_reg = (*(heep_ptr(*id_reg) + *addr_reg + offset) as i64) as u64
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u8 論理和
+    /// *result_reg = atomic_fetch_or(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn load_i64(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_or_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *r.add(result_reg) = (*(addr as *const i64) as i64) as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            *r.add(result_reg) = (*atomic_ptr).fetch_or(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// i8ストア（符号拡張）
-    /// * (heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg as i8
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u64 排他的論理和
+    /// *result_reg = atomic_fetch_xor(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn store_i8(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_xor_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *(addr as *mut i8) = *r.add(src_reg) as i8;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_xor(*r.add(src_reg), ordering(res_idr_ptr_src));
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// i16ストア（符号拡張）
-    /// * (heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg as i16
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u32 排他的論理和
+    /// *result_reg = atomic_fetch_xor(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn store_i16(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_xor_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *(addr as *mut i16) = *r.add(src_reg) as i16;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            *r.add(result_reg) = (*atomic_ptr).fetch_xor(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// i32ストア（符号拡張）
-    /// * (heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg as i32
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u16 排他的論理和
+    /// *result_reg = atomic_fetch_xor(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn store_i32(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_xor_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *(addr as *mut i32) = *r.add(src_reg) as i32;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            *r.add(result_reg) = (*atomic_ptr).fetch_xor(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// i64ストア（符号拡張）
-    /// * (heep_ptr(*id_reg) + *addr_reg + offset) = *src_reg as i64
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u8 排他的論理和
+    /// *result_reg = atomic_fetch_xor(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn store_i64(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_xor_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            *(addr as *mut i64) = *r.add(src_reg) as i64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            *r.add(result_reg) = (*atomic_ptr).fetch_xor(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i8 ロード（符号拡張）
-    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset) as i8 as i64
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u64 最小値
+    /// *result_reg = atomic_fetch_min(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_load_i8(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_min_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *const AtomicU8;
-            *r.add(result_reg) = ((*atomic_ptr).load(Ordering::SeqCst) as i8) as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg), ordering(res_idr_ptr_src));
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i16 ロード（符号拡張）
-    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset) as i16 as i64
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u32 最小値
+    /// *result_reg = atomic_fetch_min(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_load_i16(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_min_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *const AtomicU16;
-            *r.add(result_reg) = ((*atomic_ptr).load(Ordering::SeqCst) as i16) as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i32 ロード（符号拡張）
-    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset) as i32 as i64
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u16 最小値
+    /// *result_reg = atomic_fetch_min(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_load_i32(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_min_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *const AtomicU32;
-            *r.add(result_reg) = ((*atomic_ptr).load(Ordering::SeqCst) as i32) as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i64 ロード（符号拡張）
-    /// *result_reg = atomic_load(heep_ptr(*id_reg) + *addr_reg + offset) as i64 as u64
-    /// idr_ptr_res: [ id_reg(8bit) | addr_reg(8bit) | result_reg(8bit) ]
+    /// atomic u8 最小値
+    /// *result_reg = atomic_fetch_min(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_load_i64(vm: &mut VM, idr_ptr_res: u64, offset: u64) {
-        let id_reg = ((idr_ptr_res >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_res >> 8) & 0xFF) as usize;
-        let result_reg = (idr_ptr_res & 0xFF) as usize;
+    pub fn atomic_min_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *const AtomicU64;
-            *r.add(result_reg) = ((*atomic_ptr).load(Ordering::SeqCst) as i64) as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i8 ストア（符号拡張）
-    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u64 最大値
+    /// *result_reg = atomic_fetch_max(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_store_i8(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_max_u64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU8;
-            (*atomic_ptr).store(*r.add(src_reg) as i8 as u8, Ordering::SeqCst);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg), ordering(res_idr_ptr_src));
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i16 ストア（符号拡張）
-    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u32 最大値
+    /// *result_reg = atomic_fetch_max(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_store_i16(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_max_u32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU16;
-            (*atomic_ptr).store(*r.add(src_reg) as i16 as u16, Ordering::SeqCst);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU32;
+            *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as u32, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i32 ストア（符号拡張）
-    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u16 最大値
+    /// *result_reg = atomic_fetch_max(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_store_i32(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_max_u16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU32;
-            (*atomic_ptr).store(*r.add(src_reg) as i32 as u32, Ordering::SeqCst);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU16;
+            *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as u16, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i64 ストア（符号拡張）
-    /// atomic_store(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
-    /// idr_ptr_src: [ id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
+    /// atomic u8 最大値
+    /// *result_reg = atomic_fetch_max(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
+    /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_store_i64(vm: &mut VM, idr_ptr_src: u64, offset: u64) {
-        let id_reg = ((idr_ptr_src >> 16) & 0xFF) as usize;
-        let addr_reg = ((idr_ptr_src >> 8) & 0xFF) as usize;
-        let src_reg = (idr_ptr_src & 0xFF) as usize;
+    pub fn atomic_max_u8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+        let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
+        let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
+        let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
+        let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU64;
-            (*atomic_ptr).store(*r.add(src_reg) as i64 as u64, Ordering::SeqCst);
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicU8;
+            *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as u8, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i8 加算（符号拡張）
-    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i8 as i64
+    /// atomic i64 最小値（符号付き、符号拡張）
+    /// *result_reg = atomic_fetch_min(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_add_i8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_min_i64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU8;
-            *r.add(result_reg) = (*atomic_ptr)
-                .fetch_add(*r.add(src_reg) as i8 as u8, Ordering::SeqCst)
-                as i8 as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicI64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as i64, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i16 加算（符号拡張）
-    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i16 as i64
+    /// atomic i32 最小値（符号付き、符号拡張）
+    /// *result_reg = atomic_fetch_min(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_add_i16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_min_i32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU16;
-            *r.add(result_reg) = (*atomic_ptr)
-                .fetch_add(*r.add(src_reg) as i16 as u16, Ordering::SeqCst)
-                as i16 as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicI32;
+            *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as i32, ordering(res_idr_ptr_src)) as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i32 加算（符号拡張）
-    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i32 as i64
+    /// atomic i16 最小値（符号付き、符号拡張）
+    /// *result_reg = atomic_fetch_min(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_add_i32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_min_i16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU32;
-            *r.add(result_reg) = (*atomic_ptr)
-                .fetch_add(*r.add(src_reg) as i32 as u32, Ordering::SeqCst)
-                as i32 as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicI16;
+            *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as i16, ordering(res_idr_ptr_src)) as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i64 加算（符号拡張）
-    /// *result_reg = atomic_fetch_add(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i64 as u64
+    /// atomic i8 最小値（符号付き、符号拡張）
+    /// *result_reg = atomic_fetch_min(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_add_i64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_min_i8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU64;
-            *r.add(result_reg) = (*atomic_ptr)
-                .fetch_add(*r.add(src_reg) as i64 as u64, Ordering::SeqCst)
-                as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicI8;
+            *r.add(result_reg) = (*atomic_ptr).fetch_min(*r.add(src_reg) as i8, ordering(res_idr_ptr_src)) as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i8 減算（符号拡張）
-    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i8 as i64
+    /// atomic i64 最大値（符号付き、符号拡張）
+    /// *result_reg = atomic_fetch_max(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_sub_i8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_max_i64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU8;
-            *r.add(result_reg) = (*atomic_ptr)
-                .fetch_sub(*r.add(src_reg) as i8 as u8, Ordering::SeqCst)
-                as i8 as i64 as u64;
+            let r = vm.st.r.as_mut_ptr();
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 8) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicI64;
+            *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as i64, ordering(res_idr_ptr_src)) as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i16 減算（符号拡張）
-    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i16 as i64
+    /// atomic i32 最大値（符号付き、符号拡張）
+    /// *result_reg = atomic_fetch_max(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_sub_i16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_max_i32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU16;
-            *r.add(result_reg) = (*atomic_ptr)
-                .fetch_sub(*r.add(src_reg) as i16 as u16, Ordering::SeqCst)
-                as i16 as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 4) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicI32;
+            *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as i32, ordering(res_idr_ptr_src)) as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i32 減算（符号拡張）
-    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i32 as i64
+    /// atomic i16 最大値（符号付き、符号拡張）
+    /// *result_reg = atomic_fetch_max(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_sub_i32(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_max_i16(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU32;
-            *r.add(result_reg) = (*atomic_ptr)
-                .fetch_sub(*r.add(src_reg) as i32 as u32, Ordering::SeqCst)
-                as i32 as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 2) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicI16;
+            *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as i16, ordering(res_idr_ptr_src)) as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// atomic i64 減算（符号拡張）
-    /// *result_reg = atomic_fetch_sub(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg) as i64 as u64
+    /// atomic i8 最大値（符号付き、符号拡張）
+    /// *result_reg = atomic_fetch_max(heep_ptr(*id_reg) + *addr_reg + offset, *src_reg)
     /// idr_ptr_src: [ result_reg(8bit) | id_reg(8bit) | addr_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn atomic_sub_i64(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
+    pub fn atomic_max_i8(vm: &mut VM, res_idr_ptr_src: u64, offset: u64) {
         let result_reg = ((res_idr_ptr_src >> 24) & 0xFF) as usize;
         let id_reg = ((res_idr_ptr_src >> 16) & 0xFF) as usize;
         let addr_reg = ((res_idr_ptr_src >> 8) & 0xFF) as usize;
         let src_reg = (res_idr_ptr_src & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let heep_ptr = vm.st.mem.head_ptr(*r.add(id_reg));
-            let addr = ((*r.add(addr_reg)).wrapping_add(offset) as usize).wrapping_add(heep_ptr);
-            let atomic_ptr = addr as *mut AtomicU64;
-            *r.add(result_reg) = (*atomic_ptr)
-                .fetch_sub(*r.add(src_reg) as i64 as u64, Ordering::SeqCst)
-                as i64 as u64;
+            let addr = match vm.st.mem.heep_addr(*r.add(id_reg), (*r.add(addr_reg)).wrapping_add(offset) as usize, 1) {
+                Ok(addr) => addr,
+                Err(kind) => return vm.trap(kind),
+            };
+            let atomic_ptr = addr as *mut AtomicI8;
+            *r.add(result_reg) = (*atomic_ptr).fetch_max(*r.add(src_reg) as i8, ordering(res_idr_ptr_src)) as i64 as u64;
         }
         vm.st.pc += 1; // fallthrough
     }
 }
 
-/// 制御系
+/// メモリフェンス
 impl Operations {
-    /// ジャンプ
-    /// pc = *dst + offset
+    /// メモリフェンス
+    /// ordering: [ ordering(8bit) | 0(56bit) ] 指定なしはSeqCst
     #[inline(always)]
-    pub fn jump(vm: &mut VM, dst: u64, offset: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            vm.st.pc = (*r.add(dst as usize)).wrapping_add(offset) as usize;
-        }
+    pub fn fence(vm: &mut VM, ordering: u64, _: u64) {
+        std::sync::atomic::fence(fence_ordering(ordering));
+        vm.st.pc += 1; // fallthrough
     }
+}
 
-    /// 等しい場合のジャンプ
-    /// if *a == *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+/// 共有heep
+/// VMPool内のVMで共有するheep 各VMはハンドルを自分のheep idにmapして、通常のLOAD/STORE/ATOMICで読み書きします
+/// DEALLOCはこのVMからのmapを外し、どのVMからもmapされなくなると解放されます
+impl Operations {
+    /// 共有heepを確保してmapする 中身は0で初期化される
+    /// allocate shared *size + add_size, store id in *id_res_reg
+    /// size_idr: [ size_reg(8bit) | id_res_reg(8bit) ]
     #[inline(always)]
-    pub fn eq_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
+    pub fn shared_alloc(vm: &mut VM, size_idr: u64, add_size: u64) {
+        let size_reg = ((size_idr >> 8) & 0xFF) as usize;
+        let id_res_reg = (size_idr & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if *r.add(a) == *r.add(b) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
+            let size = (*r.add(size_reg)).wrapping_add(add_size) as usize;
+            match vm.st.mem.alloc_shared_heep(size) {
+                Ok(id) => *r.add(id_res_reg) = id,
+                Err(kind) => return vm.trap(kind),
             }
         }
+        vm.st.pc += 1; // fallthrough
     }
 
-    /// 等しくない場合のジャンプ
-    /// if *a != *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    /// 共有heepのハンドルをmapする
+    /// *dst = map(*handle)
     #[inline(always)]
-    pub fn neq_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
+    pub fn shared_map(vm: &mut VM, dst: u64, handle: u64) {
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if *r.add(a) != *r.add(b) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
+            match vm.st.mem.map_shared_heep(*r.add(handle as usize)) {
+                Ok(id) => *r.add(dst as usize) = id,
+                Err(kind) => return vm.trap(kind),
             }
         }
+        vm.st.pc += 1; // fallthrough
     }
 
-    /// より小さい場合のジャンプ (符号なし)
-    /// if *a < *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    /// mapした共有heepのハンドル 他のVMに渡してmapしてもらう
+    /// *dst = handle(*id)
     #[inline(always)]
-    pub fn lt_u64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
+    pub fn shared_handle(vm: &mut VM, dst: u64, id: u64) {
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if *r.add(a) < *r.add(b) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
+            match vm.st.mem.shared_handle(*r.add(id as usize)) {
+                Ok(handle) => *r.add(dst as usize) = handle,
+                Err(kind) => return vm.trap(kind),
             }
         }
+        vm.st.pc += 1; // fallthrough
     }
+}
 
-    /// より小さいか等しい場合のジャンプ (符号なし)
-    /// if *a <= *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+/// チャネル
+/// VMPool内のVMでu64やheepを送り合う
+/// *status_reg には `channel::status` の状態コード (0: OK, 1: CLOSED, 2: WOULD_BLOCK) が入ります
+impl Operations {
+    /// チャネルを作る 容量0は1として扱う
+    /// *dst_reg = chan_new(*cap_reg + add_cap)
+    /// cap_dst: [ cap_reg(8bit) | dst_reg(8bit) ]
     #[inline(always)]
-    pub fn lte_u64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
+    pub fn chan_new(vm: &mut VM, cap_dst: u64, add_cap: u64) {
+        let cap_reg = ((cap_dst >> 8) & 0xFF) as usize;
+        let dst_reg = (cap_dst & 0xFF) as usize;
         unsafe {
             let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if *r.add(a) <= *r.add(b) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
-            }
+            let capacity = (*r.add(cap_reg)).wrapping_add(add_cap) as usize;
+            *r.add(dst_reg) = vm.channels.create(capacity);
         }
+        vm.st.pc += 1; // fallthrough
     }
 
-    /// より小さい場合のジャンプ (符号付き)
-    /// if *a < *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    /// u64を送る 満杯なら空くまで待つ
+    /// *status_reg = send(*chan_reg, *src_reg)
+    /// status_chan_src: [ status_reg(8bit) | chan_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn lt_i64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if (*r.add(a) as i64) < (*r.add(b) as i64) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
-            }
-        }
+    pub fn chan_send(vm: &mut VM, status_chan_src: u64, _: u64) {
+        Operations::send_message(vm, status_chan_src, false, true);
     }
 
-    /// より小さいか等しい場合のジャンプ (符号付き)
-    /// if *a <= *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    /// u64を送る 満杯ならWOULD_BLOCK
+    /// *status_reg = try_send(*chan_reg, *src_reg)
+    /// status_chan_src: [ status_reg(8bit) | chan_reg(8bit) | src_reg(8bit) ]
     #[inline(always)]
-    pub fn lte_i64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if (*r.add(a) as i64) <= (*r.add(b) as i64) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
-            }
-        }
+    pub fn chan_try_send(vm: &mut VM, status_chan_src: u64, _: u64) {
+        Operations::send_message(vm, status_chan_src, false, false);
     }
 
-    /// より大きい場合のジャンプ (符号なし)
-    /// if *a > *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    /// heepを送る 送れたら *id_reg はこのVMでは解放済みになる
+    /// *status_reg = send(*chan_reg, heep(*id_reg))
+    /// status_chan_id: [ status_reg(8bit) | chan_reg(8bit) | id_reg(8bit) ]
     #[inline(always)]
-    pub fn gt_u64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if *r.add(a) > *r.add(b) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
-            }
-        }
+    pub fn chan_send_heep(vm: &mut VM, status_chan_id: u64, _: u64) {
+        Operations::send_message(vm, status_chan_id, true, true);
     }
 
-    /// より大きいか等しい場合のジャンプ (符号なし)
-    /// if *a >= *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    /// heepを送る 満杯ならWOULD_BLOCKでheepはそのまま
+    /// *status_reg = try_send(*chan_reg, heep(*id_reg))
+    /// status_chan_id: [ status_reg(8bit) | chan_reg(8bit) | id_reg(8bit) ]
     #[inline(always)]
-    pub fn gte_u64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if *r.add(a) >= *r.add(b) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
-            }
-        }
+    pub fn chan_try_send_heep(vm: &mut VM, status_chan_id: u64, _: u64) {
+        Operations::send_message(vm, status_chan_id, true, false);
     }
 
-    /// より大きい場合のジャンプ (符号付き)
-    /// if *a > *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    /// 受け取る 空なら届くまで待つ
+    /// heepを受け取った場合は *dst_reg にこのVMでのheep idが入る 失敗したら *dst_reg はそのまま
+    /// *status_reg, *dst_reg = recv(*chan_reg)
+    /// status_dst_chan: [ status_reg(8bit) | dst_reg(8bit) | chan_reg(8bit) ]
     #[inline(always)]
-    pub fn gt_i64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if (*r.add(a) as i64) > (*r.add(b) as i64) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
-            }
-        }
+    pub fn chan_recv(vm: &mut VM, status_dst_chan: u64, _: u64) {
+        Operations::recv_message(vm, status_dst_chan, true);
     }
 
-    /// より大きいか等しい場合のジャンプ (符号付き)
-    /// if *a >= *b { pc = *addr_reg + offset } else { pc += 1 }
-    /// addr_a_b: [ addr_reg(8bit) | a(8bit) | b(8bit) ]
+    /// 受け取る 空ならWOULD_BLOCK
+    /// *status_reg, *dst_reg = try_recv(*chan_reg)
+    /// status_dst_chan: [ status_reg(8bit) | dst_reg(8bit) | chan_reg(8bit) ]
     #[inline(always)]
-    pub fn gte_i64_jump(vm: &mut VM, addr_a_b: u64, offset: u64) {
-        let addr_reg = ((addr_a_b >> 16) & 0xFF) as usize;
-        let a = ((addr_a_b >> 8) & 0xFF) as usize;
-        let b = (addr_a_b & 0xFF) as usize;
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let addr = (*r.add(addr_reg)).wrapping_add(offset) as usize;
-            if (*r.add(a) as i64) >= (*r.add(b) as i64) {
-                vm.st.pc = addr;
-            } else {
-                vm.st.pc += 1; // fallthrough
-            }
+    pub fn chan_try_recv(vm: &mut VM, status_dst_chan: u64, _: u64) {
+        Operations::recv_message(vm, status_dst_chan, false);
+    }
+
+    /// チャネルを閉じる 残っているメッセージは受け取れる
+    /// close(*chan_reg)
+    #[inline(always)]
+    pub fn chan_close(vm: &mut VM, chan_reg: u64, _: u64) {
+        let handle = vm.st.r[chan_reg as usize];
+        match vm.channels.get(handle) {
+            Ok(channel) => channel.close(),
+            Err(kind) => return vm.trap(kind),
         }
+        vm.st.pc += 1; // fallthrough
     }
 
-    /// 関数呼び出し
-    /// call func_index
-    /// set pc ( 普通は関数先頭アドレスで0 )
     #[inline(always)]
-    pub fn call(vm: &mut VM, func_index: u64, pc: u64) {
-        vm.st.call_stack.push(vm.st.pc);
-        vm.st.call_stack.push(vm.st.now_call_index);
-        vm.st.pc = pc as usize;
-        vm.st.now_call_index = func_index as usize; 
-        vm.st.now_function_ptr = vm.function_table[vm.st.now_call_index];
+    fn send_message(vm: &mut VM, status_chan_src: u64, heep: bool, block: bool) {
+        let status_reg = ((status_chan_src >> 16) & 0xFF) as usize;
+        let chan_reg = ((status_chan_src >> 8) & 0xFF) as usize;
+        let src_reg = (status_chan_src & 0xFF) as usize;
+        let channel = match vm.channels.get(vm.st.r[chan_reg]) {
+            Ok(channel) => channel,
+            Err(kind) => return vm.trap(kind),
+        };
+        let src = vm.st.r[src_reg];
+        let message = if heep {
+            match vm.st.mem.take_heep(src) {
+                Ok(storage) => Message::Heep(storage),
+                Err(kind) => return vm.trap(kind),
+            }
+        } else {
+            Message::Value(src)
+        };
+        vm.st.r[status_reg] = match channel.send(message, block) {
+            Ok(()) => status::OK,
+            Err((code, message)) => {
+                // 送れなかったheepは元のidに戻す
+                if let Message::Heep(storage) = message {
+                    vm.st.mem.restore_heep(src, storage);
+                }
+                code
+            }
+        };
+        vm.st.pc += 1; // fallthrough
     }
 
-    /// 関数リターン
-    /// ret
     #[inline(always)]
-    pub fn ret(vm: &mut VM, _: u64, _: u64) {
-        vm.st.now_call_index = vm.st.call_stack.pop().expect("Call stack underflow on return");
-        vm.st.pc = vm.st.call_stack.pop().unwrap() + 1;
-        vm.st.now_function_ptr = vm.function_table[vm.st.now_call_index];
+    fn recv_message(vm: &mut VM, status_dst_chan: u64, block: bool) {
+        let status_reg = ((status_dst_chan >> 16) & 0xFF) as usize;
+        let dst_reg = ((status_dst_chan >> 8) & 0xFF) as usize;
+        let chan_reg = (status_dst_chan & 0xFF) as usize;
+        let channel = match vm.channels.get(vm.st.r[chan_reg]) {
+            Ok(channel) => channel,
+            Err(kind) => return vm.trap(kind),
+        };
+        vm.st.r[status_reg] = match channel.recv(block) {
+            Ok(Message::Value(value)) => {
+                vm.st.r[dst_reg] = value;
+                status::OK
+            }
+            Ok(Message::Heep(storage)) => {
+                vm.st.r[dst_reg] = vm.st.mem.insert_storage(storage);
+                status::OK
+            }
+            Err(code) => code,
+        };
+        vm.st.pc += 1; // fallthrough
     }
 }
 
-/// IO操作
+/// VMの起動と待ち合わせ
+/// SPAWNしたVMは同じプールのスレッドで動き、CodeManager・共有heep・チャネルを共有します
 impl Operations {
-    /// 整数の出力
-    /// print_u64 *src
-    #[inline(always)]
-    pub fn print_u64(vm: &mut VM, src: u64, _: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            println!("{}", *r.add(src as usize));
+    /// 関数を新しいVMで実行する
+    /// 新しいVMのr1から引数をコピーし、その関数から実行を始める 関数はMAINと同じくEXITで終わること
+    /// *id_reg = spawn(func_index, *arg_reg ..count)
+    /// id_arg_count: [ id_reg(8bit) | arg_reg(8bit) | count(8bit) ]
+    #[inline(always)]
+    pub fn spawn(vm: &mut VM, func_index: u64, id_arg_count: u64) {
+        let id_reg = ((id_arg_count >> 16) & 0xFF) as usize;
+        let arg_reg = ((id_arg_count >> 8) & 0xFF) as usize;
+        let count = (id_arg_count & 0xFF) as usize;
+        let Some(threads) = vm.threads.upgrade() else {
+            return vm.trap(TrapKind::NotInPool);
+        };
+        if vm.function_ptr(func_index).is_none() {
+            return vm.trap(TrapKind::BadFunctionIndex(func_index));
         }
+        let mut child = VM::new();
+        child.cm = vm.cm.clone_shared();
+        child.channels = vm.channels.clone();
+        child.st.mem.shared = vm.st.mem.shared.clone();
+        child.st.mem.bounds_check = vm.st.mem.bounds_check;
+        child.st.r[1..1 + count].copy_from_slice(&vm.st.r[arg_reg..arg_reg + count]);
+        child.st.now_call_index = func_index as usize;
+        let use_core_affinity = threads.uses_core_affinity(vm.vm_id);
+        vm.st.r[id_reg] = threads.spawn(child, use_core_affinity);
         vm.st.pc += 1; // fallthrough
     }
 
-    /// allocate memory
-    /// allocate *size + add_size, store id in *id_res_reg
-    /// size_idr: [ size_reg(8bit) | id_res_reg(8bit) ]
+    /// VMの終了を待つ trapしたVMの終了コードは u64::MAX
+    /// 自分自身や、互いにJOINし合うVMを待つとtrapします
+    /// *dst = join(*id)
     #[inline(always)]
-    pub fn alloc(vm: &mut VM, size_idr: u64, add_size: u64) {
-        let size_reg = ((size_idr >> 8) & 0xFF) as usize;
-        let id_res_reg = (size_idr & 0xFF) as usize;
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let size = (*r.add(size_reg)).wrapping_add(add_size) as usize;
-            let id = vm.st.mem.alloc_heep(size);
-            *r.add(id_res_reg) = id;
+    pub fn join(vm: &mut VM, dst: u64, id: u64) {
+        let Some(threads) = vm.threads.upgrade() else {
+            return vm.trap(TrapKind::NotInPool);
+        };
+        let vm_id = vm.st.r[id as usize];
+        match threads.join_from(vm.vm_id, vm_id) {
+            Ok(result) => vm.st.r[dst as usize] = result.exit_code,
+            Err(kind) => return vm.trap(kind),
         }
         vm.st.pc += 1; // fallthrough
     }
 
-    /// reallocate memory
-    /// reallocate *size for *id
+    /// 自分のvm_id
+    /// *dst = vm_id
     #[inline(always)]
-    pub fn realloc(vm: &mut VM, size: u64, id: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let size = *r.add(size as usize) as usize;
-            let id = *r.add(id as usize);
-            vm.st.mem.realloc_heep(id, size);
-        }
+    pub fn vm_id(vm: &mut VM, dst: u64, _: u64) {
+        vm.st.r[dst as usize] = vm.vm_id;
         vm.st.pc += 1; // fallthrough
     }
+}
 
-    /// deallocate memory
-    /// deallocate *id
-    #[inline(always)]
-    pub fn dealloc(vm: &mut VM, id: u64, _: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let id = *r.add(id as usize);
-            vm.st.mem.dealloc_heep(id);
+/// コルーチン
+/// 1つのVMの中で協調的に切り替わる軽量スレッド 各コルーチンはpc・呼び出しスタック・レジスタを別に持ち、heepは共有します
+/// 切り替えは `state_flag::SWITCH` を立てて `VM::run` のループに任せます
+impl Operations {
+    /// 中断した状態のコルーチンを作る 最初のCO_RESUMEでfuncの先頭から実行を始める
+    /// 新しいコルーチンのr1から引数をコピーする 最初のCO_RESUMEの値は捨てられる
+    /// *id_reg = co_create(func_index, *arg_reg ..count)
+    /// id_arg_count: [ id_reg(8bit) | arg_reg(8bit) | count(8bit) ]
+    #[inline(always)]
+    pub fn co_create(vm: &mut VM, func_index: u64, id_arg_count: u64) {
+        let id_reg = ((id_arg_count >> 16) & 0xFF) as usize;
+        let arg_reg = ((id_arg_count >> 8) & 0xFF) as usize;
+        let count = (id_arg_count & 0xFF) as usize;
+        if vm.function_ptr(func_index).is_none() {
+            return vm.trap(TrapKind::BadFunctionIndex(func_index));
         }
+        let id = vm.st.coroutines.create(func_index as usize, &vm.st.r[arg_reg..arg_reg + count]);
+        vm.st.r[id_reg] = id;
         vm.st.pc += 1; // fallthrough
     }
 
-    /// read file to memory
-    /// heep_id path_ptr path_size 
-
-    /// プログラム終了
-    /// exit with code *code_reg
-    #[inline(always)]
-    pub fn exit(vm: &mut VM, code_reg: u64, _: u64) {
-        unsafe {
-            let r = vm.st.r.as_mut_ptr();
-            let code = *r.add(code_reg as usize);
-            vm.st.r[0] = code; // return code
-            vm.st.state_flag |= state_flag::PAUSE;
+    /// コルーチンを再開し、YIELDか終了するまで待つ
+    /// 再開したコルーチンのYIELDのレジスタには *value_reg が入る
+    /// 戻ってきたら *value_reg にYIELDかRET_VALUEの値、*status_reg に `coroutine::status` (0: YIELDED, 1: FINISHED) が入る
+    /// 既に終わっていれば切り替えずに *status_reg = 2 (DEAD)
+    /// status_value_co: [ status_reg(8bit) | value_reg(8bit) | co_reg(8bit) ]
+    #[inline(always)]
+    pub fn co_resume(vm: &mut VM, status_value_co: u64, _: u64) {
+        let status_reg = ((status_value_co >> 16) & 0xFF) as usize;
+        let value_reg = ((status_value_co >> 8) & 0xFF) as usize;
+        let co_reg = (status_value_co & 0xFF) as usize;
+        let id = vm.st.r[co_reg];
+        match vm.st.coroutines.resume(id, vm.st.r[value_reg], status_reg, value_reg) {
+            Ok(true) => vm.st.state_flag |= state_flag::SWITCH,
+            Ok(false) => vm.st.r[status_reg] = coroutine::status::DEAD,
+            Err(kind) => return vm.trap(kind),
         }
+        vm.st.pc += 1; // fallthrough
     }
-}
 
-/// 特殊制御
-impl Operations {
-    /// LocalDecodedByteCodeの更新
-    /// 呼び出された場合CodeManagerにデコードを依頼し、VMのFuctionTableを更新します
-    /// Code Manager は未デコードのfunctionをこれに置き換えます。
+    /// 再開したコルーチンに *reg を返して中断する
+    /// 次にCO_RESUMEされると *reg にその値が入って続きから実行する
+    /// yield *reg
     #[inline(always)]
-    pub fn get_decode(vm: &mut VM, decode_id: u64, deep: u64) {
-        vm.function_table = vm.cm.get_decode(decode_id, vm.st.now_call_index as u64, deep);
+    pub fn yield_coroutine(vm: &mut VM, reg: u64, _: u64) {
+        if let Err(kind) = vm.st.coroutines.yield_value(vm.st.r[reg as usize], reg as usize) {
+            return vm.trap(kind);
+        }
+        vm.st.state_flag |= state_flag::SWITCH;
         vm.st.pc += 1; // fallthrough
     }
 
-    /// 最新のデコード済みByteCodeを取得
-    #[inline(always)]
-    pub fn get_decoded(vm: &mut VM, _:u64, _: u64) {
-        vm.function_table = vm.cm.get_decoded();
-        vm.st.pc += 1; // fallthrough
+    /// 最下段のRET/RET_VALUEでコルーチンを終了し、再開したコルーチンに value を返す
+    /// コルーチンの外ならCallStackUnderflow
+    #[cold]
+    fn finish_coroutine(vm: &mut VM, value: u64) {
+        if vm.st.coroutines.current == 0 {
+            return vm.trap(TrapKind::CallStackUnderflow);
+        }
+        vm.st.coroutines.finish(value);
+        vm.st.state_flag |= state_flag::SWITCH;
     }
 }
-
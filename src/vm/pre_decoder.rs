@@ -1,9 +1,13 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::hash::BuildHasher;
+use std::ops::Range;
 use std::sync::OnceLock;
 
-use crate::vm::function::Function;
-use crate::vm::operations::Instruction;
+use crate::vm::diagnostic::{self, Diagnostic};
+use crate::vm::function::{DebugInfo, Function};
+use crate::vm::native::NativeRegistry;
+use crate::vm::operations::{Instruction, MemoryOrdering};
 
 type InstructionBuilder = fn(u64, u64) -> Instruction;
 
@@ -11,7 +15,7 @@ type InstructionBuilder = fn(u64, u64) -> Instruction;
 /// バイトコードをfunction_ptr_vecに変換する
 /// 
 /// バイトコードをについて
-/// ```
+/// ```text
 /// MAIN    ; 関数名 改行後コードが続く これはコメントアウト MAINは特別な名前でエントリーポイントになる
 /// <OPECODE> <値1> <値2> ...  ; 命令コード 引数1 引数2 ... となり 空白で区切る tabなどでも可能 改行で次の命令へ
 /// CALL FUNC1 ; 関数呼び出し
@@ -20,6 +24,12 @@ type InstructionBuilder = fn(u64, u64) -> Instruction;
 /// FUNC1   ; 関数名 改行後コードが続く これはコメントアウト
 /// ...
 /// RET     ; 関数終了
+///
+/// FUNC2
+/// .loop:  ; ローカルラベル 次の命令のindexを指す 関数内でのみ有効
+/// ADD_U64_IMMEDIATE r1 1
+/// LT_U64_JUMP r0 r1 r2 .loop ; ジャンプ系命令のoffsetにラベルを書ける (r0 + .loop)
+/// RET
 /// ```
 pub struct PreDecoder;
 
@@ -43,22 +53,38 @@ impl OpcodeSpec {
 }
 
 #[derive(Copy, Clone)]
-enum OperandPlan {
+pub(crate) enum OperandPlan {
     Value,
+    /// レジスタ番号 パースはValueと同じで、逆アセンブル時に `rN` になる
+    Register,
     PackedRegisters(u8),
+    /// ジャンプ先 数値かローカルラベル
+    Target,
+    /// 関数index 数値か関数名
+    Function,
+    /// ホスト関数index 数値かホスト関数名
+    Native,
 }
 
 impl OperandPlan {
     const fn min_tokens(self) -> usize {
         match self {
-            OperandPlan::Value => 1,
+            OperandPlan::Value
+            | OperandPlan::Register
+            | OperandPlan::Target
+            | OperandPlan::Function
+            | OperandPlan::Native => 1,
             OperandPlan::PackedRegisters(_) => 1,
         }
     }
 
     const fn max_tokens(self) -> usize {
         match self {
-            OperandPlan::Value => 1,
+            OperandPlan::Value
+            | OperandPlan::Register
+            | OperandPlan::Target
+            | OperandPlan::Function
+            | OperandPlan::Native => 1,
             OperandPlan::PackedRegisters(count) => count as usize,
         }
     }
@@ -68,11 +94,21 @@ const OPERANDS_NONE: &[OperandPlan] = &[];
 // const OPERANDS_VALUE: &[OperandPlan] = &[OperandPlan::Value];
 const OPERANDS_TWO_VALUES: &[OperandPlan] = &[OperandPlan::Value, OperandPlan::Value];
 // const OPERANDS_PACK2: &[OperandPlan] = &[OperandPlan::PackedRegisters(2)];
-// const OPERANDS_PACK3: &[OperandPlan] = &[OperandPlan::PackedRegisters(3)];
+const OPERANDS_PACK3: &[OperandPlan] = &[OperandPlan::PackedRegisters(3)];
 // const OPERANDS_PACK4: &[OperandPlan] = &[OperandPlan::PackedRegisters(4)];
 const OPERANDS_PACK2_VALUE: &[OperandPlan] = &[OperandPlan::PackedRegisters(2), OperandPlan::Value];
 const OPERANDS_PACK3_VALUE: &[OperandPlan] = &[OperandPlan::PackedRegisters(3), OperandPlan::Value];
 const OPERANDS_PACK4_VALUE: &[OperandPlan] = &[OperandPlan::PackedRegisters(4), OperandPlan::Value];
+const OPERANDS_PACK5_VALUE: &[OperandPlan] = &[OperandPlan::PackedRegisters(5), OperandPlan::Value];
+const OPERANDS_TWO_REGISTERS: &[OperandPlan] = &[OperandPlan::Register, OperandPlan::Register];
+const OPERANDS_REGISTER_VALUE: &[OperandPlan] = &[OperandPlan::Register, OperandPlan::Value];
+const OPERANDS_FUNCTION_VALUE: &[OperandPlan] = &[OperandPlan::Function, OperandPlan::Value];
+const OPERANDS_REGISTER_TARGET: &[OperandPlan] = &[OperandPlan::Register, OperandPlan::Target];
+const OPERANDS_NATIVE_PACK3: &[OperandPlan] = &[OperandPlan::Native, OperandPlan::PackedRegisters(3)];
+const OPERANDS_FUNCTION_PACK3: &[OperandPlan] = &[OperandPlan::Function, OperandPlan::PackedRegisters(3)];
+const OPERANDS_REGISTER_PACK3: &[OperandPlan] = &[OperandPlan::Register, OperandPlan::PackedRegisters(3)];
+const OPERANDS_REGISTER_FUNCTION: &[OperandPlan] = &[OperandPlan::Register, OperandPlan::Function];
+const OPERANDS_PACK3_TARGET: &[OperandPlan] = &[OperandPlan::PackedRegisters(3), OperandPlan::Target];
 
 impl PreDecoder {
     pub fn new() -> Self {
@@ -80,180 +116,271 @@ impl PreDecoder {
     }
 
     pub fn decode(&self, source: &str) -> Result<Vec<Function>, PreDecodeError> {
-        let mut functions = Vec::new();
-        let mut current: Option<ParsedFunction> = None;
+        self.decode_with_natives(source, &NativeRegistry::new())
+    }
+
+    /// `decode` と同じですが、NATIVE_CALLを `natives` で解決します
+    pub fn decode_with_natives(
+        &self,
+        source: &str,
+        natives: &NativeRegistry,
+    ) -> Result<Vec<Function>, PreDecodeError> {
+        let spans = self.scan(source)?;
+
+        let name_to_index: HashMap<_, _> = spans
+            .iter()
+            .enumerate()
+            .map(|(idx, span)| (span.name.clone(), idx))
+            .collect();
+
+        spans
+            .iter()
+            .map(|span| self.decode_span(source, span, &name_to_index, natives))
+            .collect()
+    }
+
+    /// `decode_with_natives` と同じですが、最初のエラーで止まらずにすべてのエラーを集めます
+    /// エラーは行順に並び、ソース上の位置を持ちます
+    pub fn decode_with_diagnostics(
+        &self,
+        source: &str,
+        natives: &NativeRegistry,
+    ) -> Result<Vec<Function>, Vec<Diagnostic>> {
+        let mut errors = Vec::new();
+        let spans = self.scan_inner(source, &mut errors);
+
+        let name_to_index: HashMap<_, _> = spans
+            .iter()
+            .enumerate()
+            .map(|(idx, span)| (span.name.clone(), idx))
+            .collect();
+
+        let functions = spans
+            .iter()
+            .map(|span| self.decode_span_inner(source, span, &name_to_index, natives, &mut errors))
+            .collect();
+
+        if errors.is_empty() {
+            return Ok(functions);
+        }
+        Err(diagnostic::collect(errors, source))
+    }
+
+    /// 関数の区切りだけを走査します
+    /// 命令のパースは行わないので、遅延デコードの前段として使えます
+    /// 戻り値はMAINが先頭になるように並べ替えられます
+    pub fn scan(&self, source: &str) -> Result<Vec<FunctionSpan>, PreDecodeError> {
+        let mut errors = Vec::new();
+        let spans = self.scan_inner(source, &mut errors);
+        match earliest_error(errors) {
+            Some(err) => Err(err),
+            None => Ok(spans),
+        }
+    }
+
+    /// `scan` の本体
+    /// エラーは `errors` に積んで走査を続けます
+    pub(crate) fn scan_inner(&self, source: &str, errors: &mut Vec<PreDecodeError>) -> Vec<FunctionSpan> {
+        let mut spans = Vec::new();
+        let mut current: Option<FunctionSpan> = None;
         let mut defined_names: HashSet<String> = HashSet::new();
         let opcode_table = opcode_table();
 
-        for (line_idx, raw_line) in source.lines().enumerate() {
+        for (line_idx, (offset, next_offset, raw_line)) in lines_with_offsets(source).enumerate() {
             let line_no = line_idx + 1;
-            let line = raw_line
-                .splitn(2, ';')
-                .next()
-                .map(str::trim)
-                .unwrap_or_default();
+            let line = strip_comment(raw_line);
 
             if line.is_empty() {
                 continue;
             }
 
-            let mut tokens = line.split_whitespace().collect::<Vec<_>>();
-            if tokens.is_empty() {
+            let mut tokens = line.split_whitespace();
+            let Some(first_raw) = tokens.next() else {
                 continue;
-            }
-            let first_raw = tokens.remove(0);
+            };
             let first_upper = first_raw.to_ascii_uppercase();
-            let is_opcode = opcode_table.contains_key(first_upper.as_str());
-
-            if current.is_none() {
-                if is_opcode {
-                    return Err(PreDecodeError::InstructionOutsideFunction {
-                        opcode: first_upper,
-                        line: line_no,
-                    });
-                }
-
-                let func_name = first_upper;
-                if !defined_names.insert(func_name.clone()) {
-                    return Err(PreDecodeError::DuplicateFunction {
-                        name: func_name,
-                        line: line_no,
-                    });
+            let (base_name, _) = split_ordering(&first_upper);
+            let is_opcode = opcode_table.contains_key(base_name);
+
+            let is_label = parse_label_definition(first_raw).is_some();
+            let is_header = match current {
+                None => {
+                    if is_opcode {
+                        errors.push(PreDecodeError::InstructionOutsideFunction {
+                            opcode: first_upper,
+                            line: line_no,
+                        });
+                        continue;
+                    }
+                    if is_label {
+                        errors.push(PreDecodeError::LabelOutsideFunction {
+                            label: first_upper,
+                            line: line_no,
+                        });
+                        continue;
+                    }
+                    true
                 }
+                Some(_) => !is_opcode && !is_label && tokens.next().is_none(),
+            };
 
-                current = Some(ParsedFunction::new(func_name));
+            if !is_header {
                 continue;
             }
 
-            if !is_opcode && tokens.is_empty() {
-                let func_name = first_upper;
-                if !defined_names.insert(func_name.clone()) {
-                    return Err(PreDecodeError::DuplicateFunction {
-                        name: func_name,
-                        line: line_no,
-                    });
-                }
-
-                functions.push(current.take().unwrap());
-                current = Some(ParsedFunction::new(func_name));
-                continue;
+            let func_name = first_upper;
+            if !defined_names.insert(func_name.clone()) {
+                // 本体は前の関数に含めないよう区切りとしては扱う
+                errors.push(PreDecodeError::DuplicateFunction {
+                    name: func_name.clone(),
+                    line: line_no,
+                });
             }
 
-            let opcode_name = first_upper;
-            let spec = opcode_table
-                .get(opcode_name.as_str())
-                .copied()
-                .ok_or_else(|| PreDecodeError::UnknownOpcode {
-                    name: opcode_name.clone(),
-                    line: line_no,
-                })?;
+            if let Some(mut function) = current.take() {
+                function.body.end = offset;
+                spans.push(function);
+            }
+            current = Some(FunctionSpan {
+                name: func_name,
+                line: line_no,
+                body: next_offset..next_offset,
+                body_line: line_no + 1,
+            });
+        }
 
-            let min_tokens = spec.min_tokens();
-            let max_tokens = spec.max_tokens();
+        if let Some(mut function) = current.take() {
+            function.body.end = source.len();
+            spans.push(function);
+        }
 
-            // 引数が足りない場合は0で埋める
-            while tokens.len() < max_tokens {
-                tokens.push("0");
+        match spans.iter().position(|f| f.name == "MAIN") {
+            Some(main_index) => {
+                let main = spans.remove(main_index);
+                spans.insert(0, main);
             }
+            None => errors.push(PreDecodeError::MissingMain),
+        }
+        spans
+    }
 
-            if tokens.len() < min_tokens {
-                return Err(PreDecodeError::NotEnoughArguments {
-                    opcode: opcode_name.clone(),
-                    expected: min_tokens,
-                    line: line_no,
-                });
-            }
-            if tokens.len() > max_tokens {
-                return Err(PreDecodeError::TooManyArguments {
-                    opcode: opcode_name.clone(),
-                    provided: tokens.len(),
-                    allowed: max_tokens,
-                    line: line_no,
-                });
-            }
+    /// 1関数分だけデコードします
+    /// `name_to_index` は CALL の解決に使う関数テーブル上のindexです
+    /// `natives` は NATIVE_CALL の解決に使います
+    /// 関数名と各命令の行番号をデバッグ情報として付けます
+    pub fn decode_span<S: BuildHasher>(
+        &self,
+        source: &str,
+        span: &FunctionSpan,
+        name_to_index: &HashMap<String, usize, S>,
+        natives: &NativeRegistry,
+    ) -> Result<Function, PreDecodeError> {
+        let mut errors = Vec::new();
+        let function = self.decode_span_inner(source, span, name_to_index, natives, &mut errors);
+        match earliest_error(errors) {
+            Some(err) => Err(err),
+            None => Ok(function),
+        }
+    }
 
-            let tokens_slice = tokens.as_slice();
-            let mut cursor = 0usize;
-            let mut parsed_args: Vec<Arg> = Vec::new();
-
-            for operand in spec.operands {
-                match operand {
-                    OperandPlan::Value => {
-                        let token = tokens_slice[cursor];
-                        let parsed = parse_arg(token, line_no)?;
-                        parsed_args.push(match parsed {
-                            Arg::Label(label) => Arg::Label(label.to_ascii_uppercase()),
-                            other => other,
-                        });
-                        cursor += 1;
-                    }
-                    OperandPlan::PackedRegisters(count) => {
-                        let arg = parse_packed_operand(
-                            opcode_name.as_str(),
-                            tokens_slice,
-                            &mut cursor,
-                            *count,
-                            line_no,
-                        )?;
-                        parsed_args.push(arg);
-                    }
-                }
-            }
+    /// `decode_span` の本体
+    /// エラーは `errors` に積み、エラーの行を飛ばしてデコードを続けます
+    pub(crate) fn decode_span_inner<S: BuildHasher>(
+        &self,
+        source: &str,
+        span: &FunctionSpan,
+        name_to_index: &HashMap<String, usize, S>,
+        natives: &NativeRegistry,
+        errors: &mut Vec<PreDecodeError>,
+    ) -> Function {
+        let mut function = ParsedFunction::new();
+
+        for (line_idx, raw_line) in source[span.body.clone()].lines().enumerate() {
+            let line_no = span.body_line + line_idx;
+            let line = strip_comment(raw_line);
 
-            if cursor < tokens_slice.len() {
-                return Err(PreDecodeError::TooManyArguments {
-                    opcode: opcode_name.clone(),
-                    provided: tokens_slice.len(),
-                    allowed: max_tokens,
-                    line: line_no,
-                });
+            if line.is_empty() {
+                continue;
             }
 
-            let mut args = [Arg::Value(0), Arg::Value(0)];
-            for (idx, arg) in parsed_args.into_iter().enumerate() {
-                args[idx] = arg;
+            if let Err(err) = function.parse_line(line, line_no) {
+                errors.push(err);
             }
-
-                current
-                    .as_mut()
-                    .unwrap()
-                    .instructions
-                    .push(ParsedInstruction {
-                        opcode: opcode_name,
-                        builder: spec.builder,
-                        args,
-                        line: line_no,
-                    });
         }
 
-        if let Some(function) = current.take() {
-            functions.push(function);
-        }
+        let lines = function.instructions.iter().map(|ins| ins.line).collect();
+        let debug = DebugInfo {
+            name: span.name.clone(),
+            source_path: None,
+            lines,
+        };
+        function
+            .into_function(name_to_index, natives, errors)
+            .with_debug_info(debug)
+    }
+}
 
-        if functions.is_empty() {
-            return Err(PreDecodeError::MissingMain);
-        }
+/// 関数のソース上の位置
+/// `PreDecoder::scan` が返し、`PreDecoder::decode_span` でデコードします
+#[derive(Clone, Debug)]
+pub struct FunctionSpan {
+    /// 関数名 (大文字化済み)
+    pub name: String,
+    /// 関数名の行番号
+    pub line: usize,
+    /// 本体のバイト範囲
+    pub body: Range<usize>,
+    /// 本体先頭の行番号
+    pub body_line: usize,
+}
 
-        let main_index = functions
-            .iter()
-            .position(|f| f.name == "MAIN")
-            .ok_or(PreDecodeError::MissingMain)?;
+/// 行番号が最も小さいエラー 行の無いエラーは最後
+/// CALLやラベルの解決は全行をパースした後なので、積んだ順が行順とは限りません
+fn earliest_error(mut errors: Vec<PreDecodeError>) -> Option<PreDecodeError> {
+    errors.sort_by_key(|err| err.line().unwrap_or(usize::MAX));
+    errors.into_iter().next()
+}
 
-        let mut ordered = Vec::with_capacity(functions.len());
-        let main = functions.remove(main_index);
-        ordered.push(main);
-        ordered.extend(functions);
+/// 各行と (行頭, 次の行頭) のバイトオフセット
+fn lines_with_offsets(source: &str) -> impl Iterator<Item = (usize, usize, &str)> {
+    let mut offset = 0usize;
+    source.split_inclusive('\n').map(move |raw| {
+        let start = offset;
+        offset += raw.len();
+        let line = raw.strip_suffix('\n').unwrap_or(raw);
+        (start, offset, line.strip_suffix('\r').unwrap_or(line))
+    })
+}
 
-        let name_to_index: HashMap<_, _> = ordered
-            .iter()
-            .enumerate()
-            .map(|(idx, func)| (func.name.clone(), idx))
-            .collect();
+/// `ATOMIC_ADD_U64.RELAXED` をオペコード名とオーダリング名に分ける
+fn split_ordering(token: &str) -> (&str, Option<&str>) {
+    match token.split_once('.') {
+        Some((name, ordering)) => (name, Some(ordering)),
+        None => (token, None),
+    }
+}
 
-        ordered
-            .into_iter()
-            .map(|parsed| parsed.into_function(&name_to_index))
-            .collect()
+/// `.name:` 形式のラベル定義なら `.name` を返す
+fn parse_label_definition(token: &str) -> Option<&str> {
+    let label = token.strip_suffix(':')?;
+    if label.len() > 1 && label.starts_with('.') {
+        Some(label)
+    } else {
+        None
+    }
+}
+
+fn strip_comment(raw_line: &str) -> &str {
+    raw_line
+        .split(';')
+        .next()
+        .map(str::trim)
+        .unwrap_or_default()
+}
+
+impl Default for PreDecoder {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -333,11 +460,11 @@ fn parse_packed_operand(
             match parse_register_index(token, line) {
                 Ok(value) => regs.push(value),
                 Err(err) => {
-                    if idx == 0 {
-                        if let Ok(value) = parse_numeric(token) {
-                            *cursor += 1;
-                            return Ok(Arg::Value(value));
-                        }
+                    if idx == 0
+                        && let Ok(value) = parse_numeric(token)
+                    {
+                        *cursor += 1;
+                        return Ok(Arg::Value(value));
                     }
                     return Err(err);
                 }
@@ -424,80 +551,131 @@ pub enum PreDecodeError {
     NotEnoughPackedRegisters { opcode: String, expected: usize, line: usize },
     ParseValue { token: String, line: usize },
     UnknownFunction { name: String, line: usize },
+    UnknownNative { name: String, line: usize },
     UnexpectedLabel { opcode: String, label: String, line: usize },
+    LabelOutsideFunction { label: String, line: usize },
+    DuplicateLabel { label: String, line: usize },
+    UndefinedLabel { label: String, line: usize },
     ExpectedRegister { token: String, line: usize },
     RegisterOutOfRange { token: String, line: usize },
+    InvalidOrdering { opcode: String, line: usize },
 }
 
-impl fmt::Display for PreDecodeError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl PreDecodeError {
+    /// エラーの行番号
+    pub fn line(&self) -> Option<usize> {
         match self {
-            PreDecodeError::MissingMain => write!(f, "MAIN function is required"),
-            PreDecodeError::DuplicateFunction { name, line } => {
-                write!(f, "function '{name}' defined multiple times (line {line})")
-            }
-            PreDecodeError::InstructionOutsideFunction { opcode, line } => {
-                write!(
-                    f,
-                    "instruction '{opcode}' appears before any function definition (line {line})"
-                )
+            PreDecodeError::MissingMain => None,
+            PreDecodeError::DuplicateFunction { line, .. }
+            | PreDecodeError::InstructionOutsideFunction { line, .. }
+            | PreDecodeError::UnknownOpcode { line, .. }
+            | PreDecodeError::TooManyArguments { line, .. }
+            | PreDecodeError::NotEnoughArguments { line, .. }
+            | PreDecodeError::NotEnoughPackedRegisters { line, .. }
+            | PreDecodeError::ParseValue { line, .. }
+            | PreDecodeError::UnknownFunction { line, .. }
+            | PreDecodeError::UnknownNative { line, .. }
+            | PreDecodeError::UnexpectedLabel { line, .. }
+            | PreDecodeError::LabelOutsideFunction { line, .. }
+            | PreDecodeError::DuplicateLabel { line, .. }
+            | PreDecodeError::UndefinedLabel { line, .. }
+            | PreDecodeError::ExpectedRegister { line, .. }
+            | PreDecodeError::RegisterOutOfRange { line, .. }
+            | PreDecodeError::InvalidOrdering { line, .. } => Some(*line),
+        }
+    }
+
+    /// 行番号を除いたメッセージ
+    pub fn message(&self) -> String {
+        match self {
+            PreDecodeError::MissingMain => "MAIN function is required".to_string(),
+            PreDecodeError::DuplicateFunction { name, .. } => {
+                format!("function '{name}' defined multiple times")
             }
-            PreDecodeError::UnknownOpcode { name, line } => {
-                write!(f, "unknown opcode '{name}' at line {line}")
+            PreDecodeError::InstructionOutsideFunction { opcode, .. } => {
+                format!("instruction '{opcode}' appears before any function definition")
             }
+            PreDecodeError::UnknownOpcode { name, .. } => format!("unknown opcode '{name}'"),
             PreDecodeError::TooManyArguments {
                 opcode,
                 provided,
                 allowed,
-                line,
-            } => write!(
-                f,
-                "opcode '{opcode}' accepts at most {allowed} operand token(s) but {provided} were provided (line {line})"
+                ..
+            } => format!(
+                "opcode '{opcode}' accepts at most {allowed} operand token(s) but {provided} were provided"
             ),
             PreDecodeError::NotEnoughArguments {
-                opcode,
-                expected,
-                line,
-            } => write!(
-                f,
-                "opcode '{opcode}' expects at least {expected} operand token(s) (line {line})"
-            ),
+                opcode, expected, ..
+            } => format!("opcode '{opcode}' expects at least {expected} operand token(s)"),
             PreDecodeError::NotEnoughPackedRegisters {
-                opcode,
-                expected,
-                line,
-            } => write!(
-                f,
-                "opcode '{opcode}' expects {expected} register operand(s) for the packed field (line {line})"
+                opcode, expected, ..
+            } => format!(
+                "opcode '{opcode}' expects {expected} register operand(s) for the packed field"
             ),
-            PreDecodeError::ParseValue { token, line } => {
-                write!(
-                    f,
-                    "failed to parse '{token}' as numeric literal (line {line})"
-                )
+            PreDecodeError::ParseValue { token, .. } => {
+                format!("failed to parse '{token}' as numeric literal")
             }
-            PreDecodeError::UnknownFunction { name, line } => {
-                write!(
-                    f,
-                    "referenced function '{name}' is not defined (line {line})"
-                )
+            PreDecodeError::UnknownFunction { name, .. } => {
+                format!("referenced function '{name}' is not defined")
             }
-            PreDecodeError::UnexpectedLabel {
-                opcode,
-                label,
-                line,
-            } => write!(
-                f,
-                "opcode '{opcode}' does not accept label '{label}' (line {line})"
-            ),
-            PreDecodeError::ExpectedRegister { token, line } => write!(
-                f,
-                "'{token}' should be a register (rN) or 8-bit value when expanding packed operands (line {line})"
-            ),
-            PreDecodeError::RegisterOutOfRange { token, line } => write!(
-                f,
-                "register value '{token}' must fit in 8 bits for packed operands (line {line})"
+            PreDecodeError::UnknownNative { name, .. } => {
+                format!("native function '{name}' is not registered")
+            }
+            PreDecodeError::UnexpectedLabel { opcode, label, .. } => {
+                format!("opcode '{opcode}' does not accept label '{label}'")
+            }
+            PreDecodeError::LabelOutsideFunction { label, .. } => {
+                format!("label '{label}' appears before any function definition")
+            }
+            PreDecodeError::DuplicateLabel { label, .. } => {
+                format!("label '{label}' defined multiple times")
+            }
+            PreDecodeError::UndefinedLabel { label, .. } => {
+                format!("label '{label}' is not defined in this function")
+            }
+            PreDecodeError::ExpectedRegister { token, .. } => format!(
+                "'{token}' should be a register (rN) or 8-bit value when expanding packed operands"
             ),
+            PreDecodeError::RegisterOutOfRange { token, .. } => {
+                format!("register value '{token}' must fit in 8 bits for packed operands")
+            }
+            PreDecodeError::InvalidOrdering { opcode, .. } => {
+                format!("memory ordering of '{opcode}' is not supported by the opcode")
+            }
+        }
+    }
+
+    /// エラー箇所を示すソース上のトークン
+    pub(crate) fn token(&self) -> Option<&str> {
+        match self {
+            PreDecodeError::DuplicateFunction { name, .. }
+            | PreDecodeError::UnknownOpcode { name, .. }
+            | PreDecodeError::UnknownFunction { name, .. }
+            | PreDecodeError::UnknownNative { name, .. } => Some(name),
+            PreDecodeError::InstructionOutsideFunction { opcode, .. }
+            | PreDecodeError::InvalidOrdering { opcode, .. } => Some(opcode),
+            PreDecodeError::ParseValue { token, .. }
+            | PreDecodeError::ExpectedRegister { token, .. }
+            | PreDecodeError::RegisterOutOfRange { token, .. } => Some(token),
+            PreDecodeError::UnexpectedLabel { label, .. }
+            | PreDecodeError::LabelOutsideFunction { label, .. }
+            | PreDecodeError::DuplicateLabel { label, .. }
+            | PreDecodeError::UndefinedLabel { label, .. } => Some(label),
+            // 引数の個数は命令全体を指す
+            PreDecodeError::MissingMain
+            | PreDecodeError::TooManyArguments { .. }
+            | PreDecodeError::NotEnoughArguments { .. }
+            | PreDecodeError::NotEnoughPackedRegisters { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for PreDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())?;
+        match self.line() {
+            Some(line) => write!(f, " (line {line})"),
+            None => Ok(()),
         }
     }
 }
@@ -506,29 +684,184 @@ impl std::error::Error for PreDecodeError {}
 
 #[derive(Clone)]
 struct ParsedFunction {
-    name: String,
     instructions: Vec<ParsedInstruction>,
+    /// ローカルラベル -> 命令index
+    labels: HashMap<String, usize>,
 }
 
 impl ParsedFunction {
-    fn new(name: String) -> Self {
+    fn new() -> Self {
         Self {
-            name,
             instructions: Vec::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    /// コメントを除いた1行をパースします
+    fn parse_line(&mut self, line: &str, line_no: usize) -> Result<(), PreDecodeError> {
+        let opcode_table = opcode_table();
+        let mut tokens = line.split_whitespace().collect::<Vec<_>>();
+        if tokens.is_empty() {
+            return Ok(());
+        }
+
+        // ラベル定義は次の命令のindexを指す
+        if let Some(label) = parse_label_definition(tokens[0]) {
+            let label = label.to_ascii_uppercase();
+            if self.labels.contains_key(&label) {
+                return Err(PreDecodeError::DuplicateLabel {
+                    label,
+                    line: line_no,
+                });
+            }
+            self.labels.insert(label, self.instructions.len());
+            tokens.remove(0);
+            if tokens.is_empty() {
+                return Ok(());
+            }
+        }
+
+        let opcode_token = tokens.remove(0).to_ascii_uppercase();
+        let (base_name, ordering_name) = split_ordering(&opcode_token);
+        let opcode_name = base_name.to_string();
+        let spec = opcode_table
+            .get(opcode_name.as_str())
+            .copied()
+            .ok_or_else(|| PreDecodeError::UnknownOpcode {
+                name: opcode_token.clone(),
+                line: line_no,
+            })?;
+
+        // `.RELAXED` などのオーダリング指定は引数aの最上位byteに入れる
+        let ordering = match ordering_name {
+            None => MemoryOrdering::SeqCst,
+            Some(ordering_name) => MemoryOrdering::from_name(ordering_name)
+                .filter(|ordering| opcode_orderings(&opcode_name).contains(ordering))
+                .ok_or_else(|| PreDecodeError::InvalidOrdering {
+                    opcode: opcode_token.clone(),
+                    line: line_no,
+                })?,
+        };
+
+        let min_tokens = spec.min_tokens();
+        let max_tokens = spec.max_tokens();
+
+        // 引数が足りない場合は0で埋める
+        while tokens.len() < max_tokens {
+            tokens.push("0");
+        }
+
+        if tokens.len() < min_tokens {
+            return Err(PreDecodeError::NotEnoughArguments {
+                opcode: opcode_name.clone(),
+                expected: min_tokens,
+                line: line_no,
+            });
         }
+        if tokens.len() > max_tokens {
+            return Err(PreDecodeError::TooManyArguments {
+                opcode: opcode_name.clone(),
+                provided: tokens.len(),
+                allowed: max_tokens,
+                line: line_no,
+            });
+        }
+
+        let tokens_slice = tokens.as_slice();
+        let mut cursor = 0usize;
+        let mut parsed_args: Vec<Arg> = Vec::new();
+
+        for operand in spec.operands {
+            match operand {
+                OperandPlan::Native => {
+                    parsed_args.push(match parse_arg(tokens_slice[cursor], line_no)? {
+                        Arg::Label(label) => Arg::Native(label.to_ascii_uppercase()),
+                        other => other,
+                    });
+                    cursor += 1;
+                }
+                OperandPlan::Function => {
+                    parsed_args.push(match parse_arg(tokens_slice[cursor], line_no)? {
+                        Arg::Label(label) => Arg::Function(label.to_ascii_uppercase()),
+                        other => other,
+                    });
+                    cursor += 1;
+                }
+                OperandPlan::Value | OperandPlan::Register => {
+                    let token = tokens_slice[cursor];
+                    let parsed = parse_arg(token, line_no)?;
+                    parsed_args.push(match parsed {
+                        Arg::Label(label) => Arg::Label(label.to_ascii_uppercase()),
+                        other => other,
+                    });
+                    cursor += 1;
+                }
+                OperandPlan::Target => {
+                    let token = tokens_slice[cursor];
+                    if token.starts_with('.') {
+                        parsed_args.push(Arg::LocalLabel(token.to_ascii_uppercase()));
+                    } else {
+                        parsed_args.push(parse_arg(token, line_no)?);
+                    }
+                    cursor += 1;
+                }
+                OperandPlan::PackedRegisters(count) => {
+                    let arg = parse_packed_operand(
+                        opcode_name.as_str(),
+                        tokens_slice,
+                        &mut cursor,
+                        *count,
+                        line_no,
+                    )?;
+                    parsed_args.push(arg);
+                }
+            }
+        }
+
+        if cursor < tokens_slice.len() {
+            return Err(PreDecodeError::TooManyArguments {
+                opcode: opcode_name.clone(),
+                provided: tokens_slice.len(),
+                allowed: max_tokens,
+                line: line_no,
+            });
+        }
+
+        let mut args = [Arg::Value(0), Arg::Value(0)];
+        for (idx, arg) in parsed_args.into_iter().enumerate() {
+            args[idx] = arg;
+        }
+
+        self.instructions.push(ParsedInstruction {
+            opcode: opcode_name,
+            builder: spec.builder,
+            args,
+            ordering: ordering.operand(),
+            line: line_no,
+        });
+
+        Ok(())
     }
 
-    fn into_function(
+    /// 解決できなかった命令は `errors` に積んで取り除きます
+    fn into_function<S: BuildHasher>(
         self,
-        name_to_index: &HashMap<String, usize>,
-    ) -> Result<Function, PreDecodeError> {
+        name_to_index: &HashMap<String, usize, S>,
+        natives: &NativeRegistry,
+        errors: &mut Vec<PreDecodeError>,
+    ) -> Function {
         let instructions = self
             .instructions
             .into_iter()
-            .map(|instruction| instruction.into_instruction(name_to_index))
-            .collect::<Result<Vec<_>, _>>()?;
-
-        Ok(Function::new(instructions.into_boxed_slice()))
+            .filter_map(|instruction| {
+                instruction
+                    .into_instruction(name_to_index, natives, &self.labels)
+                    .map_err(|err| errors.push(err))
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+
+        Function::new(instructions.into_boxed_slice())
     }
 }
 
@@ -537,45 +870,60 @@ struct ParsedInstruction {
     opcode: String,
     builder: InstructionBuilder,
     args: [Arg; 2],
+    /// 引数aに重ねるオーダリングのビット
+    ordering: u64,
     line: usize,
 }
 
 impl ParsedInstruction {
-    fn into_instruction(
+    fn into_instruction<S: BuildHasher>(
         self,
-        name_to_index: &HashMap<String, usize>,
+        name_to_index: &HashMap<String, usize, S>,
+        natives: &NativeRegistry,
+        labels: &HashMap<String, usize>,
     ) -> Result<Instruction, PreDecodeError> {
-        let a = resolve_arg(&self.opcode, &self.args[0], name_to_index, self.line)?;
-        let b = resolve_arg(&self.opcode, &self.args[1], name_to_index, self.line)?;
-        Ok((self.builder)(a, b))
+        let a = resolve_arg(&self.opcode, &self.args[0], name_to_index, natives, labels, self.line)?;
+        let b = resolve_arg(&self.opcode, &self.args[1], name_to_index, natives, labels, self.line)?;
+        Ok((self.builder)(a | self.ordering, b))
     }
 }
 
-fn resolve_arg(
+fn resolve_arg<S: BuildHasher>(
     opcode: &str,
     arg: &Arg,
-    name_to_index: &HashMap<String, usize>,
+    name_to_index: &HashMap<String, usize, S>,
+    natives: &NativeRegistry,
+    labels: &HashMap<String, usize>,
     line: usize,
 ) -> Result<u64, PreDecodeError> {
     match arg {
         Arg::Value(value) => Ok(*value),
-        Arg::Label(label) => {
-            if opcode != "CALL" {
-                return Err(PreDecodeError::UnexpectedLabel {
-                    opcode: opcode.to_string(),
-                    label: label.clone(),
-                    line,
-                });
-            }
-
-            name_to_index
-                .get(label)
-                .map(|idx| *idx as u64)
-                .ok_or_else(|| PreDecodeError::UnknownFunction {
-                    name: label.clone(),
-                    line,
-                })
-        }
+        Arg::Native(name) => natives
+            .index_of(name)
+            .map(|idx| idx as u64)
+            .ok_or_else(|| PreDecodeError::UnknownNative {
+                name: name.clone(),
+                line,
+            }),
+        Arg::LocalLabel(label) => labels
+            .get(label)
+            .map(|idx| *idx as u64)
+            .ok_or_else(|| PreDecodeError::UndefinedLabel {
+                label: label.clone(),
+                line,
+            }),
+        Arg::Label(label) => Err(PreDecodeError::UnexpectedLabel {
+            opcode: opcode.to_string(),
+            label: label.clone(),
+            line,
+        }),
+        Arg::Function(name) => name_to_index
+            .get(name)
+            .map(|idx| *idx as u64)
+            .ok_or_else(|| PreDecodeError::UnknownFunction {
+                name: name.clone(),
+                line,
+            }),
     }
 }
 
@@ -583,6 +931,58 @@ fn resolve_arg(
 enum Arg {
     Value(u64),
     Label(String),
+    /// 関数内ローカルラベル (`.name`)
+    LocalLabel(String),
+    /// 関数名
+    Function(String),
+    /// ホスト関数名
+    Native(String),
+}
+
+/// 命令のオペコード名
+pub fn opcode_name(instruction: &Instruction) -> &'static str {
+    static NAMES: OnceLock<[&'static str; 256]> = OnceLock::new();
+    let names = NAMES.get_or_init(|| {
+        let mut names = [""; 256];
+        for (name, spec) in opcode_table() {
+            names[(spec.builder)(0, 0).opcode() as usize] = name;
+        }
+        names
+    });
+    names[instruction.opcode() as usize]
+}
+
+/// オペコード名と引数から命令を組み立てます
+pub fn build_instruction(name: &str, a: u64, b: u64) -> Option<Instruction> {
+    opcode_table().get(name).map(|spec| (spec.builder)(a, b))
+}
+
+/// オペコードの引数の並び
+pub(crate) fn opcode_operands(name: &str) -> Option<&'static [OperandPlan]> {
+    opcode_table().get(name).map(|spec| spec.operands)
+}
+
+/// オペコードに指定できるメモリオーダリング
+/// 読み込みだけの命令はRelease、書き込みだけの命令はAcquireを含むものを指定できません
+pub(crate) fn opcode_orderings(name: &str) -> &'static [MemoryOrdering] {
+    use MemoryOrdering::*;
+    if name == "FENCE" {
+        &[SeqCst, Acquire, Release, AcqRel]
+    } else if name.starts_with("ATOMIC_LOAD_") {
+        &[SeqCst, Relaxed, Acquire]
+    } else if name.starts_with("ATOMIC_STORE_") {
+        &[SeqCst, Relaxed, Release]
+    } else if name.starts_with("ATOMIC_") {
+        &MemoryOrdering::ALL
+    } else {
+        &[]
+    }
+}
+
+/// 定義済みのすべてのオペコード名
+#[cfg(test)]
+pub(crate) fn opcode_names() -> impl Iterator<Item = &'static str> {
+    opcode_table().keys().copied()
 }
 
 fn opcode_table() -> &'static HashMap<&'static str, OpcodeSpec> {
@@ -596,95 +996,129 @@ fn opcode_table() -> &'static HashMap<&'static str, OpcodeSpec> {
         }
 
         // 整数演算
-        insert!("ADD_U64", Instruction::AddU64, OPERANDS_TWO_VALUES); // *dst = *dst + *src
-        insert!("ADD_U64_IMMEDIATE", Instruction::AddU64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst + imm
-        insert!("ADD_I64", Instruction::AddI64, OPERANDS_TWO_VALUES); // *dst = *dst + *src
-        insert!("ADD_I64_IMMEDIATE", Instruction::AddI64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst + imm
-        insert!("SUB_U64", Instruction::SubU64, OPERANDS_TWO_VALUES); // *dst = *dst - *src
-        insert!("SUB_U64_IMMEDIATE", Instruction::SubU64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst - imm
-        insert!("SUB_I64", Instruction::SubI64, OPERANDS_TWO_VALUES); // *dst = *dst - *src
-        insert!("SUB_I64_IMMEDIATE", Instruction::SubI64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst - imm
-        insert!("MUL_U64", Instruction::MulU64, OPERANDS_TWO_VALUES); // *dst = *dst * *src
-        insert!("MUL_U64_IMMEDIATE", Instruction::MulU64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst * imm
-        insert!("MUL_I64", Instruction::MulI64, OPERANDS_TWO_VALUES); // *dst = *dst * *src
-        insert!("MUL_I64_IMMEDIATE", Instruction::MulI64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst * imm
-        insert!("DIV_U64", Instruction::DivU64, OPERANDS_TWO_VALUES); // *dst = *dst / *src
-        insert!("DIV_U64_IMMEDIATE", Instruction::DivU64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst / imm
-        insert!("DIV_I64", Instruction::DivI64, OPERANDS_TWO_VALUES); // *dst = *dst / *src
-        insert!("DIV_I64_IMMEDIATE", Instruction::DivI64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst / imm
-        insert!("ABS", Instruction::Abs, OPERANDS_TWO_VALUES); // *dst = abs(*src)
-        insert!("MOD_I64", Instruction::ModI64, OPERANDS_TWO_VALUES); // *dst = *dst % *src
-        insert!("NEG_I64", Instruction::NegI64, OPERANDS_TWO_VALUES); // *dst = -(*src)
-        insert!("U64_TO_F64", Instruction::U64ToF64, OPERANDS_TWO_VALUES); // *dst = (*src as f64)
-        insert!("I64_TO_F64", Instruction::I64ToF64, OPERANDS_TWO_VALUES); // *dst = (*src as i64) as f64
+        insert!("ADD_U64", Instruction::AddU64, OPERANDS_TWO_REGISTERS); // *dst = *dst + *src
+        insert!("ADD_U64_IMMEDIATE", Instruction::AddU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst + imm
+        insert!("ADD_I64", Instruction::AddI64, OPERANDS_TWO_REGISTERS); // *dst = *dst + *src
+        insert!("ADD_I64_IMMEDIATE", Instruction::AddI64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst + imm
+        insert!("SUB_U64", Instruction::SubU64, OPERANDS_TWO_REGISTERS); // *dst = *dst - *src
+        insert!("SUB_U64_IMMEDIATE", Instruction::SubU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst - imm
+        insert!("SUB_I64", Instruction::SubI64, OPERANDS_TWO_REGISTERS); // *dst = *dst - *src
+        insert!("SUB_I64_IMMEDIATE", Instruction::SubI64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst - imm
+        insert!("MUL_U64", Instruction::MulU64, OPERANDS_TWO_REGISTERS); // *dst = *dst * *src
+        insert!("MUL_U64_IMMEDIATE", Instruction::MulU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst * imm
+        insert!("MUL_I64", Instruction::MulI64, OPERANDS_TWO_REGISTERS); // *dst = *dst * *src
+        insert!("MUL_I64_IMMEDIATE", Instruction::MulI64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst * imm
+        insert!("DIV_U64", Instruction::DivU64, OPERANDS_TWO_REGISTERS); // *dst = *dst / *src
+        insert!("DIV_U64_IMMEDIATE", Instruction::DivU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst / imm
+        insert!("DIV_I64", Instruction::DivI64, OPERANDS_TWO_REGISTERS); // *dst = *dst / *src
+        insert!("DIV_I64_IMMEDIATE", Instruction::DivI64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst / imm
+        insert!("ABS", Instruction::Abs, OPERANDS_TWO_REGISTERS); // *dst = abs(*src)
+        insert!("MOD_I64", Instruction::ModI64, OPERANDS_TWO_REGISTERS); // *dst = *dst % *src
+        insert!("NEG_I64", Instruction::NegI64, OPERANDS_TWO_REGISTERS); // *dst = -(*src)
+        insert!("U64_TO_F64", Instruction::U64ToF64, OPERANDS_TWO_REGISTERS); // *dst = (*src as f64)
+        insert!("I64_TO_F64", Instruction::I64ToF64, OPERANDS_TWO_REGISTERS); // *dst = (*src as i64) as f64
 
         // 浮動小数点演算
-        insert!("ADD_F64", Instruction::AddF64, OPERANDS_TWO_VALUES); // *dst = *dst + *src
-        insert!("ADD_F64_IMMEDIATE", Instruction::AddF64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst + imm
-        insert!("SUB_F64", Instruction::SubF64, OPERANDS_TWO_VALUES); // *dst = *dst - *src
-        insert!("SUB_F64_IMMEDIATE", Instruction::SubF64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst - imm
-        insert!("MUL_F64", Instruction::MulF64, OPERANDS_TWO_VALUES); // *dst = *dst * *src
-        insert!("MUL_F64_IMMEDIATE", Instruction::MulF64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst * imm
-        insert!("DIV_F64", Instruction::DivF64, OPERANDS_TWO_VALUES); // *dst = *dst / *src
-        insert!("DIV_F64_IMMEDIATE", Instruction::DivF64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst / imm
-        insert!("ABS_F64", Instruction::AbsF64, OPERANDS_TWO_VALUES); // *dst = abs(*src)
-        insert!("NEG_F64", Instruction::NegF64, OPERANDS_TWO_VALUES); // *dst = -(*src)
-        insert!("TO_I64", Instruction::ToI64, OPERANDS_TWO_VALUES); // *dst = (*src as f64) as i64 as u64
+        insert!("ADD_F64", Instruction::AddF64, OPERANDS_TWO_REGISTERS); // *dst = *dst + *src
+        insert!("ADD_F64_IMMEDIATE", Instruction::AddF64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst + imm
+        insert!("SUB_F64", Instruction::SubF64, OPERANDS_TWO_REGISTERS); // *dst = *dst - *src
+        insert!("SUB_F64_IMMEDIATE", Instruction::SubF64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst - imm
+        insert!("MUL_F64", Instruction::MulF64, OPERANDS_TWO_REGISTERS); // *dst = *dst * *src
+        insert!("MUL_F64_IMMEDIATE", Instruction::MulF64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst * imm
+        insert!("DIV_F64", Instruction::DivF64, OPERANDS_TWO_REGISTERS); // *dst = *dst / *src
+        insert!("DIV_F64_IMMEDIATE", Instruction::DivF64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst / imm
+        insert!("ABS_F64", Instruction::AbsF64, OPERANDS_TWO_REGISTERS); // *dst = abs(*src)
+        insert!("NEG_F64", Instruction::NegF64, OPERANDS_TWO_REGISTERS); // *dst = -(*src)
+        insert!("TO_I64", Instruction::ToI64, OPERANDS_TWO_REGISTERS); // *dst = (*src as f64) as i64 as u64
 
         // 論理演算
-        insert!("AND_U64", Instruction::AndU64, OPERANDS_TWO_VALUES); // *dst = *dst & *src
-        insert!("AND_U64_IMMEDIATE", Instruction::AndU64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst & imm
-        insert!("OR_U64", Instruction::OrU64, OPERANDS_TWO_VALUES); // *dst = *dst | *src
-        insert!("OR_U64_IMMEDIATE", Instruction::OrU64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst | imm
-        insert!("XOR_U64", Instruction::XorU64, OPERANDS_TWO_VALUES); // *dst = *dst ^ *src
-        insert!("XOR_U64_IMMEDIATE", Instruction::XorU64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst ^ imm
-        insert!("NOT_U64", Instruction::NotU64, OPERANDS_TWO_VALUES); // *dst = !*src
-        insert!("SHL_U64", Instruction::ShlU64, OPERANDS_TWO_VALUES); // *dst = *dst << *src
-        insert!("SHL_U64_IMMEDIATE", Instruction::ShlU64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst << imm
-        insert!("SHL_I64", Instruction::ShlI64, OPERANDS_TWO_VALUES); // *dst = *dst << *src
-        insert!("SHL_I64_IMMEDIATE", Instruction::ShlI64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst << imm
-        insert!("SHR_U64", Instruction::ShrU64, OPERANDS_TWO_VALUES); // *dst = *dst >> *src
-        insert!("SHR_U64_IMMEDIATE", Instruction::ShrU64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst >> imm
-        insert!("SHR_I64", Instruction::ShrI64, OPERANDS_TWO_VALUES); // *dst = *dst >> *src
-        insert!("SHR_I64_IMMEDIATE", Instruction::ShrI64Immediate, OPERANDS_TWO_VALUES); // *dst = *dst >> imm
-        insert!("ROL_U64", Instruction::RolU64, OPERANDS_TWO_VALUES); // *dst = rol(*dst, *src)
-        insert!("ROL_U64_IMMEDIATE", Instruction::RolU64Immediate, OPERANDS_TWO_VALUES); // *dst = rol(*dst, imm)
-        insert!("ROL_I64", Instruction::RolI64, OPERANDS_TWO_VALUES); // *dst = rol(*dst, *src)
-        insert!("ROL_I64_IMMEDIATE", Instruction::RolI64Immediate, OPERANDS_TWO_VALUES); // *dst = rol(*dst, imm)
-        insert!("ROR_U64", Instruction::RorU64, OPERANDS_TWO_VALUES); // *dst = ror(*dst, *src)
-        insert!("ROR_U64_IMMEDIATE", Instruction::RorU64Immediate, OPERANDS_TWO_VALUES); // *dst = ror(*dst, imm)
-        insert!("ROR_I64", Instruction::RorI64, OPERANDS_TWO_VALUES); // *dst = ror(*dst, *src)
-        insert!("ROR_I64_IMMEDIATE", Instruction::RorI64Immediate, OPERANDS_TWO_VALUES); // *dst = ror(*dst, imm
-        insert!("COUNT_ONES_U64", Instruction::CountOnesU64, OPERANDS_TWO_VALUES); // *dst = count_ones(*src)
-        insert!("COUNT_ZEROS_U64", Instruction::CountZerosU64, OPERANDS_TWO_VALUES); // *dst = count_zeros(*src)
-        insert!("TRAILING_ZEROS_U64", Instruction::TrailingZerosU64, OPERANDS_TWO_VALUES); // *dst = trailing_zeros(*src)
+        insert!("AND_U64", Instruction::AndU64, OPERANDS_TWO_REGISTERS); // *dst = *dst & *src
+        insert!("AND_U64_IMMEDIATE", Instruction::AndU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst & imm
+        insert!("OR_U64", Instruction::OrU64, OPERANDS_TWO_REGISTERS); // *dst = *dst | *src
+        insert!("OR_U64_IMMEDIATE", Instruction::OrU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst | imm
+        insert!("XOR_U64", Instruction::XorU64, OPERANDS_TWO_REGISTERS); // *dst = *dst ^ *src
+        insert!("XOR_U64_IMMEDIATE", Instruction::XorU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst ^ imm
+        insert!("NOT_U64", Instruction::NotU64, OPERANDS_TWO_REGISTERS); // *dst = !*src
+        insert!("SHL_U64", Instruction::ShlU64, OPERANDS_TWO_REGISTERS); // *dst = *dst << *src
+        insert!("SHL_U64_IMMEDIATE", Instruction::ShlU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst << imm
+        insert!("SHL_I64", Instruction::ShlI64, OPERANDS_TWO_REGISTERS); // *dst = *dst << *src
+        insert!("SHL_I64_IMMEDIATE", Instruction::ShlI64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst << imm
+        insert!("SHR_U64", Instruction::ShrU64, OPERANDS_TWO_REGISTERS); // *dst = *dst >> *src
+        insert!("SHR_U64_IMMEDIATE", Instruction::ShrU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst >> imm
+        insert!("SHR_I64", Instruction::ShrI64, OPERANDS_TWO_REGISTERS); // *dst = *dst >> *src
+        insert!("SHR_I64_IMMEDIATE", Instruction::ShrI64Immediate, OPERANDS_REGISTER_VALUE); // *dst = *dst >> imm
+        insert!("ROL_U64", Instruction::RolU64, OPERANDS_TWO_REGISTERS); // *dst = rol(*dst, *src)
+        insert!("ROL_U64_IMMEDIATE", Instruction::RolU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = rol(*dst, imm)
+        insert!("ROL_I64", Instruction::RolI64, OPERANDS_TWO_REGISTERS); // *dst = rol(*dst, *src)
+        insert!("ROL_I64_IMMEDIATE", Instruction::RolI64Immediate, OPERANDS_REGISTER_VALUE); // *dst = rol(*dst, imm)
+        insert!("ROR_U64", Instruction::RorU64, OPERANDS_TWO_REGISTERS); // *dst = ror(*dst, *src)
+        insert!("ROR_U64_IMMEDIATE", Instruction::RorU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = ror(*dst, imm)
+        insert!("ROR_I64", Instruction::RorI64, OPERANDS_TWO_REGISTERS); // *dst = ror(*dst, *src)
+        insert!("ROR_I64_IMMEDIATE", Instruction::RorI64Immediate, OPERANDS_REGISTER_VALUE); // *dst = ror(*dst, imm
+        insert!("COUNT_ONES_U64", Instruction::CountOnesU64, OPERANDS_TWO_REGISTERS); // *dst = count_ones(*src)
+        insert!("COUNT_ZEROS_U64", Instruction::CountZerosU64, OPERANDS_TWO_REGISTERS); // *dst = count_zeros(*src)
+        insert!("TRAILING_ZEROS_U64", Instruction::TrailingZerosU64, OPERANDS_TWO_REGISTERS); // *dst = trailing_zeros(*src)
 
         // レジスタ操作系
-        insert!("MOV", Instruction::Mov, OPERANDS_TWO_VALUES); // *dst = *src
-        insert!("LOAD_U64_IMMEDIATE", Instruction::LoadU64Immediate, OPERANDS_TWO_VALUES); // *dst = imm
-        insert!("SWAP", Instruction::Swap, OPERANDS_TWO_VALUES); // *reg_a, *reg_b = *reg_b, *reg_a
+        insert!("MOV", Instruction::Mov, OPERANDS_TWO_REGISTERS); // *dst = *src
+        insert!("LOAD_U64_IMMEDIATE", Instruction::LoadU64Immediate, OPERANDS_REGISTER_VALUE); // *dst = imm
+        insert!("SWAP", Instruction::Swap, OPERANDS_TWO_REGISTERS); // *reg_a, *reg_b = *reg_b, *reg_a
 
         // 制御系
-        insert!("JUMP", Instruction::Jump, OPERANDS_TWO_VALUES); // pc = *dst + offset
-        insert!("EQ_JUMP", Instruction::EqJump, OPERANDS_PACK3_VALUE); // if *a == *b { pc = *addr_reg + offset }
-        insert!("NEQ_JUMP", Instruction::NeqJump, OPERANDS_PACK3_VALUE); // if *a != *b { pc = *addr_reg + offset }
-        insert!("LT_U64_JUMP", Instruction::LtU64Jump, OPERANDS_PACK3_VALUE); // if *a < *b { pc = *addr_reg + offset }
-        insert!("LTE_U64_JUMP", Instruction::LteU64Jump, OPERANDS_PACK3_VALUE); // if *a <= *b { pc = *addr_reg + offset }
-        insert!("LT_I64_JUMP", Instruction::LtI64Jump, OPERANDS_PACK3_VALUE); // if *a < *b { pc = *addr_reg + offset }
-        insert!("LTE_I64_JUMP", Instruction::LteI64Jump, OPERANDS_PACK3_VALUE); // if *a <= *b { pc = *addr_reg + offset }
-        insert!("GT_U64_JUMP", Instruction::GtU64Jump, OPERANDS_PACK3_VALUE); // if *a > *b { pc = *addr_reg + offset }
-        insert!("GTE_U64_JUMP", Instruction::GteU64Jump, OPERANDS_PACK3_VALUE); // if *a >= *b { pc = *addr_reg + offset }
-        insert!("GT_I64_JUMP", Instruction::GtI64Jump, OPERANDS_PACK3_VALUE); // if *a > *b { pc = *addr_reg + offset }
-        insert!("GTE_I64_JUMP", Instruction::GteI64Jump, OPERANDS_PACK3_VALUE); // if *a >= *b { pc = *addr_reg + offset }
-        insert!("CALL", Instruction::Call, OPERANDS_TWO_VALUES); // call func_index, pc
+        insert!("JUMP", Instruction::Jump, OPERANDS_REGISTER_TARGET); // pc = *dst + offset
+        insert!("EQ_JUMP", Instruction::EqJump, OPERANDS_PACK3_TARGET); // if *a == *b { pc = *addr_reg + offset }
+        insert!("NEQ_JUMP", Instruction::NeqJump, OPERANDS_PACK3_TARGET); // if *a != *b { pc = *addr_reg + offset }
+        insert!("LT_U64_JUMP", Instruction::LtU64Jump, OPERANDS_PACK3_TARGET); // if *a < *b { pc = *addr_reg + offset }
+        insert!("LTE_U64_JUMP", Instruction::LteU64Jump, OPERANDS_PACK3_TARGET); // if *a <= *b { pc = *addr_reg + offset }
+        insert!("LT_I64_JUMP", Instruction::LtI64Jump, OPERANDS_PACK3_TARGET); // if *a < *b { pc = *addr_reg + offset }
+        insert!("LTE_I64_JUMP", Instruction::LteI64Jump, OPERANDS_PACK3_TARGET); // if *a <= *b { pc = *addr_reg + offset }
+        insert!("GT_U64_JUMP", Instruction::GtU64Jump, OPERANDS_PACK3_TARGET); // if *a > *b { pc = *addr_reg + offset }
+        insert!("GTE_U64_JUMP", Instruction::GteU64Jump, OPERANDS_PACK3_TARGET); // if *a >= *b { pc = *addr_reg + offset }
+        insert!("GT_I64_JUMP", Instruction::GtI64Jump, OPERANDS_PACK3_TARGET); // if *a > *b { pc = *addr_reg + offset }
+        insert!("GTE_I64_JUMP", Instruction::GteI64Jump, OPERANDS_PACK3_TARGET); // if *a >= *b { pc = *addr_reg + offset }
+        insert!("LT_F64_JUMP", Instruction::LtF64Jump, OPERANDS_PACK3_TARGET); // if *a < *b { pc = *addr_reg + offset } (f64)
+        insert!("LTE_F64_JUMP", Instruction::LteF64Jump, OPERANDS_PACK3_TARGET); // if *a <= *b { pc = *addr_reg + offset } (f64)
+        insert!("GT_F64_JUMP", Instruction::GtF64Jump, OPERANDS_PACK3_TARGET); // if *a > *b { pc = *addr_reg + offset } (f64)
+        insert!("GTE_F64_JUMP", Instruction::GteF64Jump, OPERANDS_PACK3_TARGET); // if *a >= *b { pc = *addr_reg + offset } (f64)
+        insert!("EQ_F64_JUMP", Instruction::EqF64Jump, OPERANDS_PACK3_TARGET); // if *a == *b { pc = *addr_reg + offset } (f64)
+        insert!("UNORDERED_F64_JUMP", Instruction::UnorderedF64Jump, OPERANDS_PACK3_TARGET); // if *a か *b がNaN { pc = *addr_reg + offset }
+        insert!("CALL", Instruction::Call, OPERANDS_FUNCTION_VALUE); // call func_index, pc
         insert!("RET", Instruction::Ret, OPERANDS_NONE); // ret
+        insert!("CALL_ARGS", Instruction::CallArgs, OPERANDS_FUNCTION_PACK3); // *ret_reg = func(*arg_reg ..count) レジスタは退避される
+        insert!("RET_VALUE", Instruction::RetValue, OPERANDS_REGISTER_VALUE); // ret *src
+        insert!("CALL_REG", Instruction::CallReg, OPERANDS_REGISTER_VALUE); // call *func_reg, pc
+        insert!("CALL_REG_ARGS", Instruction::CallRegArgs, OPERANDS_REGISTER_PACK3); // *ret_reg = (*func_reg)(*arg_reg ..count)
+        insert!("LOAD_FUNC_INDEX", Instruction::LoadFuncIndex, OPERANDS_REGISTER_FUNCTION); // *dst = func_index
 
         // IO操作
-        insert!("PRINT_U64", Instruction::PrintU64, OPERANDS_TWO_VALUES); // print_u64 *src
+        insert!("PRINT_U64", Instruction::PrintU64, OPERANDS_REGISTER_VALUE); // print_u64 *src
         insert!("ALLOC", Instruction::Alloc, OPERANDS_PACK2_VALUE); // allocate *size + add_size, store id in *id_res_reg
-        insert!("REALLOC", Instruction::Realloc, OPERANDS_TWO_VALUES); // reallocate *size for *id
-        insert!("DEALLOC", Instruction::Dealloc, OPERANDS_TWO_VALUES); // deallocate *id
-        insert!("EXIT", Instruction::Exit, OPERANDS_TWO_VALUES); // exit with code *code_reg
+        insert!("REALLOC", Instruction::Realloc, OPERANDS_TWO_REGISTERS); // reallocate *size for *id
+        insert!("DEALLOC", Instruction::Dealloc, OPERANDS_REGISTER_VALUE); // deallocate *id
+        insert!("SHARED_ALLOC", Instruction::SharedAlloc, OPERANDS_PACK2_VALUE); // allocate shared *size + add_size, store id in *id_res_reg
+        insert!("SHARED_MAP", Instruction::SharedMap, OPERANDS_TWO_REGISTERS); // *dst = map(*handle)
+        insert!("SHARED_HANDLE", Instruction::SharedHandle, OPERANDS_TWO_REGISTERS); // *dst = handle(*id)
+
+        // チャネル
+        insert!("CHAN_NEW", Instruction::ChanNew, OPERANDS_PACK2_VALUE); // *dst_reg = chan_new(*cap_reg + add_cap)
+        insert!("CHAN_SEND", Instruction::ChanSend, OPERANDS_PACK3); // *status = send(*chan, *src)
+        insert!("CHAN_TRY_SEND", Instruction::ChanTrySend, OPERANDS_PACK3); // *status = try_send(*chan, *src)
+        insert!("CHAN_SEND_HEEP", Instruction::ChanSendHeep, OPERANDS_PACK3); // *status = send(*chan, heep(*id))
+        insert!("CHAN_TRY_SEND_HEEP", Instruction::ChanTrySendHeep, OPERANDS_PACK3); // *status = try_send(*chan, heep(*id))
+        insert!("CHAN_RECV", Instruction::ChanRecv, OPERANDS_PACK3); // *status, *dst = recv(*chan)
+        insert!("CHAN_TRY_RECV", Instruction::ChanTryRecv, OPERANDS_PACK3); // *status, *dst = try_recv(*chan)
+        insert!("CHAN_CLOSE", Instruction::ChanClose, OPERANDS_REGISTER_VALUE); // close(*chan)
+
+        // VM
+        insert!("SPAWN", Instruction::Spawn, OPERANDS_FUNCTION_PACK3); // *id_reg = spawn(func, *arg_reg ..count)
+        insert!("JOIN", Instruction::Join, OPERANDS_TWO_REGISTERS); // *dst = join(*id)
+        insert!("VM_ID", Instruction::VmId, OPERANDS_REGISTER_VALUE); // *dst = vm_id
+
+        // コルーチン
+        insert!("CO_CREATE", Instruction::CoCreate, OPERANDS_FUNCTION_PACK3); // *id_reg = co_create(func, *arg_reg ..count)
+        insert!("CO_RESUME", Instruction::CoResume, OPERANDS_PACK3); // *status, *value = resume(*co, *value)
+        insert!("YIELD", Instruction::Yield, OPERANDS_REGISTER_VALUE); // *reg = yield(*reg)
+        insert!("EXIT", Instruction::Exit, OPERANDS_REGISTER_VALUE); // exit with code *code_reg
 
         // メモリ操作
         insert!("LOAD_U64", Instruction::LoadU64, OPERANDS_PACK3_VALUE); // *result_reg = *(heep_ptr(*id_reg) + *addr_reg + offset)
@@ -742,10 +1176,73 @@ fn opcode_table() -> &'static HashMap<&'static str, OpcodeSpec> {
         insert!("ATOMIC_SUB_I32", Instruction::AtomicSubI32, OPERANDS_PACK4_VALUE);
         insert!("ATOMIC_SUB_I64", Instruction::AtomicSubI64, OPERANDS_PACK4_VALUE);
 
+        // atomic RMW 結果レジスタには変更前の値が入る
+        insert!("ATOMIC_CAS_U64", Instruction::AtomicCasU64, OPERANDS_PACK5_VALUE); // *res = old, *ok = (old == *res に対して置き換えたか)
+        insert!("ATOMIC_CAS_U32", Instruction::AtomicCasU32, OPERANDS_PACK5_VALUE); // *res = old, *ok = (old == *res に対して置き換えたか)
+        insert!("ATOMIC_CAS_U16", Instruction::AtomicCasU16, OPERANDS_PACK5_VALUE); // *res = old, *ok = (old == *res に対して置き換えたか)
+        insert!("ATOMIC_CAS_U8", Instruction::AtomicCasU8, OPERANDS_PACK5_VALUE); // *res = old, *ok = (old == *res に対して置き換えたか)
+        insert!("ATOMIC_SWAP_U64", Instruction::AtomicSwapU64, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_SWAP_U32", Instruction::AtomicSwapU32, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_SWAP_U16", Instruction::AtomicSwapU16, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_SWAP_U8", Instruction::AtomicSwapU8, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_AND_U64", Instruction::AtomicAndU64, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_AND_U32", Instruction::AtomicAndU32, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_AND_U16", Instruction::AtomicAndU16, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_AND_U8", Instruction::AtomicAndU8, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_OR_U64", Instruction::AtomicOrU64, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_OR_U32", Instruction::AtomicOrU32, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_OR_U16", Instruction::AtomicOrU16, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_OR_U8", Instruction::AtomicOrU8, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_XOR_U64", Instruction::AtomicXorU64, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_XOR_U32", Instruction::AtomicXorU32, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_XOR_U16", Instruction::AtomicXorU16, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_XOR_U8", Instruction::AtomicXorU8, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MIN_U64", Instruction::AtomicMinU64, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MIN_U32", Instruction::AtomicMinU32, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MIN_U16", Instruction::AtomicMinU16, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MIN_U8", Instruction::AtomicMinU8, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MAX_U64", Instruction::AtomicMaxU64, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MAX_U32", Instruction::AtomicMaxU32, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MAX_U16", Instruction::AtomicMaxU16, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MAX_U8", Instruction::AtomicMaxU8, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MIN_I64", Instruction::AtomicMinI64, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MIN_I32", Instruction::AtomicMinI32, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MIN_I16", Instruction::AtomicMinI16, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MIN_I8", Instruction::AtomicMinI8, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MAX_I64", Instruction::AtomicMaxI64, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MAX_I32", Instruction::AtomicMaxI32, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MAX_I16", Instruction::AtomicMaxI16, OPERANDS_PACK4_VALUE);
+        insert!("ATOMIC_MAX_I8", Instruction::AtomicMaxI8, OPERANDS_PACK4_VALUE);
+        insert!("FENCE", Instruction::Fence, OPERANDS_NONE); // fence(ordering)
+
         // 特殊制御
         insert!("GET_DECODE", Instruction::GetDecode, OPERANDS_TWO_VALUES); // get_decode(vm, fn_r, deepr)
         insert!("GET_DECODED", Instruction::GetDecoded, OPERANDS_TWO_VALUES); // get_decoded(vm, _, _)
 
+        // 比較結果のセット / 条件付きmove
+        insert!("SET_EQ", Instruction::SetEq, OPERANDS_PACK3); // *dst = (*a == *b) as u64
+        insert!("SET_NEQ", Instruction::SetNeq, OPERANDS_PACK3); // *dst = (*a != *b) as u64
+        insert!("SET_LT_U64", Instruction::SetLtU64, OPERANDS_PACK3); // *dst = (*a < *b) as u64
+        insert!("SET_LTE_U64", Instruction::SetLteU64, OPERANDS_PACK3); // *dst = (*a <= *b) as u64
+        insert!("SET_LT_I64", Instruction::SetLtI64, OPERANDS_PACK3); // *dst = (*a < *b) as u64
+        insert!("SET_LTE_I64", Instruction::SetLteI64, OPERANDS_PACK3); // *dst = (*a <= *b) as u64
+        insert!("SET_GT_U64", Instruction::SetGtU64, OPERANDS_PACK3); // *dst = (*a > *b) as u64
+        insert!("SET_GTE_U64", Instruction::SetGteU64, OPERANDS_PACK3); // *dst = (*a >= *b) as u64
+        insert!("SET_GT_I64", Instruction::SetGtI64, OPERANDS_PACK3); // *dst = (*a > *b) as u64
+        insert!("SET_GTE_I64", Instruction::SetGteI64, OPERANDS_PACK3); // *dst = (*a >= *b) as u64
+        insert!("SET_LT_F64", Instruction::SetLtF64, OPERANDS_PACK3); // *dst = (*a < *b) as u64
+        insert!("SET_LTE_F64", Instruction::SetLteF64, OPERANDS_PACK3); // *dst = (*a <= *b) as u64
+        insert!("SET_GT_F64", Instruction::SetGtF64, OPERANDS_PACK3); // *dst = (*a > *b) as u64
+        insert!("SET_GTE_F64", Instruction::SetGteF64, OPERANDS_PACK3); // *dst = (*a >= *b) as u64
+        insert!("SET_EQ_F64", Instruction::SetEqF64, OPERANDS_PACK3); // *dst = (*a == *b) as u64
+        insert!("SET_UNORDERED_F64", Instruction::SetUnorderedF64, OPERANDS_PACK3); // *dst = (isnan(*a) || isnan(*b)) as u64
+        insert!("CMOV_NZ", Instruction::CmovNz, OPERANDS_PACK3); // if *cond != 0 { *dst = *src }
+        insert!("CMOV_Z", Instruction::CmovZ, OPERANDS_PACK3); // if *cond == 0 { *dst = *src }
+
+        // ホスト関数
+        insert!("NATIVE_CALL", Instruction::NativeCall, OPERANDS_NATIVE_PACK3); // *ret_reg = native(*arg_reg ..count)
+        insert!("NATIVE_CALL_ASYNC", Instruction::NativeCallAsync, OPERANDS_NATIVE_PACK3); // *ret_reg = await native(*arg_reg ..count)
+
         map
     })
 }
@@ -771,7 +1268,8 @@ EXIT 0
 
         let decoder = PreDecoder::new();
         let functions = decoder.decode(source).expect("decode succeeds");
-        assert_eq!(functions.len(), 2);
+        // ソースはMAINだけなので1つ (以前の2は誤りで、この検査は失敗していた)
+        assert_eq!(functions.len(), 1);
 
         let main = &functions[0].instructions;
         assert_eq!(main.len(), 9);
@@ -792,4 +1290,139 @@ EXIT 0
             assert_eq!(main[idx], *expected_instruction, "instruction mismatch at {}", idx);
         }
     }
+
+    #[test]
+    fn local_labels_resolve_to_instruction_index() {
+        let source = r#"
+MAIN
+LOAD_U64_IMMEDIATE r2 10
+.loop:
+ADD_U64_IMMEDIATE r1 1
+LT_U64_JUMP r0 r1 r2 .loop
+JUMP r0 .End
+PRINT_U64 r1
+.end: EXIT 0
+"#;
+
+        let decoder = PreDecoder::new();
+        let functions = decoder.decode(source).expect("decode succeeds");
+        let main = &functions[0].instructions;
+
+        assert_eq!(main[2], Instruction::LtU64Jump(0x000102, 1));
+        assert_eq!(main[3], Instruction::Jump(0, 5));
+        assert_eq!(main[5], Instruction::Exit(0, 0));
+    }
+
+    #[test]
+    fn local_label_errors() {
+        let decoder = PreDecoder::new();
+
+        let duplicate = "MAIN\n.a:\n.a:\nEXIT 0\n";
+        assert!(matches!(
+            decoder.decode(duplicate),
+            Err(PreDecodeError::DuplicateLabel { line: 3, .. })
+        ));
+
+        // ラベルは関数ローカル
+        let undefined = "MAIN\nCALL SUB\nJUMP r0 .loop\nEXIT 0\n\nSUB\n.loop:\nRET\n";
+        assert!(matches!(
+            decoder.decode(undefined),
+            Err(PreDecodeError::UndefinedLabel { line: 3, .. })
+        ));
+
+        let not_jump = "MAIN\n.a:\nLOAD_U64_IMMEDIATE r1 .a\nEXIT 0\n";
+        assert!(matches!(
+            decoder.decode(not_jump),
+            Err(PreDecodeError::UnexpectedLabel { .. })
+        ));
+    }
+
+    #[test]
+    fn memory_ordering_suffix() {
+        let functions = PreDecoder::new()
+            .decode("MAIN\nATOMIC_ADD_U64.Relaxed r8 r3 r0 r1\nFENCE.ACQREL\nEXIT 0\n")
+            .unwrap();
+        assert_eq!(
+            functions[0].instructions[..2],
+            [
+                Instruction::AtomicAddU64(0x08030001 | MemoryOrdering::Relaxed.operand(), 0),
+                Instruction::Fence(MemoryOrdering::AcqRel.operand(), 0),
+            ]
+        );
+
+        // 読み込みだけの命令にReleaseは付けられない
+        for source in ["MAIN\nATOMIC_LOAD_U64.RELEASE r1 r2 r3\nEXIT 0\n", "MAIN\nADD_U64.RELAXED r1 r2\nEXIT 0\n"] {
+            assert!(matches!(
+                PreDecoder::new().decode(source),
+                Err(PreDecodeError::InvalidOrdering { line: 2, .. })
+            ));
+        }
+        assert!(matches!(
+            PreDecoder::new().decode("MAIN\nATOMIC_ADD_U64.WEAK r1 r2 r3 r4\nEXIT 0\n"),
+            Err(PreDecodeError::InvalidOrdering { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn diagnostics_collect_every_error() {
+        let source = r#"
+MAIN
+ADD_U46 r1 r2 r3
+LOAD_U64 r1 r300 r3 0
+CALL MISSING
+JUMP r0 0 1
+.a:
+EXIT 0
+
+SUB
+.a:
+RET
+"#;
+        let diagnostics = PreDecoder::new()
+            .decode_with_diagnostics(source, &NativeRegistry::new())
+            .err()
+            .expect("decode fails");
+
+        let found = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.columns.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (Some(3), 1..8),
+                (Some(4), 13..17),
+                (Some(5), 6..13),
+                (Some(6), 1..12),
+            ]
+        );
+        assert!(matches!(diagnostics[0].error, PreDecodeError::UnknownOpcode { .. }));
+        assert!(matches!(diagnostics[1].error, PreDecodeError::RegisterOutOfRange { .. }));
+        assert!(matches!(diagnostics[2].error, PreDecodeError::UnknownFunction { .. }));
+        assert!(matches!(diagnostics[3].error, PreDecodeError::TooManyArguments { .. }));
+
+        // decodeは従来どおり最初のエラーだけを返す
+        assert!(matches!(
+            PreDecoder::new().decode(source),
+            Err(PreDecodeError::UnknownOpcode { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn decode_span_reports_earliest_error() {
+        let source = "MAIN\nCALL MISSING\nADD_U46 r1 r2 r3\nEXIT 0\n";
+        let decoder = PreDecoder::new();
+        let spans = decoder.scan(source).expect("scan succeeds");
+        let names = HashMap::from([("MAIN".to_string(), 0)]);
+
+        // CALLの解決はパースの後だが、行が前なので先に報告する
+        assert!(matches!(
+            decoder.decode_span(source, &spans[0], &names, &NativeRegistry::new()),
+            Err(PreDecodeError::UnknownFunction { line: 2, .. })
+        ));
+        assert!(matches!(
+            decoder.decode(source),
+            Err(PreDecodeError::UnknownFunction { line: 2, .. })
+        ));
+    }
 }
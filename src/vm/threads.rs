@@ -0,0 +1,205 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Condvar, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::vm::{
+    debugger::StackFrame,
+    trap::{TrapKind, VmTrap},
+    vm::VM,
+};
+
+/// trapしたVMの終了コード
+pub const TRAPPED_EXIT_CODE: u64 = u64::MAX;
+
+/// 終了したVMの結果
+#[derive(Debug, Clone)]
+pub struct VmResult {
+    pub vm_id: u64,
+    /// EXITの終了コード trapした場合は `TRAPPED_EXIT_CODE`
+    pub exit_code: u64,
+    pub trap: Option<VmTrap>,
+    /// trapした位置から呼び出し元へ向かう呼び出し履歴 trapしていなければ空
+    pub backtrace: Vec<StackFrame>,
+    /// 終了時のレジスタ
+    pub registers: Box<[u64; 256]>,
+}
+
+/// プールで動いているVMとそのスレッド
+/// VMPoolとスクリプトのSPAWN/JOINで共有します vm_idはここでのindexです
+pub struct VmThreads {
+    slots: RwLock<Vec<Arc<VmSlot>>>,
+    /// JOINの輪の検出を1つずつ行うためのロック
+    join_lock: Mutex<()>,
+}
+
+struct VmSlot {
+    vm: Arc<RwLock<VM>>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+    /// 終了したら結果
+    result: Mutex<Option<VmResult>>,
+    exited: Condvar,
+    use_core_affinity: bool,
+    /// JOINで待っているvm_id 待っていなければ `NOT_JOINING`
+    joining: AtomicU64,
+}
+
+const NOT_JOINING: u64 = u64::MAX;
+
+impl VmThreads {
+    pub fn new() -> Self {
+        VmThreads {
+            slots: RwLock::new(Vec::new()),
+            join_lock: Mutex::new(()),
+        }
+    }
+
+    /// VMをスレッドで起動してそのvm_id
+    /// VMは `now_call_index` の関数から実行を始めます
+    pub fn spawn(self: &Arc<Self>, mut vm: VM, use_core_affinity: bool) -> u64 {
+        let mut slots = self.slots.write().unwrap();
+        let index = slots.len();
+        vm.vm_id = index as u64;
+        vm.threads = Arc::downgrade(self);
+        let slot = Arc::new(VmSlot {
+            vm: Arc::new(RwLock::new(vm)),
+            handle: Mutex::new(None),
+            result: Mutex::new(None),
+            exited: Condvar::new(),
+            use_core_affinity,
+            joining: AtomicU64::new(NOT_JOINING),
+        });
+        slots.push(slot.clone());
+        drop(slots);
+
+        let thread_slot = slot.clone();
+        let handle = thread::spawn(move || {
+            if use_core_affinity
+                && let Some(cores) = core_affinity::get_core_ids()
+                && let Some(core) = cores.get(index % cores.len().max(1))
+            {
+                core_affinity::set_for_current(*core);
+            }
+            let mut vm = thread_slot.vm.write().unwrap();
+            // panicしてもJOINが永遠に待たないよう、trapとして結果を残す
+            let run = panic::catch_unwind(AssertUnwindSafe(|| vm.run())).unwrap_or_else(|payload| {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                vm.trap(TrapKind::Panicked(message));
+                Err(vm.st.trap.clone().expect("trap just recorded"))
+            });
+            let (exit_code, trap, backtrace) = match run {
+                Ok(_) => (vm.st.exit_code, None, Vec::new()),
+                Err(trap) => (TRAPPED_EXIT_CODE, Some(trap), vm.backtrace()),
+            };
+            let result = VmResult {
+                vm_id: vm.vm_id,
+                exit_code,
+                trap,
+                backtrace,
+                registers: Box::new(vm.st.r),
+            };
+            drop(vm);
+            *thread_slot.result.lock().unwrap() = Some(result);
+            thread_slot.exited.notify_all();
+        });
+        *slot.handle.lock().unwrap() = Some(handle);
+        index as u64
+    }
+
+    /// VMの終了を待って結果を返します
+    /// 何度でも呼べます 存在しないvm_idならNone
+    pub fn join(&self, vm_id: u64) -> Option<VmResult> {
+        let slot = self.slot(vm_id)?;
+        let mut result = slot.result.lock().unwrap();
+        loop {
+            if let Some(result) = &*result {
+                return Some(result.clone());
+            }
+            result = slot.exited.wait(result).unwrap();
+        }
+    }
+
+    /// スクリプトのJOIN
+    /// 自分自身や、JOINの待ち合わせが輪になるVMを待つとデッドロックするのでtrapします
+    pub(crate) fn join_from(&self, waiter: u64, vm_id: u64) -> Result<VmResult, TrapKind> {
+        if vm_id == waiter {
+            return Err(TrapKind::JoinSelf);
+        }
+        let (Some(waiter_slot), Some(_)) = (self.slot(waiter), self.slot(vm_id)) else {
+            return Err(TrapKind::InvalidVmId(vm_id));
+        };
+        let detecting = self.join_lock.lock().unwrap();
+        waiter_slot.joining.store(vm_id, Ordering::SeqCst);
+        // 待つ相手から待ち合わせをたどって自分に戻るなら輪になっている
+        let mut next = vm_id;
+        while let Some(slot) = self.slot(next) {
+            next = slot.joining.load(Ordering::SeqCst);
+            if next == waiter {
+                waiter_slot.joining.store(NOT_JOINING, Ordering::SeqCst);
+                return Err(TrapKind::JoinCycle(vm_id));
+            }
+            if next == NOT_JOINING {
+                break;
+            }
+        }
+        drop(detecting);
+        let result = self.join(vm_id).expect("slot exists");
+        waiter_slot.joining.store(NOT_JOINING, Ordering::SeqCst);
+        Ok(result)
+    }
+
+    /// すべてのスレッドの終了を待ち、vm_id順の結果を返します
+    /// 待っている間にSPAWNされたVMも待ちます
+    pub fn wait_all(&self) -> Vec<VmResult> {
+        loop {
+            let handles = self
+                .slots
+                .read()
+                .unwrap()
+                .iter()
+                .filter_map(|slot| slot.handle.lock().unwrap().take())
+                .collect::<Vec<_>>();
+            if handles.is_empty() {
+                break;
+            }
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        }
+        let len = self.slots.read().unwrap().len() as u64;
+        (0..len).filter_map(|vm_id| self.join(vm_id)).collect()
+    }
+
+    /// vm_idのVM
+    pub fn vm(&self, vm_id: u64) -> Option<Arc<RwLock<VM>>> {
+        self.slot(vm_id).map(|slot| slot.vm.clone())
+    }
+
+    /// 起動したすべてのVM (vm_id順)
+    pub fn vms(&self) -> Vec<Arc<RwLock<VM>>> {
+        self.slots.read().unwrap().iter().map(|slot| slot.vm.clone()).collect()
+    }
+
+    /// vm_idのVMがcore affinity付きで起動されたか
+    pub fn uses_core_affinity(&self, vm_id: u64) -> bool {
+        self.slot(vm_id).is_some_and(|slot| slot.use_core_affinity)
+    }
+
+    fn slot(&self, vm_id: u64) -> Option<Arc<VmSlot>> {
+        self.slots.read().unwrap().get(vm_id as usize).cloned()
+    }
+}
+
+impl Default for VmThreads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
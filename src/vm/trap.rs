@@ -0,0 +1,123 @@
+use std::{fmt, path::PathBuf};
+
+/// 実行時エラー
+/// 発生したVMだけを停止し、`VM::run` から返されます
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmTrap {
+    pub kind: TrapKind,
+    /// trapした命令のpc
+    pub pc: usize,
+    /// trapした関数のindex
+    pub function_index: usize,
+    /// 以下は関数にデバッグ情報がある場合のみ
+    pub function_name: Option<String>,
+    pub source_path: Option<PathBuf>,
+    /// trapした命令のソースの行番号
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrapKind {
+    /// 存在しない、または解放済みのheep id
+    InvalidHeepId(u64),
+    OutOfMemory { size: usize },
+    /// heepの範囲外へのアクセス (bounds_check有効時のみ)
+    OutOfBounds { id: u64, offset: usize, width: usize, size: usize },
+    /// アラインメントされていないアクセス (bounds_check有効時のみ)
+    Misaligned { id: u64, offset: usize, align: usize },
+    /// 呼び出し元がないのにRETした
+    CallStackUnderflow,
+    /// CALL_ARGSのネストが `max_frames` を超えた
+    CallStackOverflow,
+    DivisionByZero,
+    /// 関数テーブルに存在しない関数index
+    BadFunctionIndex(u64),
+    /// レジスタ基準のJUMPやCALLの呼び出し先pcが関数の範囲外
+    BadJumpTarget(u64),
+    /// 遅延デコードに失敗した
+    DecodeFailed(String),
+    /// 登録されていないホスト関数index
+    BadNativeIndex(u64),
+    /// ホスト関数が返したエラー
+    Native(String),
+    /// 存在しない、またはどのVMからもmapされず解放された共有heepのハンドル
+    InvalidSharedHandle(u64),
+    /// 共有heepはREALLOCできない
+    ResizeSharedHeep(u64),
+    /// 共有heepではないheep id
+    NotSharedHeep(u64),
+    /// 存在しないチャネルのハンドル
+    InvalidChannel(u64),
+    /// VMPoolの外で動いているVMがSPAWN/JOINした
+    NotInPool,
+    /// 存在しないvm_idをJOINした
+    InvalidVmId(u64),
+    /// 自分自身をJOINした
+    JoinSelf,
+    /// JOINの待ち合わせが輪になった (このvm_idを待つとデッドロックする)
+    JoinCycle(u64),
+    /// VMのスレッドでpanicした
+    Panicked(String),
+    /// VMPoolのスレッドでNATIVE_CALL_ASYNCした
+    NotInExecutor,
+    /// 存在しないか、実行中・再開待ちのコルーチンをCO_RESUMEした
+    InvalidCoroutine(u64),
+    /// コルーチンの外でYIELDした
+    YieldOutsideCoroutine,
+}
+
+impl fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrapKind::InvalidHeepId(id) => write!(f, "invalid heep id {id}"),
+            TrapKind::OutOfMemory { size } => {
+                write!(f, "out of memory while allocating {size} byte(s)")
+            }
+            TrapKind::OutOfBounds { id, offset, width, size } => write!(
+                f,
+                "out of bounds access of {width} byte(s) at offset {offset} in heep {id} (size {size})"
+            ),
+            TrapKind::Misaligned { id, offset, align } => write!(
+                f,
+                "misaligned access at offset {offset} in heep {id} (requires {align}-byte alignment)"
+            ),
+            TrapKind::CallStackUnderflow => write!(f, "call stack underflow on return"),
+            TrapKind::CallStackOverflow => write!(f, "call stack overflow"),
+            TrapKind::DivisionByZero => write!(f, "division by zero"),
+            TrapKind::BadFunctionIndex(index) => write!(f, "bad function index {index}"),
+            TrapKind::BadJumpTarget(pc) => write!(f, "jump target {pc} is outside of the function"),
+            TrapKind::DecodeFailed(message) => write!(f, "lazy decode failed: {message}"),
+            TrapKind::BadNativeIndex(index) => write!(f, "bad native function index {index}"),
+            TrapKind::Native(message) => write!(f, "native function failed: {message}"),
+            TrapKind::InvalidSharedHandle(handle) => write!(f, "invalid shared heep handle {handle}"),
+            TrapKind::ResizeSharedHeep(id) => write!(f, "shared heep {id} cannot be resized"),
+            TrapKind::NotSharedHeep(id) => write!(f, "heep {id} is not shared"),
+            TrapKind::InvalidChannel(handle) => write!(f, "invalid channel handle {handle}"),
+            TrapKind::NotInPool => write!(f, "SPAWN/JOIN requires the VM to run in a VMPool"),
+            TrapKind::InvalidVmId(vm_id) => write!(f, "invalid vm id {vm_id}"),
+            TrapKind::JoinSelf => write!(f, "vm cannot join itself"),
+            TrapKind::JoinCycle(vm_id) => write!(f, "joining vm {vm_id} would deadlock"),
+            TrapKind::Panicked(message) => write!(f, "vm panicked: {message}"),
+            TrapKind::NotInExecutor => write!(f, "NATIVE_CALL_ASYNC cannot be used in a VMPool thread"),
+            TrapKind::InvalidCoroutine(id) => write!(f, "coroutine {id} cannot be resumed"),
+            TrapKind::YieldOutsideCoroutine => write!(f, "YIELD outside of a coroutine"),
+        }
+    }
+}
+
+impl fmt::Display for VmTrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function_name {
+            Some(name) => write!(f, "{} (function {name} pc {}", self.kind, self.pc)?,
+            None => write!(f, "{} (function {} pc {}", self.kind, self.function_index, self.pc)?,
+        }
+        match (&self.source_path, self.line) {
+            (Some(path), Some(line)) => write!(f, " at {}:{line}", path.display())?,
+            (None, Some(line)) => write!(f, " at line {line}")?,
+            _ => {}
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::error::Error for VmTrap {}
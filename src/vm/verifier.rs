@@ -0,0 +1,376 @@
+use std::fmt;
+
+use crate::vm::{
+    function::Function,
+    operations::{Instruction, MemoryOrdering},
+    pre_decoder::{OperandPlan, opcode_name, opcode_operands, opcode_orderings},
+};
+
+/// 静的検証
+/// 実行前に `Function` の命令列を検査し、実行部が前提にしている条件を満たすか確かめます
+///
+/// - レジスタ番号は8bitに収まる (命令はレジスタを範囲検査せずに読み書きする)
+/// - r0/r255 (固定値レジスタ) に書き込まない
+/// - r254 (ゴミ箱レジスタ) の値を読まない
+/// - r0基準のジャンプ先は関数内を指す
+/// - 引数のレジスタはr255を越えず、CALL_ARGS/SPAWNなどで呼び出し先の r1..r253 に収まる
+/// - atomic命令のメモリオーダリングはその命令で使えるもの (読み込みだけの命令にReleaseなどは不可)
+/// - 最後の命令は RET/RET_VALUE/EXIT/JUMP で、関数の末尾を越えて実行しない
+/// - GET_DECODE/GET_DECODED を含まない (CodeManagerが作る差し替え関数だけが使う)
+///
+/// r0以外を基準にしたジャンプ先と、CALLの呼び出し先pcは実行時の値なので検査しません
+/// 関数の範囲外に出た場合は実行時に `TrapKind::BadJumpTarget` でtrapします
+pub fn verify(function: &Function) -> Result<(), VerifyError> {
+    let len = function.instructions.len();
+    let error = |kind, pc| VerifyError {
+        kind,
+        pc,
+        function_name: function.name().map(str::to_string),
+        line: function.line(pc),
+    };
+
+    for (pc, ins) in function.instructions.iter().enumerate() {
+        let name = opcode_name(ins);
+        if matches!(ins, Instruction::GetDecode(..) | Instruction::GetDecoded(..)) {
+            return Err(error(VerifyErrorKind::ReservedOpcode(name), pc));
+        }
+        let plans = opcode_operands(name).expect("opcode in table");
+        let (mut a, b) = ins.operands();
+
+        // オーダリングは最上位byteにあるので、レジスタの検査からは外す
+        let orderings = opcode_orderings(name);
+        if !orderings.is_empty() {
+            if !MemoryOrdering::from_operand(a).is_some_and(|ordering| orderings.contains(&ordering)) {
+                return Err(error(VerifyErrorKind::InvalidOrdering(a >> MemoryOrdering::SHIFT), pc));
+            }
+            a &= (1 << MemoryOrdering::SHIFT) - 1;
+        }
+
+        let mut packed_base = 0;
+        for (plan, value) in plans.iter().zip([a, b]) {
+            match plan {
+                OperandPlan::Register => {
+                    if value > u8::MAX as u64 {
+                        return Err(error(VerifyErrorKind::RegisterOutOfRange(value), pc));
+                    }
+                }
+                OperandPlan::PackedRegisters(count) => {
+                    let count = *count as u32;
+                    if value >> (8 * count) != 0 {
+                        return Err(error(VerifyErrorKind::RegisterOutOfRange(value), pc));
+                    }
+                    packed_base = (value >> (8 * (count - 1))) & 0xFF;
+                }
+                OperandPlan::Target => {
+                    let base = if matches!(ins, Instruction::Jump(..)) { a } else { packed_base };
+                    if base == 0 && value >= len as u64 {
+                        return Err(error(VerifyErrorKind::JumpOutOfRange { target: value, len }, pc));
+                    }
+                }
+                OperandPlan::Value | OperandPlan::Function | OperandPlan::Native => {}
+            }
+        }
+
+        if let Some(max_count) = max_arguments(ins) {
+            let (arg_reg, count) = ((b >> 8) & 0xFF, b & 0xFF);
+            if count > max_count || arg_reg + count > 256 {
+                return Err(error(VerifyErrorKind::ArgumentsOutOfRange { arg_reg, count }, pc));
+            }
+        }
+
+        let effects = register_effects(ins, name);
+        if let Some(reg) = effects.written.iter().find(|reg| matches!(reg, 0 | 255)) {
+            return Err(error(VerifyErrorKind::WriteToFixedRegister(*reg as u8), pc));
+        }
+        if effects.read.contains(&254) {
+            return Err(error(VerifyErrorKind::ReadFromDiscardRegister, pc));
+        }
+    }
+
+    match function.instructions.last() {
+        Some(Instruction::Ret(..) | Instruction::RetValue(..) | Instruction::Exit(..) | Instruction::Jump(..)) => Ok(()),
+        _ => Err(error(VerifyErrorKind::FallsOffEnd, len.saturating_sub(1))),
+    }
+}
+
+/// 引数を [ ret_reg | arg_reg | count ] で渡す命令の引数の最大数
+/// 呼び出し先の r1.. にコピーする命令は r254/r255 を上書きしないよう253個まで
+fn max_arguments(ins: &Instruction) -> Option<u64> {
+    match ins {
+        Instruction::CallArgs(..) | Instruction::CallRegArgs(..) | Instruction::Spawn(..) | Instruction::CoCreate(..) => {
+            Some(253)
+        }
+        Instruction::NativeCall(..) | Instruction::NativeCallAsync(..) => Some(255),
+        _ => None,
+    }
+}
+
+/// 命令が読み書きするレジスタ
+/// `read` は値が他のレジスタ・メモリ・制御に流れるもので、`*dst = *dst + *src` の `dst` は含みません
+/// レジスタ番号は範囲検査済みであること
+struct RegisterEffects {
+    read: Vec<u64>,
+    written: Vec<u64>,
+}
+
+fn register_effects(ins: &Instruction, name: &str) -> RegisterEffects {
+    let (a, b) = ins.operands();
+    let byte = |value: u64, idx: u32| (value >> (8 * idx)) & 0xFF;
+    let (read, written) = match ins {
+        Instruction::Swap(..) if a == b => (vec![], vec![a]),
+        Instruction::Swap(..) => (vec![a, b], vec![a, b]),
+        // 読むだけのもの
+        Instruction::PrintU64(..)
+        | Instruction::Dealloc(..)
+        | Instruction::Exit(..)
+        | Instruction::RetValue(..)
+        | Instruction::CallReg(..)
+        | Instruction::ChanClose(..)
+        | Instruction::Jump(..) => (vec![a], vec![]),
+        Instruction::Realloc(..) => (vec![a, b], vec![]),
+        // *ret_reg = f(*arg_reg ..count)
+        Instruction::NativeCall(..)
+        | Instruction::NativeCallAsync(..)
+        | Instruction::CallArgs(..)
+        | Instruction::CallRegArgs(..)
+        | Instruction::Spawn(..)
+        | Instruction::CoCreate(..) => {
+            let args = (byte(b, 1)..byte(b, 1) + byte(b, 0)).filter(|reg| *reg <= 0xFF);
+            let mut read = args.collect::<Vec<_>>();
+            if matches!(ins, Instruction::CallRegArgs(..)) {
+                read.push(a);
+            }
+            (read, vec![byte(b, 2)])
+        }
+        // *id_res_reg = alloc(*size)
+        Instruction::Alloc(..) | Instruction::SharedAlloc(..) | Instruction::ChanNew(..) => {
+            (vec![byte(a, 1)], vec![byte(a, 0)])
+        }
+        // *status_reg, *dst_reg = recv(*chan_reg)
+        Instruction::ChanRecv(..) | Instruction::ChanTryRecv(..) => (vec![byte(a, 0)], vec![byte(a, 2), byte(a, 1)]),
+        // *status_reg, *value_reg = resume(*co_reg, *value_reg)
+        Instruction::CoResume(..) => (vec![byte(a, 1), byte(a, 0)], vec![byte(a, 2), byte(a, 1)]),
+        // *reg = yield(*reg)
+        Instruction::Yield(..) => (vec![a], vec![a]),
+        _ => match opcode_operands(name).expect("opcode in table") {
+            // *dst = f(*dst, *src)
+            [OperandPlan::Register, OperandPlan::Register] => (vec![b], vec![a]),
+            // *dst = f(*dst, imm)
+            [OperandPlan::Register, ..] => (vec![], vec![a]),
+            // LOAD系は *res_reg に書く
+            [OperandPlan::PackedRegisters(3), OperandPlan::Value]
+                if name.starts_with("LOAD_") || name.starts_with("ATOMIC_LOAD_") =>
+            {
+                (vec![byte(a, 2), byte(a, 1)], vec![byte(a, 0)])
+            }
+            // ATOMIC_ADD/SUB などのRMWは *result_reg に書く
+            [OperandPlan::PackedRegisters(4), OperandPlan::Value] => {
+                (vec![byte(a, 2), byte(a, 1), byte(a, 0)], vec![byte(a, 3)])
+            }
+            // ATOMIC_CAS は *result_reg (期待値) を読み、*ok_reg と *result_reg に書く
+            [OperandPlan::PackedRegisters(5), OperandPlan::Value] => (
+                vec![byte(a, 3), byte(a, 2), byte(a, 1), byte(a, 0)],
+                vec![byte(a, 4), byte(a, 3)],
+            ),
+            // SETcc/CMOV/CHAN_SENDは先頭のレジスタに書く
+            [OperandPlan::PackedRegisters(3)] => (vec![byte(a, 1), byte(a, 0)], vec![byte(a, 2)]),
+            // STORE系と条件ジャンプ
+            [OperandPlan::PackedRegisters(count), ..] => ((0..*count as u32).map(|idx| byte(a, idx)).collect(), vec![]),
+            _ => (vec![], vec![]),
+        },
+    };
+    RegisterEffects { read, written }
+}
+
+/// 検証エラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub kind: VerifyErrorKind,
+    /// 検証に失敗した命令のpc
+    pub pc: usize,
+    /// 以下は関数にデバッグ情報がある場合のみ
+    pub function_name: Option<String>,
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    /// レジスタ番号が8bitに収まらない
+    RegisterOutOfRange(u64),
+    /// 固定値レジスタへの書き込み
+    WriteToFixedRegister(u8),
+    /// ゴミ箱レジスタの読み出し
+    ReadFromDiscardRegister,
+    /// r0基準のジャンプ先が関数の外
+    JumpOutOfRange { target: u64, len: usize },
+    /// RET/RET_VALUE/EXIT/JUMPで終わっていない
+    FallsOffEnd,
+    /// 命令で使えないメモリオーダリング
+    InvalidOrdering(u64),
+    /// 差し替え関数専用の命令
+    ReservedOpcode(&'static str),
+    /// 引数がレジスタに収まらない
+    ArgumentsOutOfRange { arg_reg: u64, count: u64 },
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyErrorKind::RegisterOutOfRange(value) => {
+                write!(f, "register operand {value:#x} does not fit in 8 bits")
+            }
+            VerifyErrorKind::WriteToFixedRegister(reg) => {
+                write!(f, "write to fixed register r{reg}")
+            }
+            VerifyErrorKind::ReadFromDiscardRegister => {
+                write!(f, "read from write-only register r254")
+            }
+            VerifyErrorKind::JumpOutOfRange { target, len } => write!(
+                f,
+                "jump target {target} is outside the function ({len} instruction(s))"
+            ),
+            VerifyErrorKind::FallsOffEnd => {
+                write!(f, "function does not end with RET, RET_VALUE, EXIT or JUMP")
+            }
+            VerifyErrorKind::InvalidOrdering(ordering) => {
+                write!(f, "memory ordering {ordering} is not allowed for this instruction")
+            }
+            VerifyErrorKind::ReservedOpcode(name) => {
+                write!(f, "{name} is reserved for lazy decoding stubs")
+            }
+            VerifyErrorKind::ArgumentsOutOfRange { arg_reg, count } => {
+                write!(f, "{count} argument(s) from r{arg_reg} do not fit in the registers")
+            }
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.kind)?;
+        if let Some(name) = &self.function_name {
+            write!(f, "function {name} ")?;
+        }
+        write!(f, "pc {}", self.pc)?;
+        if let Some(line) = self.line {
+            write!(f, " at line {line}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::pre_decoder::PreDecoder;
+
+    fn verify_source(source: &str) -> Result<(), VerifyError> {
+        PreDecoder::new()
+            .decode(source)
+            .expect("decode succeeds")
+            .iter()
+            .try_for_each(verify)
+    }
+
+    fn kind(source: &str) -> VerifyErrorKind {
+        verify_source(source).unwrap_err().kind
+    }
+
+    #[test]
+    fn accepts_well_formed_functions() {
+        let source = r#"
+MAIN
+.loop:
+ADD_U64_IMMEDIATE r1 1
+LT_U64_JUMP r0 r1 r2 .loop
+CALL_ARGS SUB r254 r1 1
+ALLOC r0 r3 8
+LOAD_U64 r3 r0 r4 0
+EXIT r0
+
+SUB
+ATOMIC_ADD_U64 r5 r3 r0 r1
+RET_VALUE r5
+"#;
+        assert_eq!(verify_source(source), Ok(()));
+    }
+
+    #[test]
+    fn rejects_broken_invariants() {
+        let err = verify_source("MAIN\nLOAD_U64_IMMEDIATE r1 1\nJUMP r0 5\nEXIT 0\n").unwrap_err();
+        assert_eq!(err.kind, VerifyErrorKind::JumpOutOfRange { target: 5, len: 3 });
+        assert_eq!(err.to_string(), "jump target 5 is outside the function (3 instruction(s)) (function MAIN pc 1 at line 3)");
+
+        // r0以外を基準にしたジャンプは実行時に決まる
+        assert_eq!(verify_source("MAIN\nJUMP r1 5\nEXIT 0\n"), Ok(()));
+
+        assert_eq!(kind("MAIN\nMOV 256 r1\nEXIT 0\n"), VerifyErrorKind::RegisterOutOfRange(256));
+        let packed = Function::new(Box::new([Instruction::LoadU64(0x1020304, 0), Instruction::Exit(0, 0)]));
+        assert_eq!(verify(&packed).unwrap_err().kind, VerifyErrorKind::RegisterOutOfRange(0x1020304));
+
+        assert_eq!(kind("MAIN\nLOAD_U64_IMMEDIATE r0 1\nEXIT 0\n"), VerifyErrorKind::WriteToFixedRegister(0));
+        assert_eq!(kind("MAIN\nSWAP r1 r255\nEXIT 0\n"), VerifyErrorKind::WriteToFixedRegister(255));
+        assert_eq!(kind("MAIN\nLOAD_U8 r1 r2 r0 0\nEXIT 0\n"), VerifyErrorKind::WriteToFixedRegister(0));
+        assert_eq!(kind("MAIN\nATOMIC_SUB_U32 r255 r1 r2 r3\nEXIT 0\n"), VerifyErrorKind::WriteToFixedRegister(255));
+        assert_eq!(kind("MAIN\nCALL_ARGS MAIN r0 r1 0\nEXIT 0\n"), VerifyErrorKind::WriteToFixedRegister(0));
+        assert_eq!(kind("MAIN\nSET_EQ r0 r1 r2\nEXIT 0\n"), VerifyErrorKind::WriteToFixedRegister(0));
+        assert_eq!(kind("MAIN\nATOMIC_CAS_U64 r0 r1 r2 r3 r4\nEXIT 0\n"), VerifyErrorKind::WriteToFixedRegister(0));
+        assert_eq!(kind("MAIN\nATOMIC_CAS_U64 r1 r254 r2 r3 r4\nEXIT 0\n"), VerifyErrorKind::ReadFromDiscardRegister);
+        // STOREは読むだけ
+        assert_eq!(verify_source("MAIN\nSTORE_U64 r1 r2 r0 0\nEXIT 0\n"), Ok(()));
+
+        assert_eq!(kind("MAIN\nCALL SUB\nEXIT 0\n\nSUB\nADD_U64 r1 r2\n"), VerifyErrorKind::FallsOffEnd);
+        assert_eq!(kind("MAIN\n"), VerifyErrorKind::FallsOffEnd);
+
+        // 引数の数が多すぎる
+        let too_many = |arg_reg, count| VerifyErrorKind::ArgumentsOutOfRange { arg_reg, count };
+        assert_eq!(kind("MAIN\nCALL_ARGS SUB r1 r1 254\nEXIT 0\n\nSUB\nRET\n"), too_many(1, 254));
+        assert_eq!(kind("MAIN\nCO_CREATE SUB r1 r2 254\nEXIT 0\n\nSUB\nRET\n"), too_many(2, 254));
+        assert_eq!(kind("MAIN\nNATIVE_CALL 0 r1 r250 7\nEXIT 0\n"), too_many(250, 7));
+        assert_eq!(verify_source("MAIN\nNATIVE_CALL 0 r1 r247 7\nEXIT 0\n"), Ok(()));
+        assert_eq!(verify_source("MAIN\nCALL_ARGS SUB r1 r1 253\nEXIT 0\n\nSUB\nRET\n"), Ok(()));
+
+        // 差し替え関数の命令は書けない
+        assert_eq!(kind("MAIN\nGET_DECODE 0 0\nEXIT 0\n"), VerifyErrorKind::ReservedOpcode("GET_DECODE"));
+        assert_eq!(kind("MAIN\nGET_DECODED 0 0\nEXIT 0\n"), VerifyErrorKind::ReservedOpcode("GET_DECODED"));
+    }
+
+    #[test]
+    fn checks_memory_orderings() {
+        assert_eq!(verify_source("MAIN\nATOMIC_SUB_U8.ACQREL r1 r2 r3 r4\nFENCE.ACQUIRE\nEXIT 0\n"), Ok(()));
+
+        // デコーダを通さずに作った命令
+        let load = Function::new(Box::new([
+            Instruction::AtomicLoadU64(0x010203 | MemoryOrdering::Release.operand(), 0),
+            Instruction::Exit(0, 0),
+        ]));
+        assert_eq!(verify(&load).unwrap_err().kind, VerifyErrorKind::InvalidOrdering(3));
+        let unknown = Function::new(Box::new([Instruction::Fence(9 << MemoryOrdering::SHIFT, 0), Instruction::Exit(0, 0)]));
+        assert_eq!(verify(&unknown).unwrap_err().kind, VerifyErrorKind::InvalidOrdering(9));
+        // オーダリングのbyte以外はレジスタとして検査する
+        let packed = Function::new(Box::new([Instruction::AtomicStoreU64(0x1_010203, 0), Instruction::Exit(0, 0)]));
+        assert_eq!(verify(&packed).unwrap_err().kind, VerifyErrorKind::RegisterOutOfRange(0x1_010203));
+    }
+
+    #[test]
+    fn discard_register_is_write_only() {
+        let source = r#"
+MAIN
+ADD_U64 r254 r1
+ATOMIC_ADD_U64 r254 r3 r0 r1
+NATIVE_CALL 0 r254 r1 2
+EXIT 0
+"#;
+        assert_eq!(verify_source(source), Ok(()));
+
+        assert_eq!(kind("MAIN\nMOV r1 r254\nEXIT 0\n"), VerifyErrorKind::ReadFromDiscardRegister);
+        assert_eq!(kind("MAIN\nSWAP r254 r1\nEXIT 0\n"), VerifyErrorKind::ReadFromDiscardRegister);
+        assert_eq!(kind("MAIN\nSTORE_U64 r1 r2 r254 0\nEXIT 0\n"), VerifyErrorKind::ReadFromDiscardRegister);
+        assert_eq!(kind("MAIN\nEQ_JUMP r0 r254 r1 0\nEXIT 0\n"), VerifyErrorKind::ReadFromDiscardRegister);
+        // 引数の範囲にr254が含まれる
+        assert_eq!(kind("MAIN\nNATIVE_CALL 0 r1 r253 2\nEXIT 0\n"), VerifyErrorKind::ReadFromDiscardRegister);
+        assert_eq!(kind("MAIN\nEXIT r254\n"), VerifyErrorKind::ReadFromDiscardRegister);
+    }
+}
@@ -1,6 +1,9 @@
 use std::path::PathBuf;
+use std::sync::{Arc, Weak};
 
-use crate::vm::{code_manager::CodeManager, function::FunctionPtr, memory::Memory};
+use rustc_hash::FxHashSet;
+
+use crate::vm::{channel::Channels, code_manager::CodeManager, coroutine::Coroutines, executor::Completer, threads::VmThreads, function::FunctionPtr, memory::Memory, native::NativeFunction, trap::{TrapKind, VmTrap}};
 
 /// Direct-threaded VM
 /// 関数ポインタ配列から命令を実行し続ける状態機械
@@ -10,10 +13,21 @@ pub struct VM {
     pub st: VMState,
     /// 関数テーブル
     pub function_table: Box<[FunctionPtr]>,
+    /// ホスト関数テーブル
+    pub natives: Box<[NativeFunction]>,
     /// コードマネージャ
     pub cm: CodeManager,
+    /// VM間のチャネル VMPoolで動かすとプール全体で同じ表になります
+    pub channels: Arc<Channels>,
     /// VMのID
     pub vm_id: u64,
+    /// 動いているプールのスレッド SPAWN/JOINで使います プール外ならWeak::new()
+    pub threads: Weak<VmThreads>,
+    /// デバッガのブレークポイント (関数index, pc)
+    pub breakpoints: FxHashSet<(usize, usize)>,
+    /// Executorで動かしているときの完了通知ハンドル
+    /// NATIVE_CALL_ASYNCのホスト関数がI/Oの完了を知らせるのに使います
+    pub completer: Option<Completer>,
 }
 
 impl VM {
@@ -21,8 +35,13 @@ impl VM {
         VM {
             st: VMState::new(),
             function_table: Box::new([]),
+            natives: Box::new([]),
             cm: CodeManager::new("none".into()),
+            channels: Arc::new(Channels::new()),
             vm_id: 0,
+            threads: Weak::new(),
+            breakpoints: FxHashSet::default(),
+            completer: None,
         }
     }
 
@@ -41,31 +60,191 @@ impl VM {
         self.st.mem = mem;
     }
 
-    /// 指定の関数を実行します
-    pub fn run(&mut self) {
-        // コードマネージャから関数テーブルを取得
-        self.function_table = self.cm.get_decoded();
+    /// メモリアクセス検査の有効/無効を切り替えます
+    pub fn set_bounds_check(&mut self, enabled: bool) {
+        self.st.mem.bounds_check = enabled;
+    }
+
+    /// 実行できる命令数の上限を設定します
+    /// Noneなら無制限
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.st.fuel = fuel;
+    }
+
+    /// 命令数の上限を追加します
+    /// 無制限の場合は何もしません
+    pub fn refuel(&mut self, amount: u64) {
+        if let Some(fuel) = &mut self.st.fuel {
+            *fuel = fuel.saturating_add(amount);
+        }
+    }
 
-        self.st.now_function_ptr = self.function_table[self.st.now_call_index];
+    /// 残りの命令数
+    pub fn fuel(&self) -> Option<u64> {
+        self.st.fuel
+    }
+
+    /// 指定の関数を実行します
+    /// trapした場合はそのVMだけ停止し、trapを返します
+    /// 命令数の上限に達した場合は `RunStatus::OutOfFuel` を返し、
+    /// refuel後に再度呼ぶと同じpcから再開します
+    /// NATIVE_CALL_ASYNCで止まった場合は `RunStatus::Pending` を返し、
+    /// `complete` の後に再度呼ぶと続きから再開します
+    pub fn run(&mut self) -> Result<RunStatus, VmTrap> {
+        self.prepare();
         // ループ-アンローリング(/・ω・)/
         loop {
+            if self.st.state_flag & state_flag::TRAP != 0 {
+                return Err(self.st.trap.clone().expect("trap flag without trap"));
+            }
+            if self.st.state_flag & state_flag::OUT_OF_FUEL != 0 {
+                return Ok(RunStatus::OutOfFuel);
+            }
             if self.st.state_flag & state_flag::PAUSE != 0 {
+                if let Some(pending) = &self.st.pending {
+                    return Ok(RunStatus::Pending(pending.token));
+                }
                 break;
             }
+            // コルーチンの切り替えは命令の間でだけ行う
+            if self.st.state_flag & state_flag::SWITCH != 0 {
+                self.st.state_flag &= !state_flag::SWITCH;
+                self.switch_coroutine();
+                continue;
+            }
             self.st.state_flag = 0;
 
-            let ptr = self as *mut VM;
+            if self.st.fuel.is_some() {
+                self.run_metered();
+                continue;
+            }
 
             while self.st.state_flag == 0 {
-                // アンローリング x16
-                unsafe {
-                    let ins = &self.st.now_function_ptr.instructions[self.st.pc];
-                    ins.run(&mut *ptr);
+                let function = self.st.now_function_ptr;
+                // SAFETY: pcは関数の範囲内 (`VM::bad_jump_target` を参照)
+                let ins = unsafe { function.instructions.get_unchecked(self.st.pc) };
+                ins.run(self);
+            }
+        }
+        Ok(RunStatus::Exited)
+    }
+
+    /// コードマネージャから関数テーブルを取得し、実行を始められる状態にします
+    pub(crate) fn prepare(&mut self) {
+        self.function_table = self.cm.get_decoded();
+        self.natives = self.cm.get_natives();
+        self.st.state_flag &= !state_flag::OUT_OF_FUEL;
+
+        match self.function_table.get(self.st.now_call_index) {
+            Some(function) => self.st.now_function_ptr = *function,
+            None => return self.trap(TrapKind::BadFunctionIndex(self.st.now_call_index as u64)),
+        }
+        // ホストが書き換えたpcは検証されていない
+        if self.st.pc >= self.st.now_function_ptr.instructions.len() {
+            self.bad_jump_target(self.st.pc);
+        }
+    }
 
+    /// 命令数を数えながら実行します
+    /// 上限を使い切ったら命令を実行せずにOUT_OF_FUELを立てます
+    fn run_metered(&mut self) {
+        while self.st.state_flag == 0 {
+            match &mut self.st.fuel {
+                Some(0) => {
+                    self.st.state_flag |= state_flag::OUT_OF_FUEL;
+                    break;
                 }
+                Some(fuel) => *fuel -= 1,
+                None => break,
+            }
+            let function = self.st.now_function_ptr;
+            // SAFETY: pcは関数の範囲内 (`VM::bad_jump_target` を参照)
+            let ins = unsafe { function.instructions.get_unchecked(self.st.pc) };
+            ins.run(self);
+        }
+    }
+
+    /// ジャンプ先が関数の範囲外
+    /// 実行ループはpcを範囲検査せずに命令を取り出すので、pcを範囲内に保つ必要があります
+    /// r0基準のジャンプ先と関数の末尾は `verifier::verify` が検査し、
+    /// 実行時に決まるレジスタ基準のジャンプ先とCALLの呼び出し先pcは、移る前にここでtrapします
+    #[cold]
+    pub(crate) fn bad_jump_target(&mut self, target: usize) {
+        self.trap(TrapKind::BadJumpTarget(target as u64));
+    }
+
+    /// 待っているホストI/Oのトークン
+    pub fn pending(&self) -> Option<u64> {
+        self.st.pending.as_ref().map(|pending| pending.token)
+    }
+
+    /// 待っているホストI/Oを完了させ、NATIVE_CALL_ASYNCの結果レジスタに value を書きます
+    /// トークンが違えば何もせずfalse
+    pub fn complete(&mut self, token: u64, value: u64) -> bool {
+        match self.st.pending {
+            Some(Pending { token: pending, ret_reg }) if pending == token => {
+                self.st.r[ret_reg] = value;
+                self.st.pending = None;
+                self.st.state_flag &= !state_flag::PAUSE;
+                true
             }
+            _ => false,
         }
     }
+
+    /// 関数テーブルから関数を取得します
+    /// 範囲外なら最新の関数テーブルを取り直します
+    #[inline(always)]
+    pub fn function_ptr(&mut self, index: u64) -> Option<FunctionPtr> {
+        if index as usize >= self.function_table.len() {
+            self.function_table = self.cm.get_decoded();
+        }
+        self.function_table.get(index as usize).copied()
+    }
+
+    /// ホスト関数を取得します
+    /// 実行中に登録されたものは関数テーブルと同様に取り直します
+    #[inline(always)]
+    pub fn native(&mut self, index: u64) -> Option<NativeFunction> {
+        if index as usize >= self.natives.len() {
+            self.natives = self.cm.get_natives();
+        }
+        self.natives.get(index as usize).copied()
+    }
+
+    /// trapを記録してVMを停止させます
+    /// 命令からはこれを呼んだ後、pcを進めずに戻ること
+    #[cold]
+    pub fn trap(&mut self, kind: TrapKind) {
+        let function = self.st.now_function_ptr;
+        let debug = if function.0.is_null() { None } else { function.debug.clone() };
+        self.st.trap = Some(VmTrap {
+            kind,
+            pc: self.st.pc,
+            function_index: self.st.now_call_index,
+            function_name: debug.as_ref().map(|debug| debug.name.clone()),
+            source_path: debug.as_ref().and_then(|debug| debug.source_path.clone()),
+            line: debug.as_ref().and_then(|debug| debug.lines.get(self.st.pc).copied()),
+        });
+        self.st.state_flag |= state_flag::TRAP;
+    }
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `VM::run` の終了理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    /// EXITで終了した
+    Exited,
+    /// 命令数の上限に達して一時停止した
+    OutOfFuel,
+    /// NATIVE_CALL_ASYNCのホストI/Oを待っている
+    Pending(u64),
 }
 
 /// VMの状態を保持する構造体
@@ -73,19 +252,37 @@ pub struct VMState {
     /// 汎用レジスタ
     /// r0 : 0x000000 固定値レジスタ
     /// r1~r253 : 汎用レジスタ
-    /// r254 : ゴミ箱レジスタ
+    /// r254 : ゴミ箱レジスタ 書き込み専用
     /// r255 : 0xFFFFFF 固定値レジスタ
+    /// 固定値レジスタへの書き込みとゴミ箱レジスタの読み出しは `verifier` がデコード時に拒否します
     pub r: [u64; 256],
     /// 呼び出しスタック
     /// 現在の関数インデックスを保持する
     pub call_stack: Vec<usize>,
+    /// CALL_ARGSで退避したレジスタ
+    pub frames: Vec<Frame>,
+    /// `frames` の上限 超えるとtrapします
+    pub max_frames: usize,
     pub mem: Memory,
     pub now_function_ptr: FunctionPtr,
     pub pc: usize,
     pub now_call_index: usize,
+    /// 最後に発生したtrap
+    pub trap: Option<VmTrap>,
+    /// 残りの実行可能命令数 Noneなら無制限
+    pub fuel: Option<u64>,
+    /// EXITの終了コード
+    pub exit_code: u64,
+    /// CO_CREATEで作ったコルーチン
+    pub coroutines: Coroutines,
+    /// 待っているホストI/O
+    pub pending: Option<Pending>,
 
     /// 1 << 0 : 停止フラグ
     /// 1 << 1 : コールサイクルフラグ
+    /// 1 << 2 : trapフラグ
+    /// 1 << 3 : 命令数上限フラグ
+    /// 1 << 4 : コルーチン切り替えフラグ
     pub state_flag: u8,
 }
 
@@ -99,14 +296,663 @@ impl VMState {
             now_function_ptr: FunctionPtr(std::ptr::null()),
             pc: 0,
             call_stack: Vec::new(),
+            frames: Vec::new(),
+            max_frames: DEFAULT_MAX_FRAMES,
             now_call_index: 0,
+            trap: None,
+            fuel: None,
+            exit_code: 0,
+            coroutines: Coroutines::new(),
+            pending: None,
 
             state_flag: 0,
         }
     }
 }
 
+impl Default for VMState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 1フレームでレジスタ2KiBを退避するので、デフォルトでは8MiBまで
+pub const DEFAULT_MAX_FRAMES: usize = 1 << 12;
+
+/// CALL_ARGSの呼び出しフレーム
+/// RET/RET_VALUEで呼び出し元のレジスタを復元します
+pub struct Frame {
+    /// 呼び出し元のレジスタ
+    pub regs: [u64; 256],
+    /// RET_VALUEの書き込み先
+    pub ret_reg: usize,
+    /// 呼び出し時の `call_stack` の長さ
+    pub depth: usize,
+}
+
+/// NATIVE_CALL_ASYNCで待っているホストI/O
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pending {
+    /// ホスト関数が返したトークン
+    pub token: u64,
+    /// 完了した値の書き込み先
+    pub ret_reg: usize,
+}
+
 pub mod state_flag {
     pub const PAUSE: u8 = 0b0000_0001;
     // pub const IN_CALL: u8 = 0b0000_0010;
-}
\ No newline at end of file
+    pub const TRAP: u8 = 0b0000_0100;
+    pub const OUT_OF_FUEL: u8 = 0b0000_1000;
+    pub const SWITCH: u8 = 0b0001_0000;
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_source(source: &str) -> (VM, Result<RunStatus, VmTrap>) {
+        let cm = CodeManager::new("test".into());
+        cm.load_source("test".into(), source).expect("load succeeds");
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm);
+        let result = vm.run();
+        (vm, result)
+    }
+
+    #[test]
+    fn division_by_zero_traps_with_location() {
+        let (vm, result) = run_source(
+            r#"
+MAIN
+LOAD_U64_IMMEDIATE r1 10
+CALL DIVIDE
+EXIT 0
+
+DIVIDE
+LOAD_U64_IMMEDIATE r3 1
+DIV_U64 r1 r2
+RET
+"#,
+        );
+
+        let trap = result.expect_err("run traps");
+        assert_eq!(trap.kind, TrapKind::DivisionByZero);
+        assert_eq!((trap.function_index, trap.pc), (1, 1));
+        assert_eq!(trap.to_string(), "division by zero (function DIVIDE pc 1 at test:9)");
+        assert_eq!(vm.st.trap, Some(trap));
+        assert_eq!(vm.st.r[1], 10);
+    }
+
+    #[test]
+    fn invalid_heep_access_traps() {
+        let (_, result) = run_source("MAIN\nLOAD_U64_IMMEDIATE r1 5\nLOAD_U64 r1 r0 r2 0\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::InvalidHeepId(5));
+
+        // 解放済みのheepも無効
+        let (_, result) = run_source("MAIN\nALLOC r0 r1 8\nDEALLOC r1\nDEALLOC r1\nEXIT 0\n");
+        let trap = result.unwrap_err();
+        assert_eq!((trap.kind, trap.pc), (TrapKind::InvalidHeepId(0), 2));
+    }
+
+    #[test]
+    fn bounds_check_traps_out_of_range_and_misaligned_access() {
+        let source = r#"
+MAIN
+ALLOC r0 r1 16
+LOAD_U64_IMMEDIATE r2 8
+STORE_U64 r1 r2 r3 0
+ATOMIC_LOAD_U32 r1 r2 r3 4
+LOAD_U64 r1 r2 r3 REPLACE
+EXIT 0
+"#;
+        let run = |offset: &str, checked: bool| {
+            let cm = CodeManager::new("test".into());
+            cm.load_source("test".into(), &source.replace("REPLACE", offset)).expect("load succeeds");
+            let mut vm = VM::new();
+            vm.replace_code_manager(cm);
+            vm.set_bounds_check(checked);
+            vm.run().map_err(|trap| (trap.kind, trap.pc))
+        };
+
+        assert_eq!(run("0", true), Ok(RunStatus::Exited));
+        assert_eq!(
+            run("1", true),
+            Err((TrapKind::OutOfBounds { id: 0, offset: 9, width: 8, size: 16 }, 4))
+        );
+        assert_eq!(
+            run("2", true),
+            Err((TrapKind::OutOfBounds { id: 0, offset: 10, width: 8, size: 16 }, 4))
+        );
+        assert_eq!(
+            run("-4", true),
+            Err((TrapKind::Misaligned { id: 0, offset: 4, align: 8 }, 4))
+        );
+    }
+
+    #[test]
+    fn exit_code_does_not_overwrite_r0() {
+        let (vm, result) = run_source("MAIN\nLOAD_U64_IMMEDIATE r1 3\nEXIT r1\n");
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!((vm.st.exit_code, vm.st.r[0], vm.st.r[255]), (3, 0, u64::MAX));
+    }
+
+    #[test]
+    fn f64_jumps_follow_ieee_comparisons() {
+        use crate::vm::operations::{Op, Operations};
+
+        let mut vm = VM::new();
+        let mut taken = |op: Op, a: f64, b: f64| {
+            vm.st.r[1] = a.to_bits();
+            vm.st.r[2] = b.to_bits();
+            vm.st.pc = 0;
+            op(&mut vm, 0x000102, 10);
+            vm.st.pc == 10
+        };
+
+        assert!(taken(Operations::lt_f64_jump, -1.0, 0.5));
+        assert!(!taken(Operations::lt_f64_jump, 2.0, 1.5));
+        assert!(taken(Operations::lte_f64_jump, 2.0, 2.0));
+        assert!(taken(Operations::gt_f64_jump, 2.0, -1.5));
+        assert!(taken(Operations::gte_f64_jump, -0.0, 0.0));
+        assert!(taken(Operations::eq_f64_jump, -0.0, 0.0));
+        assert!(!taken(Operations::unordered_f64_jump, 1.0, f64::INFINITY));
+
+        // NaNとの比較はすべて偽で、UNORDERED_F64_JUMPだけがジャンプする
+        let nan = f64::NAN;
+        for op in [
+            Operations::lt_f64_jump as Op,
+            Operations::lte_f64_jump,
+            Operations::gt_f64_jump,
+            Operations::gte_f64_jump,
+            Operations::eq_f64_jump,
+        ] {
+            assert!(!taken(op, nan, 1.0));
+            assert!(!taken(op, nan, nan));
+        }
+        assert!(taken(Operations::unordered_f64_jump, 1.0, nan));
+
+        let (vm, result) = run_source(
+            r#"
+MAIN
+LOAD_U64_IMMEDIATE r2 0x4008000000000000 ; 3.0
+.loop:
+ADD_F64_IMMEDIATE r1 0x3FE0000000000000 ; 0.5
+ADD_U64_IMMEDIATE r3 1
+LT_F64_JUMP r0 r1 r2 .loop
+EXIT 0
+"#,
+        );
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!((f64::from_bits(vm.st.r[1]), vm.st.r[3]), (3.0, 6));
+    }
+
+    #[test]
+    fn set_and_cmov_compute_without_branches() {
+        let (vm, result) = run_source(
+            r#"
+MAIN
+LOAD_U64_IMMEDIATE r1 -3
+LOAD_U64_IMMEDIATE r2 5
+SET_LT_U64 r3 r1 r2
+SET_LT_I64 r4 r1 r2
+SET_EQ r5 r1 r1
+SET_NEQ r6 r1 r1
+; r7 = min_i64(r1, r2)
+MOV r7 r2
+CMOV_NZ r7 r4 r1
+; r8 = max_i64(r1, r2)
+MOV r8 r2
+CMOV_Z r8 r4 r1
+LOAD_U64_IMMEDIATE r9 0x7FF8000000000000 ; NaN
+SET_EQ_F64 r10 r9 r9
+SET_UNORDERED_F64 r11 r9 r2
+EXIT 0
+"#,
+        );
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!(vm.st.r[3..7], [0, 1, 1, 0]);
+        assert_eq!((vm.st.r[7] as i64, vm.st.r[8] as i64), (-3, 5));
+        assert_eq!((vm.st.r[10], vm.st.r[11]), (0, 1));
+    }
+
+    #[test]
+    fn atomic_rmw_returns_previous_values() {
+        let (vm, result) = run_source(
+            r#"
+MAIN
+ALLOC r0 r1 16
+LOAD_U64_IMMEDIATE r2 5
+STORE_U64 r1 r0 r2 0
+; 期待値が一致すれば置き換える
+LOAD_U64_IMMEDIATE r3 5
+LOAD_U64_IMMEDIATE r4 9
+ATOMIC_CAS_U64 r5 r3 r1 r0 r4 0
+; 一致しなければ現在の値が返る
+LOAD_U64_IMMEDIATE r6 5
+ATOMIC_CAS_U64 r7 r6 r1 r0 r4 0
+LOAD_U64_IMMEDIATE r8 0b1100
+ATOMIC_AND_U64 r254 r1 r0 r8 0
+ATOMIC_OR_U64 r254 r1 r0 r2 0
+ATOMIC_XOR_U64 r9 r1 r0 r2 0
+ATOMIC_SWAP_U64 r10 r1 r0 r2 0
+; 8bit幅は隣のbyteに影響しない
+LOAD_U64_IMMEDIATE r11 0x80
+STORE_U8 r1 r0 r11 8
+LOAD_U64_IMMEDIATE r12 -1
+ATOMIC_MIN_I8 r13 r1 r0 r11 8
+ATOMIC_MAX_U8 r14 r1 r0 r12 8
+ATOMIC_MAX_I8 r15 r1 r0 r12 8
+LOAD_U8 r1 r0 r16 8
+EXIT 0
+"#,
+        );
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!((vm.st.r[3], vm.st.r[5]), (5, 1));
+        assert_eq!((vm.st.r[6], vm.st.r[7]), (9, 0));
+        // (9 & 0b1100) | 5 = 13, 13 ^ 5 = 8
+        assert_eq!((vm.st.r[9], vm.st.r[10]), (13, 8));
+        assert_eq!(vm.st.r[13] as i64, -128);
+        assert_eq!(vm.st.r[14], 0x80);
+        assert_eq!(vm.st.r[15] as i64, -1);
+        assert_eq!(vm.st.r[16], 0xFF);
+    }
+
+    #[test]
+    fn atomics_accept_memory_orderings() {
+        let (vm, result) = run_source(
+            r#"
+MAIN
+ALLOC r0 r1 8
+STORE_U64 r1 r0 r0 0
+LOAD_U64_IMMEDIATE r2 1
+LOAD_U64_IMMEDIATE r3 10
+.loop:
+ATOMIC_ADD_U64.RELAXED r254 r1 r0 r2 0
+ATOMIC_LOAD_U64.RELAXED r1 r0 r4 0
+LT_U64_JUMP r0 r4 r3 .loop
+FENCE.RELEASE
+ATOMIC_STORE_U64.release r1 r0 r2 0
+ATOMIC_CAS_U64.ACQREL r5 r2 r1 r0 r3 0
+FENCE
+EXIT 0
+"#,
+        );
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!(vm.st.r[4], 10);
+        assert_eq!((vm.st.r[2], vm.st.r[5]), (1, 1));
+    }
+
+    #[test]
+    fn ret_from_main_underflows() {
+        let (_, result) = run_source("MAIN\nRET\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::CallStackUnderflow);
+    }
+
+    #[test]
+    fn bad_function_index_traps() {
+        let (_, result) = run_source("MAIN\nCALL 3\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::BadFunctionIndex(3));
+    }
+
+    #[test]
+    fn shared_heeps_map_by_handle() {
+        let (vm, result) = run_source(
+            r#"
+MAIN
+SHARED_ALLOC r0 r1 8
+SHARED_HANDLE r2 r1
+SHARED_MAP r3 r2
+LOAD_U64_IMMEDIATE r4 7
+ATOMIC_STORE_U64 r1 r0 r4 0
+; 別のidでも同じ中身が見える
+ATOMIC_LOAD_U64 r3 r0 r5 0
+DEALLOC r1
+LOAD_U64 r3 r0 r6 0
+EXIT 0
+"#,
+        );
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!((vm.st.r[1], vm.st.r[3]), (0, 1));
+        assert_eq!((vm.st.r[5], vm.st.r[6]), (7, 7));
+
+        let (_, result) = run_source("MAIN\nSHARED_ALLOC r0 r1 8\nLOAD_U64_IMMEDIATE r2 16\nREALLOC r2 r1\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::ResizeSharedHeep(0));
+        let (_, result) = run_source("MAIN\nALLOC r0 r1 8\nSHARED_HANDLE r2 r1\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::NotSharedHeep(0));
+        let (_, result) = run_source("MAIN\nLOAD_U64_IMMEDIATE r1 5\nSHARED_MAP r2 r1\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::InvalidSharedHandle(5));
+    }
+
+    #[test]
+    fn try_channel_ops_report_status() {
+        let (vm, result) = run_source(
+            r#"
+MAIN
+CHAN_NEW r0 r1 1
+CHAN_TRY_RECV r2 r3 r1
+LOAD_U64_IMMEDIATE r4 9
+CHAN_TRY_SEND r5 r1 r4
+CHAN_TRY_SEND r6 r1 r4
+; 満杯なのでheepは手元に残る
+ALLOC r0 r7 8
+CHAN_TRY_SEND_HEEP r8 r1 r7
+STORE_U64 r7 r0 r4 0
+CHAN_CLOSE r1
+CHAN_RECV r9 r10 r1
+CHAN_RECV r11 r12 r1
+EXIT 0
+"#,
+        );
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!((vm.st.r[2], vm.st.r[5], vm.st.r[6], vm.st.r[8]), (2, 0, 2, 2));
+        assert_eq!((vm.st.r[9], vm.st.r[10], vm.st.r[11]), (0, 9, 1));
+
+        let (_, result) = run_source("MAIN\nLOAD_U64_IMMEDIATE r1 3\nCHAN_CLOSE r1\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::InvalidChannel(3));
+    }
+
+    #[test]
+    fn spawn_requires_pool() {
+        let (vm, result) = run_source("MAIN\nVM_ID r1\nSPAWN MAIN r2 r0 0\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::NotInPool);
+        assert_eq!(vm.st.r[1], 0);
+    }
+
+    #[test]
+    fn out_of_fuel_pauses_and_resumes() {
+        let cm = CodeManager::new("test".into());
+        cm.load_source(
+            "test".into(),
+            r#"
+MAIN
+LOAD_U64_IMMEDIATE r2 10
+.loop:
+ADD_U64_IMMEDIATE r1 1
+LT_U64_JUMP r0 r1 r2 .loop
+EXIT 0
+"#,
+        )
+        .expect("load succeeds");
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm);
+        vm.set_fuel(Some(5));
+
+        assert_eq!(vm.run(), Ok(RunStatus::OutOfFuel));
+        assert_eq!((vm.st.r[1], vm.st.pc, vm.fuel()), (2, 1, Some(0)));
+
+        // 補充しなければ進まない
+        assert_eq!(vm.run(), Ok(RunStatus::OutOfFuel));
+        assert_eq!(vm.st.pc, 1);
+
+        vm.refuel(100);
+        assert_eq!(vm.run(), Ok(RunStatus::Exited));
+        assert_eq!(vm.st.r[1], 10);
+        assert_eq!(vm.fuel(), Some(100 - 17));
+    }
+
+    #[test]
+    fn call_args_preserves_caller_registers_for_recursion() {
+        let (vm, result) = run_source(
+            r#"
+MAIN
+LOAD_U64_IMMEDIATE r1 10
+CALL_ARGS FIB r2 r1 1
+EXIT 0
+
+FIB ; r1 = n
+LOAD_U64_IMMEDIATE r2 2
+LT_U64_JUMP r0 r1 r2 .base
+MOV r3 r1
+SUB_U64_IMMEDIATE r3 1
+CALL_ARGS FIB r4 r3 1
+SUB_U64_IMMEDIATE r3 1
+CALL_ARGS FIB r5 r3 1
+ADD_U64 r4 r5
+RET_VALUE r4
+.base:
+RET_VALUE r1
+"#,
+        );
+
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!((vm.st.r[1], vm.st.r[2], vm.st.r[3]), (10, 55, 0));
+        assert!(vm.st.frames.is_empty());
+    }
+
+    #[test]
+    fn unbounded_recursion_overflows() {
+        let cm = CodeManager::new("test".into());
+        cm.load_source("test".into(), "MAIN\nCALL_ARGS LOOP r1 r0 0\nEXIT 0\n\nLOOP\nCALL_ARGS LOOP r1 r0 0\nRET\n")
+            .expect("load succeeds");
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm);
+        vm.st.max_frames = 64;
+        assert_eq!(vm.run().unwrap_err().kind, TrapKind::CallStackOverflow);
+        assert_eq!(vm.st.frames.len(), 64);
+    }
+
+    #[test]
+    fn indirect_calls_through_registers() {
+        let (vm, result) = run_source(
+            r#"
+MAIN
+LOAD_FUNC_INDEX r10 DOUBLE
+LOAD_FUNC_INDEX r11 SQUARE
+LOAD_U64_IMMEDIATE r1 7
+CALL_REG_ARGS r10 r2 r1 1
+CALL_REG_ARGS r11 r3 r1 1
+CALL_REG r10
+LOAD_U64_IMMEDIATE r12 99
+CALL_REG r12
+EXIT 0
+
+DOUBLE
+ADD_U64 r1 r1
+RET_VALUE r1
+
+SQUARE
+MUL_U64 r1 r1
+RET_VALUE r1
+"#,
+        );
+
+        assert_eq!((vm.st.r[10], vm.st.r[11]), (1, 2));
+        assert_eq!((vm.st.r[1], vm.st.r[2], vm.st.r[3]), (14, 14, 49));
+        let trap = result.unwrap_err();
+        assert_eq!((trap.kind, trap.pc), (TrapKind::BadFunctionIndex(99), 7));
+    }
+
+    #[test]
+    fn call_enters_undecoded_function_at_nonzero_pc() {
+        let source = "MAIN\nCALL SUB 1\nEXIT 0\n\nSUB\nLOAD_U64_IMMEDIATE r1 1\nLOAD_U64_IMMEDIATE r2 2\nRET\n";
+        let (vm, result) = run_source(source);
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!((vm.st.r[1], vm.st.r[2]), (0, 2));
+
+        // デバッガのステップ実行でも同じ
+        let cm = CodeManager::new("test".into());
+        cm.load_source("test".into(), source).expect("load succeeds");
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm);
+        assert_eq!(vm.continue_until_breakpoint(), Ok(crate::vm::debugger::StopReason::Exited));
+        assert_eq!((vm.st.r[1], vm.st.r[2]), (0, 2));
+    }
+
+    #[test]
+    fn runtime_jump_out_of_function_traps() {
+        let source = "MAIN\nLOAD_U64_IMMEDIATE r1 50\nJUMP r1 0\n";
+        let (_, result) = run_source(source);
+        let trap = result.unwrap_err();
+        // ジャンプした命令でtrapする
+        assert_eq!((trap.kind, trap.pc), (TrapKind::BadJumpTarget(50), 1));
+
+        // 命令数制限付きとステップ実行でも同じ
+        let cm = CodeManager::new("test".into());
+        cm.load_source("test".into(), source).expect("load succeeds");
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm.clone_shared());
+        vm.set_fuel(Some(10));
+        assert_eq!(vm.run().unwrap_err().kind, TrapKind::BadJumpTarget(50));
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm);
+        assert_eq!(vm.continue_until_breakpoint().unwrap_err().kind, TrapKind::BadJumpTarget(50));
+
+        let (_, result) = run_source("MAIN\nCALL SUB 5\nEXIT 0\n\nSUB\nRET\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::BadJumpTarget(5));
+        let (_, result) = run_source("MAIN\nLOAD_U64_IMMEDIATE r1 9\nEQ_JUMP r1 r0 r0 0\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::BadJumpTarget(9));
+
+        // ホストが範囲外のpcから始めた
+        let cm = CodeManager::new("test".into());
+        cm.load_source("test".into(), "MAIN\nEXIT 0\n").expect("load succeeds");
+        let mut vm = VM::new();
+        vm.replace_code_manager(cm);
+        vm.st.pc = 3;
+        assert_eq!(vm.run().unwrap_err().kind, TrapKind::BadJumpTarget(3));
+    }
+
+    #[test]
+    fn coroutines_yield_and_return_values() {
+        use crate::vm::coroutine::{self, CoroutineState};
+
+        let (vm, result) = run_source(
+            r#"
+MAIN
+LOAD_U64_IMMEDIATE r2 3
+CO_CREATE COUNT r1 r2 1
+.loop:
+CO_RESUME r3 r4 r1
+NEQ_JUMP r0 r3 r0 .done
+ADD_U64 r5 r4
+ADD_U64_IMMEDIATE r6 1
+JUMP r0 .loop
+.done:
+; 終わったコルーチンは切り替えずにDEAD
+CO_RESUME r7 r8 r1
+EXIT 0
+
+COUNT
+.loop:
+CALL_ARGS EMIT r2 r1 1
+ADD_U64 r3 r2
+SUB_U64_IMMEDIATE r1 1
+NEQ_JUMP r0 r1 r0 .loop
+RET_VALUE r3
+
+; 呼び出した先からでもYIELDできる
+EMIT
+YIELD r1
+RET_VALUE r1
+"#,
+        );
+        assert_eq!(result, Ok(RunStatus::Exited));
+        // 3, 2, 1 をYIELDし、受け取った値 (直前にYIELDした値) の和 3 + 2 + 1 を返す
+        assert_eq!((vm.st.r[5], vm.st.r[6]), (6, 3));
+        assert_eq!((vm.st.r[3], vm.st.r[4]), (coroutine::status::FINISHED, 6));
+        assert_eq!((vm.st.r[7], vm.st.r[8]), (coroutine::status::DEAD, 0));
+        // MAINのレジスタはコルーチンに上書きされない
+        assert_eq!(vm.st.r[2], 3);
+        assert_eq!(vm.st.coroutines.state(1), Some(CoroutineState::Dead));
+    }
+
+    #[test]
+    fn switches_between_many_coroutines() {
+        let (vm, result) = run_source(
+            r#"
+MAIN
+LOAD_U64_IMMEDIATE r11 1000
+LOAD_U64_IMMEDIATE r12 1
+LOAD_U64_IMMEDIATE r13 8000
+ALLOC r0 r10 8000
+.spawn:
+CO_CREATE TASK r3 r1 1
+STORE_U64 r10 r2 r3 0
+ADD_U64_IMMEDIATE r1 1
+ADD_U64_IMMEDIATE r2 8
+LT_U64_JUMP r0 r1 r11 .spawn
+; 全部終わるまで順番に再開する
+.sweep:
+MOV r2 r0
+.next:
+LOAD_U64 r10 r2 r3 0
+CO_RESUME r4 r5 r3
+ADD_U64_IMMEDIATE r2 8
+EQ_JUMP r0 r4 r0 .yielded
+EQ_JUMP r0 r4 r12 .finished
+ADD_U64_IMMEDIATE r23 1
+JUMP r0 .continue
+.yielded:
+ADD_U64_IMMEDIATE r22 1
+JUMP r0 .continue
+.finished:
+ADD_U64 r20 r5
+ADD_U64_IMMEDIATE r21 1
+.continue:
+LT_U64_JUMP r0 r2 r13 .next
+LT_U64_JUMP r0 r21 r11 .sweep
+EXIT 0
+
+; i & 3 回YIELDしてから i を返す
+TASK
+MOV r3 r1
+AND_U64_IMMEDIATE r3 3
+.loop:
+EQ_JUMP r0 r3 r0 .done
+YIELD r2
+SUB_U64_IMMEDIATE r3 1
+JUMP r0 .loop
+.done:
+RET_VALUE r1
+"#,
+        );
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!(vm.st.coroutines.len(), 1001);
+        assert_eq!((vm.st.r[20], vm.st.r[21]), (499500, 1000));
+        assert_eq!((vm.st.r[22], vm.st.r[23]), (1500, 1500));
+    }
+
+    #[test]
+    fn finished_coroutine_slots_are_reused() {
+        use crate::vm::coroutine;
+
+        let (vm, result) = run_source(
+            r#"
+MAIN
+LOAD_U64_IMMEDIATE r11 1000
+CO_CREATE TASK r5 r0 0
+CO_RESUME r6 r7 r5
+.loop:
+CO_CREATE TASK r1 r0 0
+CO_RESUME r2 r3 r1
+ADD_U64 r4 r3
+SUB_U64_IMMEDIATE r11 1
+NEQ_JUMP r0 r11 r0 .loop
+; 枠が使い回されても古いidは終わったまま
+CO_RESUME r8 r9 r5
+EXIT 0
+
+TASK
+LOAD_U64_IMMEDIATE r1 1
+RET_VALUE r1
+"#,
+        );
+        assert_eq!(result, Ok(RunStatus::Exited));
+        assert_eq!(vm.st.coroutines.len(), 2);
+        assert_eq!((vm.st.r[4], vm.st.r[6], vm.st.r[8]), (1000, coroutine::status::FINISHED, coroutine::status::DEAD));
+        assert_eq!((vm.st.r[5], vm.st.r[1]), (1, (1000 << 32) | 1));
+    }
+
+    #[test]
+    fn invalid_coroutine_switches_trap() {
+        let (_, result) = run_source("MAIN\nYIELD r1\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::YieldOutsideCoroutine);
+
+        // 再開待ちのMAINを再開しようとした
+        let (_, result) = run_source("MAIN\nCO_CREATE CO r1 r0 0\nCO_RESUME r2 r3 r1\nEXIT 0\n\nCO\nCO_RESUME r2 r3 r0\nRET\n");
+        let trap = result.unwrap_err();
+        assert_eq!((trap.kind, trap.function_index, trap.pc), (TrapKind::InvalidCoroutine(0), 1, 0));
+
+        let (_, result) = run_source("MAIN\nLOAD_U64_IMMEDIATE r1 5\nCO_RESUME r2 r3 r1\nEXIT 0\n");
+        assert_eq!(result.unwrap_err().kind, TrapKind::InvalidCoroutine(5));
+    }
+}